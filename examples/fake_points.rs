@@ -1,41 +1,127 @@
-use itertools::Itertools;
-use nalgebra::{Rotation3, Vector3, vector};
-use odometries::algorithm::lio::{self, LIO, StampedImu, measurement::StampedPoints};
+use nalgebra::{IsometryMatrix3, Rotation3, Vector3, vector};
+use odometries::{
+    algorithm::lio::{
+        self, LIO, StampedImu,
+        config::ImuPropagation,
+        measurement::{MeasureNoiseConfig, RobustKernel, StampedPoints},
+    },
+    frame::Framed,
+    trajectory::{
+        Trajectory, TrajectoryPoint,
+        evaluation::{self, Alignment, ErrorStats, EvaluationConfig},
+    },
+    voxel_map,
+};
 
 fn main() {
-    let mut lio = LIO::new_with_gravity_factor(lio::NoGravityConfig::default(), 0.0, 1.0);
+    // compare the IMU formulations by `IMU_PROPAGATION=control`
+    let imu_propagation = match option_env!("IMU_PROPAGATION") {
+        Some("control") => ImuPropagation::ControlInput(Default::default()),
+        _ => ImuPropagation::Observation,
+    };
+    let config = lio::NoGravityConfig {
+        imu_propagation,
+        // observe the zero velocity when the fake IMU is stationary by `ZUPT=1`
+        stationary: option_env!("ZUPT").map(|_| Default::default()),
+        // down-weight the outlier points by `ROBUST=huber|cauchy|geman-mcclure|tukey`
+        measure_noise: MeasureNoiseConfig {
+            lidar_kernel: match option_env!("ROBUST") {
+                Some("huber") => RobustKernel::Huber(1.345),
+                Some("cauchy") => RobustKernel::Cauchy(2.385),
+                Some("geman-mcclure") => RobustKernel::GemanMcClure(1.0),
+                Some("tukey") => RobustKernel::Tukey(4.685),
+                _ => RobustKernel::Gaussian,
+            },
+            ..Default::default()
+        },
+        // remove the voxels seen through by the scans by `FREE_SPACE=1`
+        voxel_map: voxel_map::Config {
+            free_space: option_env!("FREE_SPACE").map(|_| Default::default()),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    // calibrate the extrinsics online by `CALIBRATE=1`
+    if option_env!("CALIBRATE").is_some() {
+        let config = lio::extrinsic::NoGravityConfig {
+            lio: config,
+            ..Default::default()
+        };
+        let mut lio = LIO::new_calibrated_with_gravity_factor(config, 0.0, 1.0);
+        lio.extend(fake_points());
+        let extrinsics = lio.calibrated_extrinsics();
+        println!("calibrated extrinsics: {:?}", extrinsics.state.translation);
+        println!("variance: {:?}", extrinsics.cov.diagonal().as_slice());
+        return;
+    }
 
-    let mut rng = rand::rng();
+    let mut lio = LIO::new_with_gravity_factor(config, 0.0, 1.0)
+        .with_trajectory_recorder()
+        .with_smoother(None);
+    lio.extend(fake_points());
+
+    let pose = lio.get_pose();
+    println!("{:?}", pose.translation);
+    println!("stationary: {}", lio.is_stationary());
+
+    if let Some(ate) = lio.trajectory().and_then(stationary_ate) {
+        println!("ATE: {ate:?}");
+    }
+    if let Some(ate) = lio.smoothed_trajectory().as_ref().and_then(stationary_ate) {
+        println!("smoothed ATE: {ate:?}");
+    }
+
+    // export the trajectory in the TUM format for the evaluation scripts
+    if let (Ok(path), Some(trajectory)) = (std::env::var("TUM_OUTPUT"), lio.trajectory()) {
+        let file = std::fs::File::create(path).expect("failed to create the TUM output file");
+        trajectory
+            .write_tum(std::io::BufWriter::new(file))
+            .expect("failed to write the trajectory");
+    }
+}
+
+/// A fake ring of points around the stationary lidar, with the zero IMU measurements.
+fn fake_points() -> impl Iterator<Item = (StampedImu<f64>, StampedPoints<f64, Vec<Vector3<f64>>>)> {
     use rand::Rng;
+    let mut rng = rand::rng();
 
-    // generate fake points stream
-    let fake_points = (0..)
-        .map(|t| {
-            Rotation3::from_scaled_axis(
-                Vector3::z() * core::f64::consts::PI * (t % 96) as f64 / 48.0,
-            ) * vector![
-                1.7 + rng.random::<f64>() * 0.005,
-                0.0 + rng.random::<f64>() * 0.005,
-                0.3 + rng.random::<f64>() * 0.01,
-            ]
+    let steps = option_env!("STEPS")
+        .and_then(|s| {
+            s.parse()
+                .inspect_err(|e| eprintln!("Invalid STEPS: {e}"))
+                .ok()
         })
-        .chunks(96);
-    let fake_points = fake_points
-        .into_iter()
-        .enumerate()
-        .map(|(i, chunk)| StampedPoints::new((i * 96) as f64 * 0.00025, chunk))
-        .take(
-            option_env!("STEPS")
-                .and_then(|s| {
-                    s.parse()
-                        .inspect_err(|e| eprintln!("Invalid STEPS: {e}"))
-                        .ok()
-                })
-                .unwrap_or(1000),
-        )
-        .map(|points| (StampedImu::zeros(points.timestamp), points));
-    lio.extend(fake_points);
+        .unwrap_or(1000);
+    (0..steps).map(move |i| {
+        let points = (0..96)
+            .map(|t| {
+                Rotation3::from_scaled_axis(Vector3::z() * core::f64::consts::PI * t as f64 / 48.0)
+                    * vector![
+                        1.7 + rng.random::<f64>() * 0.005,
+                        0.0 + rng.random::<f64>() * 0.005,
+                        0.3 + rng.random::<f64>() * 0.01,
+                    ]
+            })
+            .collect();
+        let points = StampedPoints::new((i * 96) as f64 * 0.00025, points);
+        (StampedImu::zeros(points.timestamp), points)
+    })
+}
 
-    let pose = lio.get_pose();
-    println!("{:?}", pose.translation);
+/// The fake lidar is stationary at the origin.
+fn stationary_ate(trajectory: &Trajectory<f64>) -> Option<ErrorStats<f64>> {
+    let ground_truth = trajectory
+        .points()
+        .iter()
+        .map(|point| TrajectoryPoint {
+            pose: Framed::new(IsometryMatrix3::identity()),
+            ..point.clone()
+        })
+        .collect();
+    let config = EvaluationConfig {
+        alignment: Alignment::None,
+        ..Default::default()
+    };
+    let ate = evaluation::absolute_trajectory_error(trajectory, &ground_truth, &config)?;
+    Some(ate.translation)
 }
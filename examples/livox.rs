@@ -5,7 +5,9 @@ use livox2::{
     types::ethernet::{CartesianHighPoint, ImuData as LivoxImuData},
 };
 use odometries::algorithm::lio::{
-    self, ImuInit, ImuMeasured, StampedImu, measurement::StampedPoints,
+    self, ImuInit, ImuMeasured, StampedImu,
+    measurement::StampedPoints,
+    sync::{SyncConfig, Synchronizer},
 };
 use smol::stream::{self, StreamExt};
 
@@ -35,28 +37,31 @@ fn main() -> std::io::Result<()> {
         println!("init imu with 200 samples: \n{imu_init:?}");
 
         let mut lio = imu_init.new_lio(lio::Config::default().with_mid360_extrinsics());
+        let mut synchronizer = Synchronizer::new(SyncConfig::default());
 
         point_clouds
             .into_stream(|point_cloud| {
                 let CoordinateDataRef::CartesianHigh(points) = point_cloud.data else {
                     return;
                 };
-                let point_start_timestamp = point_cloud.header.timestamp_sec();
                 let point_end_timestamp = point_cloud.header.end_timestamp_sec();
 
-                let imu_measurments = stream::block_on(
-                    imu_stream
-                        .drain()
-                        .skip_while(|measurment| measurment.timestamp < point_start_timestamp)
-                        .take_while(|measurment| measurment.timestamp < point_end_timestamp),
-                );
-                lio.update_points_with_imus(
-                    StampedPoints::new(
-                        point_end_timestamp,
-                        points.iter().map(livox_point_to_mesurement),
-                    ),
-                    imu_measurments,
-                );
+                stream::block_on(imu_stream.drain()).for_each(|imu| {
+                    if let Err(late) = synchronizer.push_imu(imu) {
+                        eprintln!("drop the IMU: {late}");
+                    }
+                });
+                let points = points
+                    .iter()
+                    .map(livox_point_to_mesurement)
+                    .collect::<Vec<_>>();
+                if let Err(late) =
+                    synchronizer.push_points(StampedPoints::new(point_end_timestamp, points))
+                {
+                    eprintln!("drop the points: {late}");
+                }
+
+                lio.update_synchronized(&mut synchronizer);
                 let pose = lio.get_pose();
                 println!("{pose:?}");
             })
@@ -1,4 +1,4 @@
-mod fast_lio;
+pub mod fast_lio;
 mod fast_livo;
 pub mod kilo;
 pub mod lio;
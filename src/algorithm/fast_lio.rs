@@ -1,3 +1,211 @@
 //! Fast LIO2 system
 //!
-//! TODO
+//! A tightly‑coupled lidar inertial odometry with an incremental k-d tree map,
+//! which shares the state and the IMU observation with [`LIO`](super::lio::LIO).
+
+pub mod config;
+mod measurement;
+pub mod plane;
+
+use std::ops::Deref;
+
+use nalgebra::{ComplexField, RealField};
+
+use crate::{
+    algorithm::lio::{
+        ImuInit, MeasureNoiseConfig,
+        downsample::{Downsampler, ScanDownsampler},
+        state::State,
+    },
+    eskf::{
+        Eskf, IteratedConfig,
+        state::common::{GravityState, LinearAccState},
+    },
+    frame::{IsometryFramed, WorldPoint, frames},
+    kd_tree::KdTree,
+    utils::ToRadians,
+};
+
+pub use config::{Config, NoGravityConfig};
+use measurement::PointsProcessBuffer;
+use plane::PlaneConfig;
+
+pub struct FastLio<T>
+where
+    T: ComplexField,
+{
+    eskf: Eskf<State<T>>,
+    map: KdTree<T>,
+    downsampler: ScanDownsampler<T>,
+    points_process_buffer: PointsProcessBuffer<T>,
+    /// The position where the local map was last slided.
+    last_slide_position: Option<WorldPoint<T>>,
+    // configs
+    measure_noise: MeasureNoiseConfig<T>,
+    extrinsics: IsometryFramed<T, fn(frames::Body) -> frames::Imu>,
+    gravity_factor: T,
+    iterated_update: IteratedConfig<T>,
+    plane: PlaneConfig<T>,
+    map_size: T,
+    sliding_thresh: T,
+}
+
+impl<T> ImuInit<T>
+where
+    T: RealField + ToRadians,
+{
+    pub fn new_fast_lio(self, config: Config<T>) -> FastLio<T> {
+        FastLio::new(config, self)
+    }
+}
+
+impl<T> FastLio<T>
+where
+    T: RealField + ToRadians,
+{
+    pub fn new(config: Config<T>, imu_init: ImuInit<T>) -> Self {
+        let (gravity, config) = config.take_gravity();
+        let gravity_factor = gravity / imu_init.linear_acc_norm.clone();
+
+        let mut fast_lio =
+            Self::new_with_gravity_factor(config, imu_init.timestamp_init, gravity_factor.clone());
+
+        let gravity = imu_init.linear_acc_mean.deref() * gravity_factor;
+
+        let eskf = &mut fast_lio.eskf;
+        eskf.acc_with_bias.acc.linear = LinearAccState::new(gravity.clone());
+        eskf.gravity = GravityState::new(-gravity);
+        eskf.acc_with_bias.bias.angular = imu_init.angular_acc_bias;
+
+        fast_lio
+    }
+
+    /// Create a new Fast LIO instance with a given gravity factor.
+    ///
+    /// This does not need the `gravity` in [`Config<T>`], provide [`NoGravityConfig<T>`] instead.
+    pub fn new_with_gravity_factor(
+        config: NoGravityConfig<T>,
+        timestamp_init: T,
+        gravity_factor: T,
+    ) -> Self {
+        let eskf = Eskf::new(config.process_cov.into(), timestamp_init);
+
+        Self {
+            eskf,
+            map: KdTree::new(config.kd_tree),
+            downsampler: Downsampler::new(config.downsample_resolution),
+            points_process_buffer: Vec::with_capacity(config.buffer_init_size),
+            last_slide_position: None,
+            measure_noise: config.measure_noise,
+            extrinsics: config.extrinsics,
+            gravity_factor,
+            iterated_update: config.iterated_update,
+            plane: config.plane,
+            map_size: config.map_size,
+            sliding_thresh: config.sliding_thresh,
+        }
+    }
+
+    #[inline]
+    pub fn get_pose(&self) -> &IsometryFramed<T, fn(frames::Imu) -> frames::World> {
+        &self.eskf.pose.0
+    }
+
+    #[inline]
+    pub fn map(&self) -> &KdTree<T> {
+        &self.map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Point3, Vector3};
+
+    use super::*;
+    use crate::{
+        algorithm::lio::{ImuMeasured, StampedImu, measurement::StampedPoints},
+        kd_tree::BoxRegion,
+    };
+
+    fn imu(i: usize) -> StampedImu<f64> {
+        StampedImu::new(
+            i as f64 * 0.01,
+            ImuMeasured::new(0.0, 0.0, 9.81, 0.0, 0.0, 0.0),
+        )
+    }
+
+    /// The points on the floor and two walls of a room around the origin,
+    /// which are apart so that the nearest neighbours of each point are on its own plane.
+    fn room() -> Vec<Vector3<f64>> {
+        let grid = |i: usize| (i as f64 - 5.0) * 0.4 + 0.05;
+        itertools::iproduct!(0..11, 0..11)
+            .flat_map(|(i, j)| {
+                [
+                    Vector3::new(grid(i), grid(j), -3.0),
+                    Vector3::new(3.0, grid(i), grid(j)),
+                    Vector3::new(grid(i), -3.0, grid(j)),
+                ]
+            })
+            .collect()
+    }
+
+    fn new_fast_lio(config: Config<f64>) -> FastLio<f64> {
+        let Some(imu_init) = (0..10).map(imu).collect::<Option<ImuInit<f64>>>() else {
+            panic!("failed to init the IMU");
+        };
+        imu_init.new_fast_lio(Config {
+            downsample_resolution: 0.2,
+            ..config
+        })
+    }
+
+    #[test]
+    fn test_stationary_scans_converge() {
+        let mut fast_lio = new_fast_lio(Config::default());
+        fast_lio.map.extend(
+            room()
+                .into_iter()
+                .map(|point| WorldPoint::new(point.into())),
+        );
+        let update = |fast_lio: &mut FastLio<f64>, scan: usize| {
+            let imus = (scan * 10 + 1..=(scan + 1) * 10).map(imu);
+            fast_lio
+                .update_points_with_imus(StampedPoints::new((scan + 1) as f64 * 0.1, room()), imus);
+            let pose = fast_lio.get_pose();
+            (pose.translation.vector.amax(), pose.rotation.angle())
+        };
+
+        for scan in 1..=3 {
+            let (position, angle) = update(&mut fast_lio, scan);
+            assert!(position < 1e-6 && angle < 1e-6);
+        }
+
+        // the room scanned from the origin pulls the displaced pose back
+        fast_lio.eskf.pose.0.translation.vector += Vector3::new(0.05, -0.05, 0.03);
+        let (position, angle) = update(&mut fast_lio, 4);
+        assert!(position < 5e-3 && angle < 5e-3);
+    }
+
+    #[test]
+    fn test_slide_map() {
+        let mut fast_lio = new_fast_lio(Config {
+            map_size: 7.0,
+            sliding_thresh: 1.0,
+            ..Default::default()
+        });
+        fast_lio.update_points_with_imus(StampedPoints::new(0.1, room()), (10..=10).map(imu));
+        let len = fast_lio.map().len();
+        assert!(len > 0);
+
+        // not slided within the threshold
+        fast_lio.slide_map(WorldPoint::new(Point3::new(0.8, 0.0, 0.0)));
+        assert_eq!(fast_lio.map().len(), len);
+
+        // the points out of the local map around the new position are deleted
+        let position = Point3::new(2.0, 0.0, 0.0);
+        fast_lio.slide_map(WorldPoint::new(position));
+        let region = BoxRegion::new_cube(&position, 7.0);
+        assert!(fast_lio.map().len() < len);
+        assert!(fast_lio.map().iter().all(|point| region.contains(point)));
+    }
+}
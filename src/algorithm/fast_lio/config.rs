@@ -0,0 +1,127 @@
+use crate::{
+    algorithm::lio::{
+        MeasureNoiseConfig,
+        config::{StateProcessCovConfig, mid360_extrinsics},
+    },
+    eskf::IteratedConfig,
+    frame::{IsometryFramed, frames},
+    kd_tree,
+};
+
+use super::plane::PlaneConfig;
+
+use nalgebra::{RealField, Scalar};
+
+/// The configuration of the Fast LIO algorithm with no need to provide the gravity.
+pub type NoGravityConfig<T> = Config<T, NoGravity>;
+pub struct NoGravity;
+
+pub struct Config<T: Scalar, G = T> {
+    /// The process noise configuration of the state.
+    pub process_cov: StateProcessCovConfig<T>,
+
+    /// The measurement noise configuration of the IMU and the point-to-plane distance.
+    pub measure_noise: MeasureNoiseConfig<T>,
+
+    /// The extrinsics of the IMU to the body frame.
+    pub extrinsics: IsometryFramed<T, fn(frames::Body) -> frames::Imu>,
+
+    /// The gravity norm. Used to calculate the gravity factor (also known as gravity compensation)
+    ///
+    /// Note that this is optional, you can provide the gravity factor directly,
+    /// see also [`FastLio::new_with_gravity_factor`](super::FastLio::new_with_gravity_factor).
+    pub gravity: G,
+
+    /// The k-d tree map configuration.
+    pub kd_tree: kd_tree::Config<T>,
+
+    /// The plane fitting configuration of the nearest neighbours.
+    pub plane: PlaneConfig<T>,
+
+    /// The side length of the local map cube centered at the current position.
+    pub map_size: T,
+
+    /// delta position change threshold to slide the local map
+    pub sliding_thresh: T,
+
+    /// downsample leaf size
+    pub downsample_resolution: T,
+
+    /// The size of the processing buffer used to store the temporary transformed points.
+    pub buffer_init_size: usize,
+
+    /// The iterated update configuration of the lidar points observation.
+    pub iterated_update: IteratedConfig<T>,
+}
+
+impl<T: RealField> Default for Config<T> {
+    fn default() -> Self {
+        Self {
+            process_cov: Default::default(),
+            measure_noise: MeasureNoiseConfig {
+                lidar_point: nalgebra::convert(0.001),
+                ..Default::default()
+            },
+            extrinsics: Default::default(),
+            gravity: nalgebra::convert(9.81),
+            kd_tree: Default::default(),
+            plane: Default::default(),
+            map_size: nalgebra::convert(200.0),
+            sliding_thresh: nalgebra::convert(8.0),
+            downsample_resolution: nalgebra::convert(0.5),
+            buffer_init_size: 80,
+            iterated_update: Default::default(),
+        }
+    }
+}
+
+impl<T: RealField> Default for NoGravityConfig<T> {
+    #[inline]
+    fn default() -> Self {
+        Config::<T>::default().take_gravity().1
+    }
+}
+
+impl<T: Scalar, G> Config<T, G> {
+    pub fn take_gravity(self) -> (G, NoGravityConfig<T>) {
+        let Self {
+            process_cov,
+            measure_noise,
+            extrinsics,
+            gravity,
+            kd_tree,
+            plane,
+            map_size,
+            sliding_thresh,
+            downsample_resolution,
+            buffer_init_size,
+            iterated_update,
+        } = self;
+        (
+            gravity,
+            NoGravityConfig {
+                gravity: NoGravity,
+                process_cov,
+                measure_noise,
+                extrinsics,
+                kd_tree,
+                plane,
+                map_size,
+                sliding_thresh,
+                downsample_resolution,
+                buffer_init_size,
+                iterated_update,
+            },
+        )
+    }
+}
+
+impl<T: RealField> Config<T> {
+    #[inline]
+    pub fn with_mid360_extrinsics(self) -> Self {
+        Self {
+            extrinsics: mid360_extrinsics(),
+            ..self
+        }
+    }
+}
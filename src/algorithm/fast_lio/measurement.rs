@@ -0,0 +1,200 @@
+use std::ops::Deref;
+
+use nalgebra::{RealField, stack};
+
+use crate::{
+    algorithm::lio::{
+        StampedImu,
+        downsample::Downsample,
+        measurement::{LidarPoint, PointsObserved, StampedPoints},
+        state::State,
+    },
+    eskf::Eskf,
+    frame::{BodyPoint, CrossMatrixFramed, Framed, IsometryFramed, WorldPoint, frames},
+    kd_tree::{BoxRegion, KdTree},
+    utils::{CollectTo, ToRadians},
+};
+
+use super::{
+    FastLio,
+    plane::{NearestPlane, PlaneConfig},
+};
+
+pub type PointsProcessBuffer<T> = Vec<(BodyPoint<T>, CrossMatrixFramed<T, frames::Imu>)>;
+
+impl<T> FastLio<T>
+where
+    T: RealField + ToRadians,
+{
+    pub fn update_points_with_imus<P>(
+        &mut self,
+        points: StampedPoints<T, P>,
+        imus: impl IntoIterator<Item = StampedImu<T>>,
+    ) where
+        P: IntoIterator<Item: LidarPoint<T>>,
+    {
+        self.extend(imus);
+        self.update_stamped_points(points);
+    }
+
+    #[doc(alias = "update_points")]
+    pub fn update_stamped_points(
+        &mut self,
+        stamped_points: StampedPoints<T, impl IntoIterator<Item = impl LidarPoint<T>>>,
+    ) {
+        self.update_points(stamped_points.timestamp, stamped_points.measured)
+    }
+
+    pub fn update_points(
+        &mut self,
+        timestamp: T,
+        points: impl IntoIterator<Item = impl LidarPoint<T>>,
+    ) {
+        let body_to_imu = &self.extrinsics;
+
+        debug_assert_eq!(self.points_process_buffer.len(), 0);
+
+        points
+            .into_iter()
+            .map(LidarPoint::to_body_point)
+            .voxel_grid_downsample(&self.downsampler.resolution, &mut self.downsampler.grid)
+            .map(|body_point| {
+                let imu_point = &body_point * body_to_imu;
+                let cross_matrix_imu = Framed::new(imu_point.coords.cross_matrix());
+                (body_point, cross_matrix_imu)
+            })
+            .collect_to(&mut self.points_process_buffer);
+
+        self.eskf
+            .update_iterated(timestamp, &self.iterated_update, |eskf, _| {
+                let body_to_world = body_to_imu * eskf.pose.deref();
+                eskf.observe_nearest_planes(
+                    &self.map,
+                    &self.plane,
+                    &self.measure_noise.lidar_point,
+                    &body_to_world,
+                    self.points_process_buffer.iter(),
+                )
+            });
+
+        // the points are inserted with the predicted pose if not updated,
+        // which also initializes the map with the first scan.
+        let body_to_world = body_to_imu * self.eskf.pose.deref();
+        self.points_process_buffer
+            .drain(..)
+            .for_each(|(body_point, _)| {
+                self.map.insert_downsampled(&body_point * &body_to_world);
+            });
+
+        let position = WorldPoint::new(self.eskf.pose.translation.vector.clone().into());
+        self.slide_map(position);
+    }
+
+    /// Delete the points outside the local map cube if the position has moved enough.
+    pub(super) fn slide_map(&mut self, position: WorldPoint<T>) {
+        if let Some(last_position) = &self.last_slide_position
+            && (position.deref() - last_position.deref()).norm() <= self.sliding_thresh
+        {
+            return;
+        }
+        let region = BoxRegion::new_cube(&position, self.map_size.clone());
+        self.map.delete_outside_box(&region);
+        self.last_slide_position = Some(position);
+    }
+}
+
+impl<T> Eskf<State<T>>
+where
+    T: RealField + ToRadians,
+{
+    fn observe_nearest_planes<'a>(
+        &self,
+        map: &KdTree<T>,
+        plane_config: &PlaneConfig<T>,
+        measure_noise: &T,
+        body_to_world: &IsometryFramed<T, fn(frames::Body) -> frames::World>,
+        points: impl IntoIterator<Item = &'a (BodyPoint<T>, CrossMatrixFramed<T, frames::Imu>)>,
+    ) -> Option<PointsObserved<T>> {
+        let observation = points
+            .into_iter()
+            .filter_map(|(body_point, cross_matrix_imu)| {
+                let world_point = body_point * body_to_world;
+                let neighbours = map.nearest(&world_point, plane_config.num_neighbours);
+                let plane = NearestPlane::fit(&neighbours, plane_config)?;
+
+                let distance_to_plane = plane.distance_to(&world_point.coords);
+
+                // reject the residuals which are too large relative to the point range,
+                // the same as the original Fast LIO2.
+                let range_factor: T = nalgebra::convert(9.0);
+                if distance_to_plane.clone().abs() * range_factor >= body_point.coords.norm().sqrt()
+                {
+                    return None;
+                }
+
+                let plane_normal = &plane.normal;
+                let cross_matrix_rotation_t_normal =
+                    cross_matrix_imu.deref() * self.pose.rotation.transpose() * plane_normal;
+
+                #[expect(clippy::toplevel_ref_arg)]
+                let model = stack![cross_matrix_rotation_t_normal; plane_normal];
+
+                Some((-distance_to_plane, model, measure_noise.clone()))
+            })
+            .collect::<PointsObserved<T>>();
+
+        if observation.get_dim().0 == 0 {
+            return None;
+        }
+        Some(observation)
+    }
+}
+
+impl<T> Extend<StampedImu<T>> for FastLio<T>
+where
+    T: RealField + ToRadians,
+{
+    fn extend<I>(&mut self, imus: I)
+    where
+        I: IntoIterator<Item = StampedImu<T>>,
+    {
+        imus.into_iter().for_each(|imu| {
+            self.eskf.update(imu.timestamp, |eskf| {
+                Some(eskf.observe_imu(
+                    self.gravity_factor.clone(),
+                    &self.measure_noise,
+                    &imu.measured,
+                ))
+            });
+        })
+    }
+}
+
+impl<T, P> Extend<StampedPoints<T, P>> for FastLio<T>
+where
+    T: RealField + ToRadians,
+    P: IntoIterator<Item: LidarPoint<T>>,
+{
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = StampedPoints<T, P>>,
+    {
+        iter.into_iter().for_each(|points| {
+            self.update_stamped_points(points);
+        });
+    }
+}
+
+impl<T, P> Extend<(StampedImu<T>, StampedPoints<T, P>)> for FastLio<T>
+where
+    T: RealField + ToRadians,
+    P: IntoIterator<Item: LidarPoint<T>>,
+{
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (StampedImu<T>, StampedPoints<T, P>)>,
+    {
+        iter.into_iter()
+            .for_each(|(imu, points)| self.update_points_with_imus(points, [imu]));
+    }
+}
@@ -0,0 +1,117 @@
+use nalgebra::{Matrix3, RealField, Vector3};
+use simba::scalar::SupersetOf;
+
+use crate::kd_tree::Neighbour;
+
+pub struct PlaneConfig<T> {
+    /// The number of the nearest neighbours used to fit a plane.
+    pub num_neighbours: usize,
+    /// maximum distance from the point to its farthest neighbour
+    pub max_neighbour_distance: T,
+    /// maximum distance from the neighbours to the fitted plane
+    pub plane_thresh: T,
+}
+
+impl<T> Default for PlaneConfig<T>
+where
+    T: SupersetOf<f64>,
+{
+    fn default() -> Self {
+        Self {
+            num_neighbours: 5,
+            max_neighbour_distance: nalgebra::convert(5.0f64.sqrt()),
+            plane_thresh: nalgebra::convert(0.1),
+        }
+    }
+}
+
+/// A plane in Hessian normal form, `normal.dot(p) + offset = 0`.
+pub struct NearestPlane<T> {
+    pub normal: Vector3<T>,
+    pub offset: T,
+}
+
+impl<T: RealField> NearestPlane<T> {
+    /// Fit a plane from the `neighbours` by solving `A * n = -1` in the least square sense.
+    ///
+    /// Returns `None` if the neighbours are not enough or not planar.
+    pub fn fit(neighbours: &[Neighbour<'_, T>], config: &PlaneConfig<T>) -> Option<Self> {
+        let farthest = neighbours.last()?;
+        if neighbours.len() < config.num_neighbours
+            || farthest.distance_squared > config.max_neighbour_distance.clone().powi(2)
+        {
+            return None;
+        }
+
+        let (ata, atb) = neighbours.iter().fold(
+            (Matrix3::zeros(), Vector3::zeros()),
+            |(ata, atb), neighbour| {
+                let coords = &neighbour.point.coords;
+                (ata + coords * coords.transpose(), atb - coords)
+            },
+        );
+        let normal = ata.try_inverse()? * atb;
+
+        let norm = normal.norm();
+        let plane = Self {
+            normal: normal / norm.clone(),
+            offset: norm.recip(),
+        };
+
+        neighbours
+            .iter()
+            .all(|neighbour| {
+                plane.distance_to(&neighbour.point.coords).abs() <= config.plane_thresh
+            })
+            .then_some(plane)
+    }
+
+    #[inline]
+    pub fn distance_to(&self, coords: &Vector3<T>) -> T {
+        self.normal.dot(coords) + self.offset.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point3;
+
+    use super::*;
+    use crate::{frame::WorldPoint, kd_tree::KdTree};
+
+    /// The points on a grid of the plane `x + 2y - 2z = 3` bumped along `z` by `bump`.
+    fn plane_points(bump: impl Fn(usize, usize) -> f64) -> KdTree<f64> {
+        let mut tree = KdTree::new(Default::default());
+        tree.extend(itertools::iproduct!(0..5, 0..5).map(|(i, j)| {
+            let (x, y) = (i as f64 * 0.1, j as f64 * 0.1);
+            WorldPoint::new(Point3::new(x, y, (x + 2.0 * y - 3.0) / 2.0 + bump(i, j)))
+        }));
+        tree
+    }
+
+    #[test]
+    fn test_fit() {
+        let config = PlaneConfig::default();
+        let tree = plane_points(|_, _| 0.0);
+        let query = WorldPoint::new(Point3::new(0.2, 0.2, -1.2 + 0.3));
+        let neighbours = tree.nearest(&query, config.num_neighbours);
+        let Some(plane) = NearestPlane::fit(&neighbours, &config) else {
+            panic!("no plane is fitted");
+        };
+
+        // the plane is up to the sign, and the residual is the signed distance to it
+        let normal = Vector3::new(1.0, 2.0, -2.0) / 3.0;
+        let sign = plane.normal.dot(&normal).signum();
+        assert!((plane.normal * sign - normal).amax() < 1e-9);
+        assert!((plane.offset * sign + 1.0).abs() < 1e-9);
+        assert!((plane.distance_to(&query.coords) * sign + 0.2).abs() < 1e-9);
+
+        // not enough neighbours
+        assert!(NearestPlane::fit(&neighbours[..3], &config).is_none());
+        // one of the neighbours too far from the plane fitted
+        let tree = plane_points(|i, j| if (i, j) == (2, 2) { 0.4 } else { 0.0 });
+        let query = WorldPoint::new(Point3::new(0.2, 0.2, -1.2 + 0.4));
+        let neighbours = tree.nearest(&query, config.num_neighbours);
+        assert!(NearestPlane::fit(&neighbours, &config).is_none());
+    }
+}
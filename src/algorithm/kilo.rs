@@ -1,15 +1,290 @@
 //! An inertial–LiDAR tightly‑coupled error‑state Kalman filter odometry system.
+//!
+//! The [`LIO`] is extended with the leg kinematics of a legged robot,
+//! see also [Leg-KILO](https://arxiv.org/abs/2404.09154).
 
+pub mod config;
 pub mod estimate;
+pub mod predict;
 pub mod state;
 
-use nalgebra::ComplexField;
+use std::ops::{Deref, DerefMut};
+
+use nalgebra::{ComplexField, RealField};
 use state::State;
 
-use crate::{eskf::Eskf, voxel_map::VoxelMap};
+use crate::{
+    algorithm::lio::{ImuInit, LIO},
+    utils::{CollectTo, ToRadians},
+};
+
+pub use config::{Config, NoGravityConfig};
+use estimate::{LegKinematics, LegMeasured, MeasureNoiseConfig, StampedLegs, StanceFoot};
+
+/// # Input
+/// The IMU and the LiDAR points are the same as [`LIO`], which can be accessed by [`Deref`],
+/// and the legs are updated by [`Kilo::update_legs`].
+pub struct Kilo<T, K>
+where
+    T: ComplexField,
+{
+    lio: LIO<T, State<T>>,
+    kinematics: K,
+    stance_feet: Vec<StanceFoot<T>>,
+    /// The leg whose foot is tracked by the contact foot position sub-state.
+    contact_leg: Option<usize>,
+    // configs
+    measure_noise: MeasureNoiseConfig<T>,
+    contact_foot_init_cov: T,
+}
+
+impl<T> ImuInit<T>
+where
+    T: RealField + ToRadians,
+{
+    pub fn new_kilo<K>(self, config: Config<T>, kinematics: K) -> Kilo<T, K> {
+        Kilo::new(config, kinematics, self)
+    }
+}
+
+impl<T, K> Kilo<T, K>
+where
+    T: RealField + ToRadians,
+{
+    pub fn new(config: Config<T>, kinematics: K, imu_init: ImuInit<T>) -> Self {
+        let (gravity, config) = config.take_gravity();
+        let gravity_factor = gravity / imu_init.linear_acc_norm.clone();
+
+        let mut kilo = Self::new_with_gravity_factor(
+            config,
+            kinematics,
+            imu_init.timestamp_init.clone(),
+            gravity_factor,
+        );
+        kilo.lio.init_with_imu(imu_init);
+        kilo
+    }
+
+    /// Create a new Kilo instance with a given gravity factor.
+    ///
+    /// This does not need the `gravity` in [`Config<T>`], provide [`NoGravityConfig<T>`] instead.
+    pub fn new_with_gravity_factor(
+        config: NoGravityConfig<T>,
+        kinematics: K,
+        timestamp_init: T,
+        gravity_factor: T,
+    ) -> Self {
+        let lio_process_cov = config
+            .lio
+            .imu_propagation
+            .state_process_cov(config.lio.process_cov.state.clone());
+        let process_cov = (lio_process_cov, config.process_cov).into();
+        let lio =
+            LIO::new_with_process_cov(config.lio, process_cov, timestamp_init, gravity_factor);
+
+        Self {
+            lio,
+            kinematics,
+            stance_feet: Vec::new(),
+            contact_leg: None,
+            measure_noise: config.measure_noise,
+            contact_foot_init_cov: config.contact_foot_init_cov,
+        }
+    }
+
+    #[inline]
+    pub fn state(&self) -> &State<T> {
+        &self.lio.eskf.state
+    }
+}
+
+impl<T, K> Kilo<T, K>
+where
+    T: RealField + ToRadians,
+    K: LegKinematics<T>,
+{
+    #[doc(alias = "update_legs")]
+    pub fn update_stamped_legs(
+        &mut self,
+        stamped_legs: StampedLegs<T, impl IntoIterator<Item = LegMeasured<K::Joints>>>,
+    ) {
+        self.update_legs(stamped_legs.timestamp, stamped_legs.measured)
+    }
+
+    /// Observe the body velocity from the feet contacting the ground,
+    /// and the position of the tracked contact foot which is static until it lifts off.
+    pub fn update_legs(
+        &mut self,
+        timestamp: T,
+        legs: impl IntoIterator<Item = LegMeasured<K::Joints>>,
+    ) {
+        debug_assert_eq!(self.stance_feet.len(), 0);
+
+        let kinematics = &self.kinematics;
+        legs.into_iter()
+            .enumerate()
+            .filter(|(_, leg)| leg.contact)
+            .map(|(index, leg)| StanceFoot {
+                leg: index,
+                position: kinematics.foot_position(index, &leg.joints),
+                velocity: kinematics.foot_velocity(index, &leg.joints),
+            })
+            .collect_to(&mut self.stance_feet);
+
+        let contact_foot = self
+            .contact_leg
+            .and_then(|leg| self.stance_feet.iter().find(|foot| foot.leg == leg));
+
+        self.lio.eskf.update(timestamp.clone(), |eskf| {
+            eskf.observe_legs(&self.stance_feet, contact_foot, &self.measure_noise)
+        });
+
+        // the tracked foot lifted off, track a new one touching down
+        if contact_foot.is_none() {
+            self.contact_leg = self.stance_feet.first().map(|foot| {
+                self.lio
+                    .eskf
+                    .reset_contact_foot(&foot.position, self.contact_foot_init_cov.clone());
+                foot.leg
+            });
+        }
+
+        self.stance_feet.clear();
+        self.lio.record_state(timestamp);
+    }
+}
+
+impl<T: ComplexField, K> Deref for Kilo<T, K> {
+    type Target = LIO<T, State<T>>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.lio
+    }
+}
+
+impl<T: ComplexField, K> DerefMut for Kilo<T, K> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.lio
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use super::*;
+    use crate::{
+        algorithm::lio::{StampedImu, predict::ImuPropagation},
+        eskf::state::common::{
+            AccState, AngularAccState, LinearAccState, PositionState, VelocityState,
+        },
+    };
+
+    /// The joints are the foot position and velocity themselves.
+    struct FootKinematics;
+
+    type Foot = (Vector3<f64>, Vector3<f64>);
+
+    impl LegKinematics<f64> for FootKinematics {
+        type Joints = Foot;
+
+        fn foot_position(&self, _: usize, joints: &Foot) -> Vector3<f64> {
+            joints.0
+        }
+
+        fn foot_velocity(&self, _: usize, joints: &Foot) -> Vector3<f64> {
+            joints.1
+        }
+    }
+
+    fn new_kilo(config: NoGravityConfig<f64>) -> Kilo<f64, FootKinematics> {
+        let mut kilo = Kilo::new_with_gravity_factor(config, FootKinematics, 0.0, 1.0);
+        let cov = &mut kilo.lio.eskf.cov;
+        cov.sub_covariance_mut::<VelocityState<f64>>()
+            .fill_diagonal(1.0);
+        cov.sub_covariance_mut::<PositionState<f64>>()
+            .fill_diagonal(1.0);
+        kilo
+    }
+
+    fn legs(stance: Vector3<f64>, velocity: Vector3<f64>) -> [LegMeasured<Foot>; 2] {
+        let swing = Vector3::new(-0.3, 0.2, -0.1);
+        [
+            LegMeasured {
+                joints: (stance, velocity),
+                contact: true,
+            },
+            // the swing foot is ignored
+            LegMeasured {
+                joints: (swing, Vector3::new(5.0, 5.0, 5.0)),
+                contact: false,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_stance_foot_velocity() {
+        let mut kilo = new_kilo(Default::default());
+        *kilo.lio.eskf.state.state.velocity = Vector3::new(1.0, -0.5, 0.2);
+
+        // the static stance foot means the body is static as well
+        let stance = Vector3::new(0.3, 0.2, -0.4);
+        kilo.update_legs(0.0, legs(stance, Vector3::zeros()));
+        assert!(kilo.state().state.velocity.norm() < 0.05);
+        assert_eq!(kilo.contact_leg, Some(0));
+
+        // the stance foot moving backward means the body moves forward
+        let mut kilo = new_kilo(Default::default());
+        kilo.update_legs(0.0, legs(stance, Vector3::new(-0.5, 0.0, 0.0)));
+        let velocity = kilo.state().state.velocity.0;
+        assert!((velocity - Vector3::new(0.5, 0.0, 0.0)).amax() < 0.05);
+    }
+
+    #[test]
+    fn test_contact_foot_position() {
+        let mut kilo = new_kilo(Default::default());
+        let stance = Vector3::new(0.3, 0.2, -0.4);
+        kilo.update_legs(0.0, legs(stance, Vector3::zeros()));
+        let contact_foot_pos = kilo.state().contact_foot_pos.0;
+        assert!((contact_foot_pos - stance).amax() < 1e-9);
+
+        // the drifted body is pulled back by the static contact foot
+        kilo.lio.eskf.state.state.pose.0.translation.vector = Vector3::new(0.1, -0.1, 0.0);
+        kilo.update_legs(0.0, legs(stance, Vector3::zeros()));
+        let position = kilo.state().state.pose.translation.vector;
+        assert!(position.amax() < 0.02);
+        assert_eq!(kilo.contact_leg, Some(0));
+    }
+
+    #[test]
+    fn test_control_input_propagation() {
+        let mut config = NoGravityConfig::<f64>::default();
+        config.lio.imu_propagation = ImuPropagation::ControlInput(Default::default());
+        let mut kilo = new_kilo(config);
+
+        let measured = AccState {
+            linear: LinearAccState::new(Vector3::new(0.1, 0.2, 9.8)),
+            angular: AngularAccState::new(Vector3::new(0.01, -0.02, 0.03)),
+        };
+        let imus = (1..=10).map(|i| StampedImu {
+            timestamp: i as f64 * 0.01,
+            measured: measured.clone(),
+        });
+        kilo.extend(imus);
 
-#[expect(unused)]
-pub struct Kilo<T: ComplexField> {
-    eskf: Eskf<State<T>>,
-    map: VoxelMap<T>,
+        // the accelerations are held as the control input rather than observed
+        let acc = &kilo.state().state.acc_with_bias.acc;
+        assert_eq!(acc.linear.0, measured.linear.0);
+        assert_eq!(acc.angular.0, measured.angular.0);
+        // while the leg kinematics sub-states are still driven by their process noise
+        let bias_cov = kilo
+            .lio
+            .eskf
+            .cov
+            .sub_covariance::<state::KinVelocityBiasState<f64>>()
+            .into_owned();
+        assert!(bias_cov[(0, 0)] > 1e-6);
+    }
 }
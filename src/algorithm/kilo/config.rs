@@ -0,0 +1,61 @@
+use crate::algorithm::lio::{self, config::NoGravity};
+
+use super::{estimate::MeasureNoiseConfig, predict::ProcessCovConfig};
+
+use nalgebra::{RealField, Scalar};
+
+/// The configuration of the Kilo algorithm with no need to provide the gravity.
+pub type NoGravityConfig<T> = Config<T, NoGravity>;
+
+pub struct Config<T: Scalar, G = T> {
+    /// The configuration of the underlying LIO, including the gravity.
+    pub lio: lio::Config<T, G>,
+
+    /// The process noise configuration of the leg kinematics sub-states.
+    pub process_cov: ProcessCovConfig<T>,
+
+    /// The measurement noise configuration of the leg kinematics.
+    pub measure_noise: MeasureNoiseConfig<T>,
+
+    /// The initial variance of the contact foot position when the foot touches down.
+    pub contact_foot_init_cov: T,
+}
+
+impl<T: RealField> Default for Config<T> {
+    fn default() -> Self {
+        Self {
+            lio: Default::default(),
+            process_cov: Default::default(),
+            measure_noise: Default::default(),
+            contact_foot_init_cov: nalgebra::convert(0.01),
+        }
+    }
+}
+
+impl<T: RealField> Default for NoGravityConfig<T> {
+    #[inline]
+    fn default() -> Self {
+        Config::<T>::default().take_gravity().1
+    }
+}
+
+impl<T: Scalar, G> Config<T, G> {
+    pub fn take_gravity(self) -> (G, NoGravityConfig<T>) {
+        let Self {
+            lio,
+            process_cov,
+            measure_noise,
+            contact_foot_init_cov,
+        } = self;
+        let (gravity, lio) = lio.take_gravity();
+        (
+            gravity,
+            NoGravityConfig {
+                lio,
+                process_cov,
+                measure_noise,
+                contact_foot_init_cov,
+            },
+        )
+    }
+}
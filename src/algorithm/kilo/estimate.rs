@@ -1,6 +1,154 @@
+use std::ops::Deref;
+
 use super::State;
-use crate::eskf::{observe::UnbiasedObservation, state::common::*};
+use crate::{
+    algorithm::lio::measurement::StampedMeasurement,
+    eskf::{
+        Eskf,
+        observe::Observation,
+        state::{StateDim, SubStateOf, SubStateOffset, common::*, correlation::FullState},
+    },
+};
+
+use super::state::{ContactFootPosState, KinVelocityBiasState};
+
+use nalgebra::{DimName, Dyn, Matrix3, OMatrix, Point3, RealField, Scalar, U3, Vector3};
+use simba::scalar::SupersetOf;
+
+/// The observation of the leg kinematics, which involves the sub-states scattered in the [`State`].
+pub type KinImuObserved<T> = Observation<FullState<State<T>>, State<T>, Dyn>;
+
+pub type StampedLegs<T, L> = StampedMeasurement<T, L>;
+
+/// The forward kinematics of the legs, which maps the joint encoder readings to the feet.
+pub trait LegKinematics<T: Scalar> {
+    /// The joint encoder readings of a leg, e.g. the joint angles and the joint velocities.
+    type Joints;
+
+    /// The position of the foot of the `leg` relative to the IMU, in the IMU frame.
+    fn foot_position(&self, leg: usize, joints: &Self::Joints) -> Vector3<T>;
+
+    /// The velocity of the foot of the `leg` relative to the IMU, in the IMU frame,
+    /// which is usually `J(q) * dq` with the leg jacobian `J`.
+    fn foot_velocity(&self, leg: usize, joints: &Self::Joints) -> Vector3<T>;
+}
+
+/// The measurement of a leg, the index of the leg is its position in the measured legs.
+pub struct LegMeasured<J> {
+    pub joints: J,
+    /// Whether the foot contacts the ground.
+    pub contact: bool,
+}
+
+pub struct MeasureNoiseConfig<T> {
+    /// The noise of the body velocity measured by the leg kinematics.
+    pub kinematic_velocity: T,
+    /// The noise of the contact foot position measured by the leg kinematics.
+    pub contact_foot_pos: T,
+}
+
+/// A foot contacting the ground.
+pub struct StanceFoot<T: Scalar> {
+    pub leg: usize,
+    /// See also [`LegKinematics::foot_position`].
+    pub position: Vector3<T>,
+    /// See also [`LegKinematics::foot_velocity`].
+    pub velocity: Vector3<T>,
+}
+
+type Model<T> = OMatrix<T, U3, StateDim<State<T>>>;
+
+impl<T> Eskf<State<T>>
+where
+    T: RealField,
+{
+    /// Observe the body velocity from every stance foot,
+    /// and the position of the `contact_foot` which is tracked by the `contact_foot_pos` sub-state.
+    pub(crate) fn observe_legs<'a>(
+        &self,
+        stance_feet: impl IntoIterator<Item = &'a StanceFoot<T>>,
+        contact_foot: Option<&StanceFoot<T>>,
+        measure_noise: &MeasureNoiseConfig<T>,
+    ) -> Option<KinImuObserved<T>> {
+        let state = &self.state.state;
+        let rotation = state.pose.rotation.matrix();
+        let velocity_imu = rotation.transpose() * state.velocity.deref();
+        let angular_acc = state.acc_with_bias.acc.angular.deref();
+        let velocity_bias = self.kinematic_velocity_bias.deref();
+
+        // h = R^T * v + w x f + b, the measurement is the negative foot velocity
+        let velocity_observations = stance_feet.into_iter().map(|foot| {
+            let residual =
+                -&foot.velocity - &velocity_imu - angular_acc.cross(&foot.position) - velocity_bias;
+
+            let mut model = Model::<T>::zeros();
+            set_model::<RotationState<T>, T>(&mut model, velocity_imu.cross_matrix());
+            set_model::<VelocityState<T>, T>(&mut model, rotation.transpose());
+            set_model::<AngularAccState<T>, T>(&mut model, -foot.position.cross_matrix());
+            set_model::<KinVelocityBiasState<T>, T>(&mut model, Matrix3::identity());
+
+            (residual, model, measure_noise.kinematic_velocity.clone())
+        });
+
+        // h = p + R * f - c, the measurement is zero since the contact foot is static
+        let contact_observation = contact_foot.map(|foot| {
+            let foot_world =
+                self.state.state.pose.deref().deref() * Point3::from(foot.position.clone());
+            let residual = self.contact_foot_pos.deref() - foot_world.coords;
+
+            let mut model = Model::<T>::zeros();
+            set_model::<RotationState<T>, T>(&mut model, -rotation * foot.position.cross_matrix());
+            set_model::<PositionState<T>, T>(&mut model, Matrix3::identity());
+            set_model::<ContactFootPosState<T>, T>(&mut model, -Matrix3::identity());
+
+            (residual, model, measure_noise.contact_foot_pos.clone())
+        });
+
+        let observation = velocity_observations
+            .chain(contact_observation)
+            .flat_map(|(residual, model, noise)| {
+                (0..3).map(move |i| (residual[i].clone(), model.row(i).transpose(), noise.clone()))
+            })
+            .collect::<KinImuObserved<T>>();
+
+        if observation.get_dim().0 == 0 {
+            return None;
+        }
+        Some(observation)
+    }
+
+    /// Start tracking a touched down foot at `foot_position` relative to the IMU,
+    /// the correlations of the previous contact foot are dropped.
+    pub(crate) fn reset_contact_foot(&mut self, foot_position: &Vector3<T>, init_cov: T) {
+        let foot_world =
+            self.state.state.pose.deref().deref() * Point3::from(foot_position.clone());
+        *self.state.contact_foot_pos = foot_world.coords;
+
+        let offset = SubStateOffset::<ContactFootPosState<T>, State<T>>::DIM;
+        self.cov.fixed_rows_mut::<3>(offset).fill(T::zero());
+        self.cov.fixed_columns_mut::<3>(offset).fill(T::zero());
+        self.cov
+            .sub_covariance_mut::<ContactFootPosState<T>>()
+            .fill_diagonal(init_cov);
+    }
+}
 
-use nalgebra::Dyn;
+/// Set the jacobian of the observation w.r.t. the sub-state `S`.
+fn set_model<S, T>(model: &mut Model<T>, jacobian: Matrix3<T>)
+where
+    T: RealField,
+    S: SubStateOf<State<T>, Dim = U3>,
+{
+    model
+        .fixed_columns_mut::<3>(S::Offset::DIM)
+        .copy_from(&jacobian);
+}
 
-pub type KinImuObserved<T> = UnbiasedObservation<PoseState<T>, State<T>, Dyn>;
+impl<T: SupersetOf<f64>> Default for MeasureNoiseConfig<T> {
+    fn default() -> Self {
+        Self {
+            kinematic_velocity: nalgebra::convert(0.01),
+            contact_foot_pos: nalgebra::convert(0.001),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use super::{
+    State,
+    state::{ContactFootPosState, KinVelocityBiasState},
+};
+use crate::{
+    algorithm::lio::config::StateProcessCovConfig,
+    eskf::{Covariance, DeltaTime, Eskf, StatePredictor},
+};
+
+use nalgebra::{RealField, Scalar};
+use num_traits::Zero;
+use simba::scalar::SupersetOf;
+
+/// The process noise configuration of the leg kinematics sub-states,
+/// see also [`StateProcessCovConfig`] for the other sub-states.
+pub struct ProcessCovConfig<T> {
+    pub kinematic_velocity_bias: T,
+    pub contact_foot_pos: T,
+}
+
+/// The accelerations are predicted by the constant acceleration model, or held as the control input
+/// with [`ImuPropagation::ControlInput`](crate::algorithm::lio::predict::ImuPropagation::ControlInput),
+/// whose process noise of the LIO sub-states is then zeroed by
+/// [`ImuPropagation::state_process_cov`](crate::algorithm::lio::predict::ImuPropagation::state_process_cov),
+/// while the leg kinematics sub-states keep their [`ProcessCovConfig`] in both cases.
+impl<T> StatePredictor<DeltaTime<T>> for Eskf<State<T>>
+where
+    T: RealField,
+{
+    fn predict(&mut self, dt: DeltaTime<T>) {
+        self.state.predict(dt.predict);
+        self.predict_cov(dt.observe);
+    }
+}
+
+impl<T: SupersetOf<f64>> Default for ProcessCovConfig<T> {
+    fn default() -> Self {
+        Self {
+            kinematic_velocity_bias: nalgebra::convert(0.01),
+            contact_foot_pos: nalgebra::convert(0.01),
+        }
+    }
+}
+
+impl<T> From<(StateProcessCovConfig<T>, ProcessCovConfig<T>)> for Covariance<State<T>>
+where
+    T: Scalar + Zero,
+{
+    fn from((lio, kilo): (StateProcessCovConfig<T>, ProcessCovConfig<T>)) -> Self {
+        let mut cov: Self = lio.into();
+
+        cov.sub_covariance_mut::<KinVelocityBiasState<T>>()
+            .fill_diagonal(kilo.kinematic_velocity_bias);
+
+        cov.sub_covariance_mut::<ContactFootPosState<T>>()
+            .fill_diagonal(kilo.contact_foot_pos);
+
+        cov
+    }
+}
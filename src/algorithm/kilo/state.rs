@@ -1,24 +1,138 @@
 use crate::{
-    algorithm::lio,
-    eskf::state::{common::Vector3State, macro_export::*},
+    algorithm::lio::{
+        self,
+        measurement::{MeasureNoiseConfig, PointsObserveStats, PointsObserved, ProcessingPoint},
+    },
+    eskf::{
+        Eskf, StatePredictor,
+        state::{common::*, correlation::UnbiasedState, macro_export::*},
+    },
+    frame::{IsometryFramed, frames},
+    voxel_map::VoxelMap,
 };
 use nalgebra::{RealField, Scalar};
-use odometries_macros::{KFState, VectorAddAssign};
+use odometries_macros::{KFState, VectorAddAssign, sub_state_of};
 
-#[derive(KFState, VectorAddAssign)]
+#[derive(Clone, KFState, VectorAddAssign)]
 #[element(T)]
 #[vector_add_assign(predicates(RealField))]
 pub struct State<T>
 where
     T: Scalar,
 {
-    state: lio::state::State<T>,
-    kinematic_velocity_bias: KinVelocityBiasState<T>,
-    contact_foot_pos: ContactFootPosState<T>,
+    pub state: lio::state::State<T>,
+    /// The bias of the body velocity measured by the leg kinematics,
+    /// which absorbs the foot slipping and the kinematics error.
+    pub kinematic_velocity_bias: KinVelocityBiasState<T>,
+    /// The position of the contact foot in the world frame,
+    /// which is static during the stance phase.
+    pub contact_foot_pos: ContactFootPosState<T>,
 }
 
 pub struct KinVelocityBias;
-type KinVelocityBiasState<T> = Vector3State<T, KinVelocityBias>;
+pub type KinVelocityBiasState<T> = Vector3State<T, KinVelocityBias>;
 
 pub struct ContactFootPos;
-type ContactFootPosState<T> = Vector3State<T, ContactFootPos>;
+pub type ContactFootPosState<T> = Vector3State<T, ContactFootPos>;
+
+type LioState<T> = lio::state::State<T>;
+
+#[sub_state_of(State)]
+struct LioState<T: Scalar>(
+    PoseState<T>,
+    VelocityState<T>,
+    GravityState<T>,
+    AccWithBiasState<T>,
+);
+
+#[sub_state_of(State)]
+struct PoseState<T: Scalar>(RotationState<T>, PositionState<T>);
+
+#[sub_state_of(State)]
+struct AccWithBiasState<T: Scalar>(AccState<T>, BiasState<T>);
+
+#[sub_state_of(State)]
+struct AccState<T: Scalar>(LinearAccState<T>, AngularAccState<T>);
+
+#[sub_state_of(State)]
+struct BiasState<T: Scalar>(LinearAccBiasState<T>, AngularAccBiasState<T>);
+
+impl<T> StatePredictor<T> for State<T>
+where
+    T: RealField,
+{
+    /// The biases and the contact foot position are constant during the prediction.
+    #[inline]
+    fn predict(&mut self, dt: T) {
+        self.state.predict(dt);
+    }
+}
+
+impl<T: RealField> lio::state::LioState<T> for State<T> {
+    type PointsState = UnbiasedState<PoseState<T>>;
+
+    #[inline]
+    fn observe_points(
+        eskf: &Eskf<Self>,
+        map: &VoxelMap<T>,
+        measure_noise: &MeasureNoiseConfig<T>,
+        body_to_world: &IsometryFramed<T, fn(frames::Body) -> frames::World>,
+        points: &[ProcessingPoint<T>],
+        stats: &mut PointsObserveStats<T>,
+    ) -> Option<PointsObserved<T, Self>> {
+        eskf.observe_pose_points(map, measure_noise, body_to_world, points, stats)
+    }
+}
+
+impl<T: Scalar> AsRef<LioState<T>> for State<T> {
+    #[inline(always)]
+    fn as_ref(&self) -> &LioState<T> {
+        &self.state
+    }
+}
+
+impl<T: Scalar> AsMut<LioState<T>> for State<T> {
+    #[inline(always)]
+    fn as_mut(&mut self) -> &mut LioState<T> {
+        &mut self.state
+    }
+}
+
+impl<T> Default for State<T>
+where
+    T: RealField,
+{
+    fn default() -> Self {
+        Self {
+            state: Default::default(),
+            kinematic_velocity_bias: Default::default(),
+            contact_foot_pos: Default::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eskf::state::StateDim;
+
+    use super::*;
+    use nalgebra::DimName;
+
+    type TestT = f64;
+
+    type State = super::State<f64>;
+    type SubStateOffset<S> = super::SubStateOffset<S, State>;
+
+    #[test]
+    fn test_error_state_impl() {
+        assert_eq!(StateDim::<State>::DIM, 30);
+
+        assert_eq!(SubStateOffset::<RotationState<TestT>>::DIM, 0);
+        assert_eq!(SubStateOffset::<PositionState<TestT>>::DIM, 3);
+        assert_eq!(SubStateOffset::<VelocityState<TestT>>::DIM, 6);
+        assert_eq!(SubStateOffset::<AngularAccState<TestT>>::DIM, 15);
+        assert_eq!(SubStateOffset::<AngularAccBiasState<TestT>>::DIM, 21);
+        assert_eq!(SubStateOffset::<KinVelocityBiasState<TestT>>::DIM, 24);
+        assert_eq!(SubStateOffset::<ContactFootPosState<TestT>>::DIM, 27);
+    }
+}
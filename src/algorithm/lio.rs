@@ -1,29 +1,45 @@
 //! Most of ideas comes from Leg-Kilo
 
 pub mod config;
+pub mod deskew;
 pub mod downsample;
+pub mod extrinsic;
 pub mod measurement;
 pub mod predict;
+pub mod relocalize;
 pub mod state;
+#[cfg(feature = "async")]
+pub mod stream;
+pub mod sync;
+pub mod time_offset;
 
-use std::ops::Deref;
+use std::ops::{AddAssign, Deref};
 
-use state::State;
+use state::{LioState, State};
 
 use crate::{
     eskf::{
-        Eskf,
-        state::common::{GravityState, LinearAccState},
+        Covariance, ErrorState, Eskf, IteratedConfig,
+        smoother::{SmoothedState, Smoother},
+        state::{
+            KFState, SubStateOf,
+            common::{GravityState, LinearAccState, PoseState},
+        },
+        uncertain::Uncertained,
     },
-    frame::{IsometryFramed, frames},
+    frame::{IsometryFramed, WorldPoint, frames},
+    trajectory::{Trajectory, TrajectoryPoint},
     utils::ToRadians,
-    voxel_map::{VoxelMap, uncertain::plane::Plane},
+    voxel_map::{SharedVoxelMap, VoxelMap, uncertain::plane::Plane},
 };
 pub use config::{BodyPointProcessCov, Config, NoGravityConfig};
+use deskew::StateHistory;
 use downsample::{Downsampler, ScanDownsampler};
-use measurement::PointsProcessBuffer;
+use measurement::{DegeneracyConfig, PointsProcessBuffer, StationaryDetector};
+use predict::ImuPropagation;
+use relocalize::RelocalizeConfig;
 
-use nalgebra::{ComplexField, RealField};
+use nalgebra::{ComplexField, DefaultAllocator, Matrix6, RealField, U6, allocator::Allocator};
 
 pub use measurement::{ImuInit, ImuMeasured, MeasureNoiseConfig, StampedImu};
 
@@ -42,19 +58,37 @@ pub use measurement::{ImuInit, ImuMeasured, MeasureNoiseConfig, StampedImu};
 ///                                    │
 ///                      LiDAR point ├─╯
 /// ```
-pub struct LIO<T>
+///
+/// The estimated state `S` is the LIO [`State`] by default,
+/// which can be extended by other algorithms, see also [`LioState`].
+pub struct LIO<T, S = State<T>>
 where
     T: ComplexField,
+    S: KFState<Element = T>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
 {
-    eskf: Eskf<State<T>>,
+    pub(crate) eskf: Eskf<S>,
     map: VoxelMap<T>,
     downsampler: ScanDownsampler<T>,
     points_process_buffer: PointsProcessBuffer<T>,
+    state_history: StateHistory<T>,
     // configs
     body_point_process_cov: BodyPointProcessCov<T>,
     measure_noise: MeasureNoiseConfig<T>,
+    imu_propagation: ImuPropagation<T>,
+    /// The timestamp of the previous IMU measurement, whose sample period scales the control input noise.
+    last_imu_timestamp: Option<T>,
+    /// The stationary detector, `None` if the detection is disabled.
+    stationary: Option<StationaryDetector<T>>,
     extrinsics: IsometryFramed<T, fn(frames::Body) -> frames::Imu>,
+    time_offset: T,
     gravity_factor: T,
+    iterated_update: IteratedConfig<T>,
+    degeneracy: DegeneracyConfig<T>,
+    state_history_size: usize,
+    relocalize: RelocalizeConfig<T>,
+    /// The recorded trajectory, `None` if the recording is disabled.
+    trajectory: Option<Trajectory<T>>,
 }
 
 impl<T> ImuInit<T>
@@ -75,15 +109,8 @@ where
         let gravity_factor = gravity / imu_init.linear_acc_norm.clone();
 
         let mut lio =
-            Self::new_with_gravity_factor(config, imu_init.timestamp_init, gravity_factor.clone());
-
-        let gravity = imu_init.linear_acc_mean.deref() * gravity_factor;
-
-        let eskf = &mut lio.eskf;
-        eskf.acc_with_bias.acc.linear = LinearAccState::new(gravity.clone());
-        eskf.gravity = GravityState::new(-gravity);
-        eskf.acc_with_bias.bias.angular = imu_init.angular_acc_bias;
-
+            Self::new_with_gravity_factor(config, imu_init.timestamp_init.clone(), gravity_factor);
+        lio.init_with_imu(imu_init);
         lio
     }
 
@@ -95,27 +122,233 @@ where
         timestamp_init: T,
         gravity_factor: T,
     ) -> Self {
-        let eskf = Eskf::new(config.process_cov.state.into(), timestamp_init);
+        let process_cov = config
+            .imu_propagation
+            .state_process_cov(config.process_cov.state.clone())
+            .into();
+        Self::new_with_process_cov(config, process_cov, timestamp_init, gravity_factor)
+    }
+}
+
+impl<T, S> LIO<T, S>
+where
+    T: RealField + ToRadians,
+    S: LioState<T>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
+{
+    /// Create a new instance estimating the state `S` with its `process_cov`,
+    /// the process noise configuration in `config` is ignored except the body point one.
+    pub(crate) fn new_with_process_cov(
+        config: NoGravityConfig<T>,
+        process_cov: Covariance<S>,
+        timestamp_init: T,
+        gravity_factor: T,
+    ) -> Self {
+        let eskf = Eskf::new(process_cov, timestamp_init);
 
         Self {
             eskf,
             map: VoxelMap::new(config.voxel_map),
             downsampler: Downsampler::new(config.downsample_resolution),
             points_process_buffer: Vec::with_capacity(config.buffer_init_size),
+            state_history: StateHistory::with_capacity(config.state_history_size),
             body_point_process_cov: config.process_cov.body_point,
             measure_noise: config.measure_noise,
+            imu_propagation: config.imu_propagation,
+            last_imu_timestamp: None,
+            stationary: config.stationary.map(StationaryDetector::new),
             extrinsics: config.extrinsics,
+            time_offset: config.time_offset.init,
             gravity_factor,
+            iterated_update: config.iterated_update,
+            degeneracy: config.degeneracy,
+            state_history_size: config.state_history_size,
+            relocalize: config.relocalize,
+            trajectory: None,
+        }
+    }
+
+    /// Initialize the accelerations, the gravity and the gyroscope bias from the static IMU measurements.
+    pub(crate) fn init_with_imu(&mut self, imu_init: ImuInit<T>) {
+        let gravity = imu_init.linear_acc_mean.deref() * self.gravity_factor.clone();
+
+        let state = self.eskf.state.as_mut();
+        state.acc_with_bias.acc.linear = LinearAccState::new(gravity.clone());
+        state.gravity = GravityState::new(-gravity);
+        state.acc_with_bias.bias.angular = imu_init.angular_acc_bias;
+    }
+
+    /// Record the current state at `timestamp` for the points deskewing.
+    pub(crate) fn record_state(&mut self, timestamp: T) {
+        if self.state_history.len() >= self.state_history_size {
+            self.state_history.pop_front();
         }
+        self.state_history
+            .push_back((timestamp, self.eskf.state.as_ref().clone()));
     }
 
     #[inline]
     pub fn get_pose(&self) -> &IsometryFramed<T, fn(frames::Imu) -> frames::World> {
-        &self.eskf.pose.0
+        &self.eskf.state.as_ref().pose.0
+    }
+
+    /// The extrinsics of the IMU to the body frame,
+    /// which are estimated online if the state has them, see also [`LioState::extrinsics`].
+    #[inline]
+    pub fn extrinsics(&self) -> &IsometryFramed<T, fn(frames::Body) -> frames::Imu> {
+        self.eskf.state.extrinsics().unwrap_or(&self.extrinsics)
+    }
+
+    /// The time offset added to the timestamps of the lidar points to get their IMU time,
+    /// which is estimated online if the state has it, see also [`LioState::time_offset`].
+    #[inline]
+    pub fn time_offset(&self) -> &T {
+        self.eskf.state.time_offset().unwrap_or(&self.time_offset)
+    }
+
+    #[inline]
+    fn position(&self) -> WorldPoint<T> {
+        WorldPoint::new(self.get_pose().translation.vector.clone().into())
+    }
+
+    /// Whether the IMU is detected stationary, which observes the zero velocity,
+    /// always `false` if the detection is disabled, see also [`Config::stationary`].
+    #[inline]
+    pub fn is_stationary(&self) -> bool {
+        self.stationary
+            .as_ref()
+            .is_some_and(StationaryDetector::is_stationary)
     }
 
     #[inline]
     pub fn planes(&self) -> impl Iterator<Item = &Plane<T>> {
         self.map.planes()
     }
+
+    #[inline]
+    pub fn map(&self) -> &VoxelMap<T> {
+        &self.map
+    }
+
+    /// Share the map with the readers on other threads, e.g. a planner,
+    /// the map is published to them after each update of the lidar points, see also [`VoxelMap::share`]
+    /// for the cost of each publish.
+    #[inline]
+    pub fn share_map(&mut self) -> SharedVoxelMap<T> {
+        self.map.share()
+    }
+
+    /// Replace the map with a prior one, e.g. loaded by [`VoxelMap::load`],
+    /// the following points are registered against it.
+    ///
+    /// The voxels of the prior map are pinned, so they are never evicted by the sliding of the map,
+    /// see also [`VoxelMap::pin`].
+    #[inline]
+    pub fn with_map(mut self, mut map: VoxelMap<T>) -> Self {
+        map.pin();
+        self.map = map;
+        self
+    }
+
+    /// Enable the trajectory recording, a [`TrajectoryPoint`] is recorded after each update of the lidar points.
+    #[inline]
+    pub fn with_trajectory_recorder(mut self) -> Self {
+        self.trajectory = Some(Trajectory::new());
+        self
+    }
+
+    #[inline]
+    pub fn trajectory(&self) -> Option<&Trajectory<T>> {
+        self.trajectory.as_ref()
+    }
+
+    /// Take the recorded trajectory, the recording continues with an empty one if enabled.
+    #[inline]
+    pub fn take_trajectory(&mut self) -> Option<Trajectory<T>> {
+        self.trajectory.as_mut().map(std::mem::take)
+    }
+
+    /// Enable the smoothing over the updates of the filter,
+    /// `lag` is the number of the latest updates kept for the fixed-lag smoothing, `None` keeps every update.
+    ///
+    /// See also [`LIO::smoothed_trajectory`] and [`LIO::pop_smoothed_trajectory`].
+    #[inline]
+    pub fn with_smoother(mut self, lag: Option<usize>) -> Self {
+        let smoother = match lag {
+            Some(lag) => Smoother::with_lag(lag),
+            None => Smoother::new(),
+        };
+        self.eskf.enable_smoother(smoother);
+        self
+    }
+}
+
+impl<T, S> LIO<T, S>
+where
+    T: RealField + ToRadians,
+    S: LioState<T>,
+    PoseState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
+{
+    /// Record the current state into the trajectory if the recording is enabled.
+    pub(crate) fn record_trajectory(&mut self) {
+        let Some(trajectory) = &mut self.trajectory else {
+            return;
+        };
+        let timestamp = self.eskf.last_update_time.predict.clone();
+        trajectory.push(trajectory_point(timestamp, &self.eskf));
+    }
+}
+
+impl<T, S> LIO<T, S>
+where
+    T: RealField + ToRadians,
+    S: LioState<T> + AddAssign<ErrorState<S>>,
+    PoseState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim> + Allocator<S::Dim>,
+{
+    /// Smooth every update kept by the smoother backward, `None` if the smoothing is disabled,
+    /// see also [`LIO::with_smoother`].
+    pub fn smoothed_trajectory(&self) -> Option<Trajectory<T>> {
+        let smoothed = self.eskf.smoother()?.smooth();
+        Some(smoothed.iter().map(smoothed_point).collect())
+    }
+
+    /// Pop the updates leaving the window of the fixed-lag smoother, which are smoothed by the window,
+    /// `None` if the smoothing is disabled, see also [`LIO::with_smoother`].
+    pub fn pop_smoothed_trajectory(&mut self) -> Option<Trajectory<T>> {
+        let smoothed = self.eskf.smoother_mut()?.pop_lagged();
+        Some(smoothed.iter().map(smoothed_point).collect())
+    }
+}
+
+fn smoothed_point<T, S>(smoothed: &SmoothedState<S>) -> TrajectoryPoint<T>
+where
+    T: RealField,
+    S: LioState<T>,
+    PoseState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
+{
+    trajectory_point(smoothed.timestamp.clone(), &smoothed.state)
+}
+
+fn trajectory_point<T, S>(timestamp: T, uncertained: &Uncertained<S>) -> TrajectoryPoint<T>
+where
+    T: RealField,
+    S: LioState<T>,
+    PoseState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
+{
+    let state = uncertained.state.as_ref();
+    let pose_cov = uncertained.cov.sub_covariance::<PoseState<T>>();
+    TrajectoryPoint {
+        timestamp,
+        pose: state.pose.0.clone(),
+        velocity: state.velocity.0.clone(),
+        pose_cov: collect_matrix6(pose_cov.iter()),
+    }
+}
+
+fn collect_matrix6<'a, T: RealField>(values: impl Iterator<Item = &'a T>) -> Matrix6<T> {
+    Matrix6::from_iterator(values.cloned())
 }
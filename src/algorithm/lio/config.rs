@@ -1,10 +1,15 @@
 use crate::{
+    eskf::IteratedConfig,
     frame::{Framed, IsometryFramed, frames},
     voxel_map,
 };
 
-use super::measurement::MeasureNoiseConfig;
-pub use super::predict::ProcessCovConfig as StateProcessCovConfig;
+pub use super::measurement::{DegeneracyConfig, StationaryConfig};
+pub use super::predict::{
+    ImuNoiseConfig, ImuPropagation, ProcessCovConfig as StateProcessCovConfig,
+};
+pub use super::time_offset::TimeOffsetConfig;
+use super::{measurement::MeasureNoiseConfig, relocalize::RelocalizeConfig};
 pub use crate::voxel_map::uncertain::body_point::ProcessCov as BodyPointProcessCov;
 
 use nalgebra::{IsometryMatrix3, RealField, Scalar, Translation3};
@@ -21,9 +26,20 @@ pub struct Config<T: Scalar, G = T> {
     /// The measurement noise configuration of the IMU and the lidar point(body frame).
     pub measure_noise: MeasureNoiseConfig<T>,
 
+    /// Whether the IMU measurements are observed or drive the prediction as the control input.
+    pub imu_propagation: ImuPropagation<T>,
+
+    /// The stationary detection over the IMU measurements, which observes the zero velocity when stationary,
+    /// `None` disables the detection.
+    pub stationary: Option<StationaryConfig<T>>,
+
     /// The extrinsics of the IMU to the body frame.
     pub extrinsics: IsometryFramed<T, fn(frames::Body) -> frames::Imu>,
 
+    /// The time offset of the lidar points to the IMU, the initial value and the noise if estimated,
+    /// see also [`LIO::new_time_offset_estimated`](super::LIO::new_time_offset_estimated).
+    pub time_offset: TimeOffsetConfig<T>,
+
     /// The gravity norm. Used to calculate the gravity factor (also known as gravity compensation)
     ///
     /// Note that this is optional, you can provide the gravity factor directly,
@@ -38,6 +54,18 @@ pub struct Config<T: Scalar, G = T> {
 
     /// The size of the processing buffer used to store the temporary transformed points.
     pub buffer_init_size: usize,
+
+    /// The iterated update configuration of the lidar points observation.
+    pub iterated_update: IteratedConfig<T>,
+
+    /// The degeneracy detection of the lidar points observation, and whether to constrain the update by it.
+    pub degeneracy: DegeneracyConfig<T>,
+
+    /// The maximum number of the IMU observed states kept for the points deskewing.
+    pub state_history_size: usize,
+
+    /// The registration configuration of [`LIO::relocalize`](super::LIO::relocalize).
+    pub relocalize: RelocalizeConfig<T>,
 }
 
 pub struct ProcessCovConfig<T> {
@@ -51,11 +79,18 @@ impl<T: RealField> Default for Config<T> {
         Self {
             process_cov: Default::default(),
             measure_noise: Default::default(),
+            imu_propagation: ImuPropagation::Observation,
+            stationary: None,
             extrinsics: Default::default(),
+            time_offset: Default::default(),
             downsample_resolution: voxel_map_config.voxel_size.clone(),
             voxel_map: voxel_map_config,
             gravity: nalgebra::convert(9.81),
             buffer_init_size: 80,
+            iterated_update: Default::default(),
+            degeneracy: Default::default(),
+            state_history_size: 200,
+            relocalize: Default::default(),
         }
     }
 }
@@ -81,11 +116,18 @@ impl<T: Scalar, G> Config<T, G> {
         let Self {
             process_cov,
             measure_noise,
+            imu_propagation,
+            stationary,
             extrinsics,
+            time_offset,
             gravity,
             voxel_map,
             downsample_resolution,
             buffer_init_size,
+            iterated_update,
+            degeneracy,
+            state_history_size,
+            relocalize,
         } = self;
         (
             gravity,
@@ -93,10 +135,17 @@ impl<T: Scalar, G> Config<T, G> {
                 gravity: NoGravity,
                 process_cov,
                 measure_noise,
+                imu_propagation,
+                stationary,
                 extrinsics,
+                time_offset,
                 voxel_map,
                 downsample_resolution,
                 buffer_init_size,
+                iterated_update,
+                degeneracy,
+                state_history_size,
+                relocalize,
             },
         )
     }
@@ -105,13 +154,18 @@ impl<T: Scalar, G> Config<T, G> {
 impl<T: RealField> Config<T> {
     #[inline]
     pub fn with_mid360_extrinsics(self) -> Self {
-        let extrinsics = IsometryMatrix3::from_parts(
-            Translation3::new(-0.011, -0.02329, 0.04412).cast(),
-            Default::default(),
-        );
         Self {
-            extrinsics: Framed::new(extrinsics),
+            extrinsics: mid360_extrinsics(),
             ..self
         }
     }
 }
+
+/// The extrinsics of the Livox Mid-360 built-in IMU.
+pub fn mid360_extrinsics<T: RealField>() -> IsometryFramed<T, fn(frames::Body) -> frames::Imu> {
+    let extrinsics = IsometryMatrix3::from_parts(
+        Translation3::new(-0.011, -0.02329, 0.04412).cast(),
+        Default::default(),
+    );
+    Framed::new(extrinsics)
+}
@@ -0,0 +1,114 @@
+//! Motion compensation of the lidar points, also known as scan deskewing.
+
+use std::{collections::VecDeque, ops::Deref};
+
+use nalgebra::{IsometryMatrix3, RealField};
+
+use crate::frame::{BodyPoint, IsometryFramed, frames};
+
+use super::state::State;
+
+/// The history of the states observed by the IMU, ordered by timestamp.
+pub type StateHistory<T> = VecDeque<(T, State<T>)>;
+
+/// Undistort the points of a scan into the body frame at the scan timestamp.
+pub struct Deskewer<'a, T: RealField> {
+    history: &'a StateHistory<T>,
+    timestamp: T,
+    /// The inverse of the predicted pose at the scan timestamp.
+    world_to_scan_imu: IsometryMatrix3<T>,
+    body_to_imu: &'a IsometryMatrix3<T>,
+    imu_to_body: IsometryMatrix3<T>,
+}
+
+impl<'a, T: RealField> Deskewer<'a, T> {
+    /// `scan_state` is the state predicted to the scan `timestamp`.
+    pub fn new(
+        history: &'a StateHistory<T>,
+        timestamp: T,
+        scan_state: &State<T>,
+        body_to_imu: &'a IsometryFramed<T, fn(frames::Body) -> frames::Imu>,
+    ) -> Self {
+        let scan_pose: &IsometryMatrix3<T> = scan_state.pose.deref();
+        Self {
+            history,
+            timestamp,
+            world_to_scan_imu: scan_pose.inverse(),
+            body_to_imu: body_to_imu.deref(),
+            imu_to_body: body_to_imu.inverse(),
+        }
+    }
+
+    /// Undistort the `point` captured at `time_offset` relative to the scan timestamp.
+    ///
+    /// The pose of the point is predicted from the latest state in the history before the point,
+    /// the point earlier than the whole history is clamped to the earliest state rather than
+    /// extrapolated backward. If the history is empty, the point is returned as is.
+    pub fn deskew(&self, point: BodyPoint<T>, time_offset: T) -> BodyPoint<T> {
+        let point_timestamp = self.timestamp.clone() + time_offset;
+
+        let index = self
+            .history
+            .partition_point(|(timestamp, _)| *timestamp <= point_timestamp);
+
+        let Some((timestamp, state)) = self.history.get(index.saturating_sub(1)) else {
+            return point;
+        };
+
+        let dt = (point_timestamp - timestamp.clone()).max(T::zero());
+        let point_pose = state.predict_pose(dt);
+
+        let point_to_scan =
+            &self.imu_to_body * &self.world_to_scan_imu * point_pose * self.body_to_imu;
+        BodyPoint::new(point_to_scan * point.deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Point3, Rotation3, Vector3};
+
+    use super::*;
+
+    /// The state at `timestamp` moving with the constant velocity in a fixed orientation.
+    fn state_at(timestamp: f64) -> State<f64> {
+        let mut state = State::default();
+        state.pose.0.rotation = Rotation3::new(Vector3::new(0.1, -0.2, 0.7));
+        state.velocity.0 = Vector3::new(2.0, -1.0, 0.5);
+        state.pose.0.translation.vector = state.pose.0.rotation * state.velocity.0 * timestamp;
+        state
+    }
+
+    /// The point in the body frame at `timestamp` observing the static `world_point`.
+    fn observe(world_point: &Point3<f64>, timestamp: f64) -> BodyPoint<f64> {
+        BodyPoint::new(
+            state_at(timestamp)
+                .pose
+                .0
+                .inverse_transform_point(world_point),
+        )
+    }
+
+    #[test]
+    fn test_deskew_constant_velocity() {
+        let history = [0.0, 0.04, 0.08]
+            .map(|timestamp| (timestamp, state_at(timestamp)))
+            .into();
+        let body_to_imu =
+            IsometryFramed::new_transform(IsometryMatrix3::identity(), frames::Body, frames::Imu);
+        let deskewer = Deskewer::new(&history, 0.1, &state_at(0.1), &body_to_imu);
+
+        let world_point = Point3::new(5.0, 1.0, -2.0);
+        let scan_point = observe(&world_point, 0.1);
+        for time_offset in [-0.1, -0.07, -0.04, -0.01, 0.0] {
+            let point = observe(&world_point, 0.1 + time_offset);
+            let deskewed = deskewer.deskew(point, time_offset);
+            assert!((deskewed.coords - scan_point.coords).amax() < 1e-9);
+        }
+
+        // the point earlier than the history is deskewed by the earliest state
+        let point = observe(&world_point, 0.0);
+        let deskewed = deskewer.deskew(point, -0.2);
+        assert!((deskewed.coords - scan_point.coords).amax() < 1e-9);
+    }
+}
@@ -67,7 +67,8 @@ pub trait Downsample<T: ComplexField, F>: Iterator<Item = FramedPoint<T, F>> + S
         resolution: &T,
         grid: &mut VoxelGrid<T, F>,
     ) -> impl Iterator<Item = FramedPoint<T, F>> {
-        // TODO: parallel optimizable
+        // serial even with the `rayon` feature, the grid insertion dominates
+        // and the barycenters depend on the order of the points
         self.for_each(|point| {
             let index = point.as_voxel_index(resolution.clone());
 
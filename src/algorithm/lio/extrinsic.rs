@@ -0,0 +1,395 @@
+//! The online calibration of the extrinsics of the IMU to the body frame,
+//! which are estimated as the [`ExtrinsicState`] along with the LIO [`State`](super::State).
+//!
+//! The lidar points observation is then w.r.t. both the pose and the extrinsics,
+//! see also [`LioState::observe_points`].
+
+use nalgebra::{DimName, Matrix6, OVector, RealField, Rotation3, Scalar, Vector3, Vector6, stack};
+use num_traits::Zero;
+use odometries_macros::{KFState, VectorAddAssign, sub_state_of};
+use simba::scalar::SupersetOf;
+
+use super::{
+    ImuInit, LIO,
+    config::{NoGravity, StateProcessCovConfig},
+    measurement::{
+        MeasureNoiseConfig, PointsObserveStats, PointsObserved, ProcessingPoint,
+        observe_point_rows, point_model,
+    },
+    state::LioState,
+};
+use crate::{
+    algorithm::lio,
+    eskf::{
+        Covariance, DeltaTime, Eskf, StatePredictor,
+        state::{StateDim, common::*, correlation::FullState, macro_export::*},
+        uncertain::Uncertained,
+    },
+    frame::{IsometryFramed, frames},
+    utils::ToRadians,
+    voxel_map::VoxelMap,
+};
+
+pub struct Extrinsic;
+/// The extrinsics of the IMU to the body frame, which are perturbed in the same way as the [`PoseState`].
+pub type ExtrinsicState<T> = IsometryState<T, fn(frames::Body) -> frames::Imu, Extrinsic>;
+pub type BodyToImu<T> = IsometryFramed<T, fn(frames::Body) -> frames::Imu>;
+
+/// The LIO state with the extrinsics calibrated online, see also [`LIO::new_calibrated`].
+#[derive(Clone, KFState, VectorAddAssign)]
+#[element(T)]
+#[vector_add_assign(predicates(RealField))]
+pub struct State<T>
+where
+    T: Scalar,
+{
+    pub state: lio::state::State<T>,
+    pub extrinsic: ExtrinsicState<T>,
+}
+
+type InnerState<T> = lio::state::State<T>;
+
+#[sub_state_of(State)]
+struct InnerState<T: Scalar>(
+    PoseState<T>,
+    VelocityState<T>,
+    GravityState<T>,
+    AccWithBiasState<T>,
+);
+
+#[sub_state_of(State)]
+struct PoseState<T: Scalar>(RotationState<T>, PositionState<T>);
+
+#[sub_state_of(State)]
+struct AccWithBiasState<T: Scalar>(AccState<T>, BiasState<T>);
+
+#[sub_state_of(State)]
+struct AccState<T: Scalar>(LinearAccState<T>, AngularAccState<T>);
+
+#[sub_state_of(State)]
+struct BiasState<T: Scalar>(LinearAccBiasState<T>, AngularAccBiasState<T>);
+
+/// The configuration of the calibrated LIO with no need to provide the gravity.
+pub type NoGravityConfig<T> = Config<T, NoGravity>;
+
+pub struct Config<T: Scalar, G = T> {
+    /// The configuration of the underlying LIO, whose extrinsics are the initial guess of the calibration.
+    pub lio: lio::Config<T, G>,
+
+    /// The process noise of the extrinsics, which drift slowly if any.
+    pub process_cov: ExtrinsicCovConfig<T>,
+
+    /// The initial variance of the extrinsics, larger for a rougher initial guess.
+    pub init_cov: ExtrinsicCovConfig<T>,
+}
+
+#[derive(Clone)]
+pub struct ExtrinsicCovConfig<T> {
+    pub rotation: T,
+    pub translation: T,
+}
+
+impl<T: RealField> Default for Config<T> {
+    fn default() -> Self {
+        Self {
+            lio: Default::default(),
+            process_cov: ExtrinsicCovConfig {
+                rotation: nalgebra::convert(1e-4),
+                translation: nalgebra::convert(1e-4),
+            },
+            init_cov: Default::default(),
+        }
+    }
+}
+
+impl<T: RealField> Default for NoGravityConfig<T> {
+    #[inline]
+    fn default() -> Self {
+        Config::<T>::default().take_gravity().1
+    }
+}
+
+impl<T: SupersetOf<f64>> Default for ExtrinsicCovConfig<T> {
+    fn default() -> Self {
+        Self {
+            rotation: nalgebra::convert(1e-3),
+            translation: nalgebra::convert(1e-2),
+        }
+    }
+}
+
+impl<T: Scalar, G> Config<T, G> {
+    pub fn take_gravity(self) -> (G, NoGravityConfig<T>) {
+        let Self {
+            lio,
+            process_cov,
+            init_cov,
+        } = self;
+        let (gravity, lio) = lio.take_gravity();
+        (
+            gravity,
+            NoGravityConfig {
+                lio,
+                process_cov,
+                init_cov,
+            },
+        )
+    }
+}
+
+impl<T> StatePredictor<T> for State<T>
+where
+    T: RealField,
+{
+    /// The extrinsics are constant during the prediction.
+    #[inline]
+    fn predict(&mut self, dt: T) {
+        self.state.predict(dt);
+    }
+}
+
+impl<T> StatePredictor<DeltaTime<T>> for Eskf<State<T>>
+where
+    T: RealField,
+{
+    fn predict(&mut self, dt: DeltaTime<T>) {
+        self.state.predict(dt.predict);
+        self.predict_cov(dt.observe);
+    }
+}
+
+impl<T: RealField> LioState<T> for State<T> {
+    type PointsState = FullState<Self>;
+
+    #[inline]
+    fn extrinsics(&self) -> Option<&BodyToImu<T>> {
+        Some(&self.extrinsic.0)
+    }
+
+    fn observe_points(
+        eskf: &Eskf<Self>,
+        map: &VoxelMap<T>,
+        measure_noise: &MeasureNoiseConfig<T>,
+        body_to_world: &IsometryFramed<T, fn(frames::Body) -> frames::World>,
+        points: &[ProcessingPoint<T>],
+        stats: &mut PointsObserveStats<T>,
+    ) -> Option<PointsObserved<T, Self>> {
+        let rotation = &eskf.state.state.pose.rotation;
+        let extrinsic_rotation = &eskf.state.extrinsic.rotation;
+        let pose_offset = SubStateOffset::<PoseState<T>, Self>::DIM;
+        let extrinsic_offset = SubStateOffset::<ExtrinsicState<T>, Self>::DIM;
+
+        let observation: PointsObserved<T, Self> = observe_point_rows(
+            points,
+            stats,
+            |(body_point, world_point, cross_matrix_imu), counts| {
+                let Uncertained {
+                    state: residual,
+                    cov: residual_cov,
+                } = map
+                    .get_or_nearest_residual_counted(world_point, counts)?
+                    .to_uncertained(body_point, body_to_world);
+                let plane_normal = residual.plane_normal();
+
+                let mut model = OVector::<T, StateDim<Self>>::zeros();
+                let pose_model = point_model(cross_matrix_imu, rotation, plane_normal);
+                model
+                    .fixed_rows_mut::<6>(pose_offset)
+                    .copy_from(&pose_model);
+                model
+                    .fixed_rows_mut::<6>(extrinsic_offset)
+                    .copy_from(&extrinsic_point_model(
+                        &body_point.coords,
+                        extrinsic_rotation,
+                        rotation,
+                        plane_normal,
+                    ));
+
+                let measurement = -residual.distance_to_plane;
+                let noise =
+                    measure_noise.lidar_point_noise(&measurement, residual_cov.to_scalar())?;
+
+                Some((measurement, model, noise, pose_model))
+            },
+        );
+
+        if observation.get_dim().0 == 0 {
+            return None;
+        }
+        Some(observation)
+    }
+}
+
+impl<T: Scalar> AsRef<InnerState<T>> for State<T> {
+    #[inline(always)]
+    fn as_ref(&self) -> &InnerState<T> {
+        &self.state
+    }
+}
+
+impl<T: Scalar> AsMut<InnerState<T>> for State<T> {
+    #[inline(always)]
+    fn as_mut(&mut self) -> &mut InnerState<T> {
+        &mut self.state
+    }
+}
+
+impl<T> Default for State<T>
+where
+    T: RealField,
+{
+    fn default() -> Self {
+        Self {
+            state: Default::default(),
+            extrinsic: Default::default(),
+        }
+    }
+}
+
+/// The observation model of the point-to-plane distance w.r.t. the [`ExtrinsicState`],
+/// i.e. `d = n' * (R * (R_e * p + t_e) + t)` with the extrinsics perturbed by `R_e * Exp(δθ)` and `R_e * δt`.
+fn extrinsic_point_model<T: RealField>(
+    body_point: &Vector3<T>,
+    extrinsic_rotation: &Rotation3<T>,
+    rotation: &Rotation3<T>,
+    plane_normal: &Vector3<T>,
+) -> Vector6<T> {
+    let rotation_t_normal = extrinsic_rotation.transpose() * (rotation.transpose() * plane_normal);
+    let cross_matrix_rotation_t_normal = body_point.cross_matrix() * &rotation_t_normal;
+
+    #[expect(clippy::toplevel_ref_arg)]
+    let model = stack![cross_matrix_rotation_t_normal; rotation_t_normal];
+    model
+}
+
+impl<T> From<(StateProcessCovConfig<T>, ExtrinsicCovConfig<T>)> for Covariance<State<T>>
+where
+    T: Scalar + Zero,
+{
+    fn from((lio, extrinsic): (StateProcessCovConfig<T>, ExtrinsicCovConfig<T>)) -> Self {
+        let mut cov: Self = lio.into();
+        set_extrinsic_cov(&mut cov, extrinsic);
+        cov
+    }
+}
+
+fn set_extrinsic_cov<T: Scalar + Zero>(
+    cov: &mut Covariance<State<T>>,
+    config: ExtrinsicCovConfig<T>,
+) {
+    let mut extrinsic_cov = cov.sub_covariance_mut::<ExtrinsicState<T>>();
+    (0..3).for_each(|i| {
+        extrinsic_cov[(i, i)] = config.rotation.clone();
+        extrinsic_cov[(i + 3, i + 3)] = config.translation.clone();
+    });
+}
+
+impl<T> ImuInit<T>
+where
+    T: RealField + ToRadians,
+{
+    pub fn new_calibrated_lio(self, config: Config<T>) -> LIO<T, State<T>> {
+        LIO::new_calibrated(config, self)
+    }
+}
+
+impl<T> LIO<T, State<T>>
+where
+    T: RealField + ToRadians,
+{
+    /// Create a new LIO instance calibrating the extrinsics online,
+    /// starting from the [`extrinsics`](lio::Config::extrinsics) in the LIO configuration.
+    pub fn new_calibrated(config: Config<T>, imu_init: ImuInit<T>) -> Self {
+        let (gravity, config) = config.take_gravity();
+        let gravity_factor = gravity / imu_init.linear_acc_norm.clone();
+
+        let mut lio = Self::new_calibrated_with_gravity_factor(
+            config,
+            imu_init.timestamp_init.clone(),
+            gravity_factor,
+        );
+        lio.init_with_imu(imu_init);
+        lio
+    }
+
+    /// Create a new LIO instance calibrating the extrinsics online with a given gravity factor.
+    ///
+    /// This does not need the `gravity` in [`Config<T>`], provide [`NoGravityConfig<T>`] instead.
+    pub fn new_calibrated_with_gravity_factor(
+        config: NoGravityConfig<T>,
+        timestamp_init: T,
+        gravity_factor: T,
+    ) -> Self {
+        let lio_process_cov = config
+            .lio
+            .imu_propagation
+            .state_process_cov(config.lio.process_cov.state.clone());
+        let process_cov = (lio_process_cov, config.process_cov).into();
+        let extrinsics = config.lio.extrinsics.clone();
+        let mut lio =
+            LIO::new_with_process_cov(config.lio, process_cov, timestamp_init, gravity_factor);
+
+        lio.eskf.state.extrinsic = ExtrinsicState::new(extrinsics);
+        set_extrinsic_cov(&mut lio.eskf.cov, config.init_cov);
+        lio
+    }
+
+    /// The calibrated extrinsics with their covariance,
+    /// the rotation comes first and then the translation.
+    pub fn calibrated_extrinsics(&self) -> Uncertained<ExtrinsicState<T>> {
+        let cov = self.eskf.cov.sub_covariance::<ExtrinsicState<T>>();
+        Uncertained::new_with_cov(
+            self.eskf.state.extrinsic.clone(),
+            Matrix6::from_iterator(cov.iter().cloned()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{IsometryMatrix3, Point3, Translation3};
+
+    use super::*;
+
+    #[test]
+    fn test_error_state_impl() {
+        type State = super::State<f64>;
+        assert_eq!(StateDim::<State>::DIM, 30);
+        assert_eq!(SubStateOffset::<PoseState<f64>, State>::DIM, 0);
+        assert_eq!(SubStateOffset::<AngularAccBiasState<f64>, State>::DIM, 21);
+        assert_eq!(SubStateOffset::<ExtrinsicState<f64>, State>::DIM, 24);
+    }
+
+    /// The distance of the perturbed extrinsics changes by `model * δ` to the first order.
+    #[test]
+    fn test_extrinsic_point_model() {
+        let extrinsic = IsometryMatrix3::from_parts(
+            Translation3::new(0.1, -0.05, 0.2),
+            Rotation3::new(Vector3::new(0.02, -0.3, 0.1)),
+        );
+        let pose = IsometryMatrix3::from_parts(
+            Translation3::new(1.0, 2.0, -1.0),
+            Rotation3::new(Vector3::new(-0.5, 0.2, 1.0)),
+        );
+        let body_point = Vector3::new(3.0, -1.0, 0.5);
+        let plane_normal = Vector3::new(1.0, 2.0, -2.0).normalize();
+        let distance = |extrinsic: &IsometryMatrix3<f64>| {
+            plane_normal.dot(&(pose * extrinsic * Point3::from(body_point)).coords)
+        };
+
+        let model = extrinsic_point_model(
+            &body_point,
+            &extrinsic.rotation,
+            &pose.rotation,
+            &plane_normal,
+        );
+
+        let error = Vector6::new(1e-4, -2e-4, 3e-4, -1e-4, 2e-4, 1e-4);
+        let mut perturbed = extrinsic;
+        perturbed *= Rotation3::new(error.fixed_rows::<3>(0).into_owned());
+        perturbed *= Translation3::from(error.fixed_rows::<3>(3).into_owned());
+
+        let delta = distance(&perturbed) - distance(&extrinsic);
+        assert!((delta - model.dot(&error)).abs() < 1e-6);
+    }
+}
@@ -1,20 +1,51 @@
+mod degeneracy;
+mod diagnostics;
 mod imu;
 mod points;
-
-use std::ops::{Deref, DerefMut};
-
-pub use imu::{ImuInit, ImuMeasured, StampedImu};
-use nalgebra::{RealField, Scalar};
-pub use points::{LidarPoint, PointsProcessBuffer, StampedPoints};
+mod relative_pose;
+mod robust;
+mod stationary;
+
+use std::ops::{AddAssign, Deref, DerefMut};
+
+pub use degeneracy::{Degeneracy, DegeneracyConfig};
+pub use diagnostics::{
+    InnovationStats, MapUpdate, PointsObserveStats, PointsUpdateReport, UpdateTiming,
+};
+pub use imu::{ImuInit, ImuMeasured, ImuObserved, StampedImu};
+use nalgebra::{DefaultAllocator, RealField, Scalar, U3, U6, allocator::Allocator};
+pub use points::{LidarPoint, PointsObserved, PointsProcessBuffer, ProcessingPoint, StampedPoints};
+pub(super) use points::{observe_point_rows, point_model};
+pub use robust::RobustKernel;
 use simba::scalar::SupersetOf;
-
-use crate::{eskf::state::common::AccState, utils::ToRadians};
-
-use super::LIO;
+pub use stationary::{
+    StationaryConfig, StationaryDetector, ZeroAngularRateObserved, ZeroVelocityObserved,
+};
+
+use crate::{
+    eskf::{
+        DeltaTime, ErrorState, Eskf, IteratedStateObserver, StateObserver, StatePredictor,
+        observe::ChiSquareGate,
+        state::{
+            SubStateOf,
+            common::{
+                AccState, AccWithBiasState, AngularAccState, BiasState, PoseState, PositionState,
+                RotationState, VelocityState,
+            },
+        },
+    },
+    utils::ToRadians,
+};
+
+use super::{LIO, state::LioState};
 
 pub struct MeasureNoiseConfig<T: Scalar> {
     pub imu_acc: AccState<T>,
     pub lidar_point: T,
+    /// The robust kernel re-weighting the noise of each lidar point by its point-to-plane residual.
+    pub lidar_kernel: RobustKernel<T>,
+    /// The outlier gate of the IMU measurement, e.g. to reject the IMU spikes.
+    pub imu_gate: Option<ChiSquareGate<T>>,
 }
 
 pub struct StampedMeasurement<T, M> {
@@ -37,13 +68,40 @@ where
                 nalgebra::convert(0.01),
             ),
             lidar_point: nalgebra::convert(10.0),
+            lidar_kernel: RobustKernel::Gaussian,
+            imu_gate: None,
         }
     }
 }
 
-impl<T> LIO<T>
+impl<T: RealField> MeasureNoiseConfig<T> {
+    /// The noise of the lidar point with the point-to-plane `residual` and its `residual_cov`,
+    /// re-weighted by the [`lidar_kernel`](Self::lidar_kernel), `None` if the point is rejected.
+    #[inline]
+    pub fn lidar_point_noise(&self, residual: &T, residual_cov: T) -> Option<T> {
+        self.lidar_kernel
+            .reweight(residual, self.lidar_point.clone() * residual_cov)
+    }
+}
+
+impl<T, S> LIO<T, S>
 where
     T: RealField + ToRadians,
+    S: LioState<T> + AddAssign<ErrorState<S>>,
+    PoseState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    RotationState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    PositionState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    AccWithBiasState<T>: SubStateOf<S, Element = T>,
+    AccState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    BiasState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    VelocityState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    AngularAccState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    Eskf<S>: StatePredictor<DeltaTime<T>>
+        + StateObserver<ImuObserved<T, S>>
+        + StateObserver<ZeroVelocityObserved<T, S>>
+        + StateObserver<ZeroAngularRateObserved<T, S>>
+        + IteratedStateObserver<S, PointsObserved<T, S>>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim> + Allocator<S::Dim>,
 {
     pub fn update_points_with_imus<P>(
         &mut self,
@@ -63,13 +121,15 @@ where
     ) where
         P: IntoIterator<Item: LidarPoint<T>>,
     {
-        let mut imus = imus.into_iter();
+        let mut imus = imus.into_iter().peekable();
 
         // TODO: could this be optimized by using `rayon`?
         point_clouds.into_iter().for_each(|points| {
-            let imus_before_points = imus
-                .by_ref()
-                .take_while(|imu_measured| imu_measured.timestamp < points.timestamp);
+            let timestamp = points.timestamp.clone() + self.time_offset().clone();
+            // the IMU measurement after the points is kept for the next point cloud
+            let imus_before_points = std::iter::from_fn(|| {
+                imus.next_if(|imu_measured| imu_measured.timestamp < timestamp)
+            });
             self.extend(imus_before_points);
             self.update_stamped_points(points);
         });
@@ -106,9 +166,24 @@ impl<T: Scalar> DerefMut for StampedImu<T> {
     }
 }
 
-impl<T, P> Extend<(StampedImu<T>, StampedPoints<T, P>)> for LIO<T>
+impl<T, S, P> Extend<(StampedImu<T>, StampedPoints<T, P>)> for LIO<T, S>
 where
     T: RealField + ToRadians,
+    S: LioState<T> + AddAssign<ErrorState<S>>,
+    PoseState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    RotationState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    PositionState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    AccWithBiasState<T>: SubStateOf<S, Element = T>,
+    AccState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    BiasState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    VelocityState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    AngularAccState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    Eskf<S>: StatePredictor<DeltaTime<T>>
+        + StateObserver<ImuObserved<T, S>>
+        + StateObserver<ZeroVelocityObserved<T, S>>
+        + StateObserver<ZeroAngularRateObserved<T, S>>
+        + IteratedStateObserver<S, PointsObserved<T, S>>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim> + Allocator<S::Dim>,
     P: IntoIterator<Item: LidarPoint<T>>,
 {
     fn extend<I>(&mut self, iter: I)
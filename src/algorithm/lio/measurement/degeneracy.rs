@@ -0,0 +1,119 @@
+//! The degeneracy detection of the lidar points observation, e.g. in the long corridors and tunnels,
+//! where the point-to-plane distances only constrain some directions of the pose.
+
+use nalgebra::{Matrix6, RealField, Scalar, Vector6, VectorView6};
+use simba::scalar::SupersetOf;
+
+#[derive(Debug, Clone)]
+pub struct DegeneracyConfig<T> {
+    /// The minimum eigenvalue of the [`pose_information`](super::PointsObserveStats::pose_information)
+    /// along a well-constrained direction, which grows with the number of the observed points
+    /// and shrinks with the [`lidar_point`](super::MeasureNoiseConfig::lidar_point) noise.
+    pub min_eigenvalue: T,
+    /// Whether to project the update of the pose onto the well-constrained directions,
+    /// also known as the solution remapping, otherwise the degeneracy is only reported.
+    pub project_update: bool,
+}
+
+impl<T: SupersetOf<f64>> Default for DegeneracyConfig<T> {
+    fn default() -> Self {
+        Self {
+            min_eigenvalue: nalgebra::convert(500.0),
+            project_update: false,
+        }
+    }
+}
+
+/// The eigen decomposition of the information matrix over the pose,
+/// the rotation comes first and then the position in the eigenvectors.
+#[derive(Debug, Clone)]
+pub struct Degeneracy<T: Scalar> {
+    /// The eigenvalues in ascending order.
+    pub eigenvalues: Vector6<T>,
+    /// The eigenvectors as the columns in the order of the eigenvalues.
+    pub eigenvectors: Matrix6<T>,
+    /// The number of the eigenvalues below the [`DegeneracyConfig::min_eigenvalue`],
+    /// whose eigenvectors are the degenerate directions.
+    pub degenerate: usize,
+}
+
+impl<T: RealField> Degeneracy<T> {
+    pub fn new(information: Matrix6<T>, min_eigenvalue: &T) -> Self {
+        let eigen = information.symmetric_eigen();
+        let mut order = [0, 1, 2, 3, 4, 5];
+        order.sort_by(|&a, &b| {
+            eigen.eigenvalues[a]
+                .partial_cmp(&eigen.eigenvalues[b])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let eigenvalues = Vector6::from_fn(|i, _| eigen.eigenvalues[order[i]].clone());
+        let eigenvectors = Matrix6::from_fn(|i, j| eigen.eigenvectors[(i, order[j])].clone());
+        let degenerate = eigenvalues
+            .iter()
+            .take_while(|eigenvalue| *eigenvalue < min_eigenvalue)
+            .count();
+        Self {
+            eigenvalues,
+            eigenvectors,
+            degenerate,
+        }
+    }
+
+    #[inline]
+    pub fn is_degenerate(&self) -> bool {
+        self.degenerate > 0
+    }
+
+    /// The directions in the pose error space which are poorly constrained by the observation.
+    pub fn degenerate_directions(&self) -> impl Iterator<Item = VectorView6<'_, T>> {
+        (0..self.degenerate).map(|i| self.eigenvectors.fixed_columns::<1>(i))
+    }
+
+    /// The projection onto the well-constrained directions.
+    pub fn projection(&self) -> Matrix6<T> {
+        let constrained = self
+            .eigenvectors
+            .columns(self.degenerate, 6 - self.degenerate);
+        let projection = &constrained * constrained.transpose();
+        Matrix6::from_iterator(projection.iter().cloned())
+    }
+}
+
+/// Project the 6 contiguous `values` of a pose error by the `projection`, see also [`Degeneracy::projection`].
+pub(super) fn project_pose<T: RealField>(projection: &Matrix6<T>, values: &mut [T]) {
+    let projected = projection * Vector6::from_column_slice(values);
+    values.clone_from_slice(projected.as_slice());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_degeneracy() {
+        // the planes of a corridor along the x axis don't constrain the x position
+        let normals = [
+            Vector6::<f64>::new(0.0, 0.0, 0.0, 0.0, 1.0, 0.0),
+            Vector6::new(0.0, 0.0, 0.0, 0.0, 0.0, 1.0),
+            Vector6::new(1.0, 0.0, 0.0, 0.0, 1.0, 0.0),
+            Vector6::new(0.0, 1.0, 0.0, 0.0, 0.0, 1.0),
+            Vector6::new(0.0, 0.0, 1.0, 0.0, 1.0, 0.0),
+        ];
+        let information = normals.iter().fold(Matrix6::zeros(), |sum, model| {
+            sum + model * model.transpose() * 1e3
+        });
+
+        let degeneracy = Degeneracy::new(information, &100.0);
+        assert_eq!(degeneracy.degenerate, 1);
+        let Some(direction) = degeneracy.degenerate_directions().next() else {
+            panic!("no degenerate direction");
+        };
+        assert!((direction[3].abs() - 1.0).abs() < 1e-9);
+
+        let mut error: [f64; 6] = [0.1, 0.2, 0.3, 0.4, 0.5, 0.6];
+        project_pose(&degeneracy.projection(), &mut error);
+        assert!(error[3].abs() < 1e-9);
+        assert!((error[4] - 0.5).abs() < 1e-9);
+    }
+}
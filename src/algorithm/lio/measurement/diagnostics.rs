@@ -0,0 +1,134 @@
+//! The diagnostics of the lidar points update, see also [`PointsUpdateReport`].
+
+use std::time::Duration;
+
+use nalgebra::{DVector, Matrix6, RealField, Scalar, Vector6};
+
+use super::degeneracy::Degeneracy;
+use crate::{eskf::IteratedReport, voxel_map::ResidualCounts};
+
+/// The diagnostics of an update of the lidar points, see also [`LIO::update_points`](crate::algorithm::lio::LIO::update_points).
+#[derive(Debug, Clone)]
+pub struct PointsUpdateReport<T: Scalar> {
+    /// The number of the input points.
+    pub input_points: usize,
+    /// The number of the points after the voxel grid downsampling.
+    pub downsampled_points: usize,
+    /// Where the residuals of the downsampled points are found at the last iteration.
+    pub residuals: ResidualCounts,
+    /// The statistics of the point-to-plane innovations at the last iteration, `None` if nothing is observed.
+    pub innovation: Option<InnovationStats<T>>,
+    /// The number of the iterations and the gated rows of the update, `None` if nothing is observed.
+    pub iterated: Option<IteratedReport>,
+    /// The degeneracy of the observation at the last iteration, `None` if nothing is observed.
+    pub degeneracy: Option<Degeneracy<T>>,
+    /// Which points are inserted into the map.
+    pub map_update: MapUpdate,
+    /// The number of the voxels in the map after the insertion and the sliding.
+    pub map_voxels: usize,
+    /// The number of the voxels removed by the sliding.
+    pub removed_voxels: usize,
+    /// The number of the voxels removed as seen through by the points,
+    /// see also [`voxel_map::Config::free_space`](crate::voxel_map::Config::free_space).
+    pub cleared_voxels: usize,
+    pub timing: UpdateTiming,
+}
+
+/// The statistics accumulated over the observed points, see also [`LioState::observe_points`](crate::algorithm::lio::state::LioState::observe_points).
+#[derive(Debug, Clone)]
+pub struct PointsObserveStats<T: Scalar> {
+    /// Where the residuals of the points are found.
+    pub residuals: ResidualCounts,
+    /// The information matrix `H' * R^-1 * H` of the observation over the pose,
+    /// see also [`Degeneracy`].
+    pub pose_information: Matrix6<T>,
+}
+
+impl<T: RealField> Default for PointsObserveStats<T> {
+    fn default() -> Self {
+        Self {
+            residuals: Default::default(),
+            pose_information: Matrix6::zeros(),
+        }
+    }
+}
+
+impl<T: RealField> PointsObserveStats<T> {
+    /// Accumulate the information of a point with its `pose_model` and measurement `noise`.
+    #[inline]
+    pub fn add_pose_information(&mut self, pose_model: &Vector6<T>, noise: &T) {
+        self.pose_information
+            .ger(noise.clone().recip(), pose_model, pose_model, T::one());
+    }
+}
+
+/// The points inserted into the map after the update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapUpdate {
+    /// The points are transformed by the updated state.
+    Corrected,
+    /// The points are transformed by the predicted state, since the update is not applied.
+    Predicted,
+}
+
+/// The processing time of each stage of the update.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UpdateTiming {
+    /// The deskewing, the downsampling and the transformation into the world frame.
+    pub preprocess: Duration,
+    /// The iterated update of the filter.
+    pub update: Duration,
+    /// The insertion of the points into the map and the sliding of the map.
+    pub map: Duration,
+}
+
+/// The statistics of the innovations of an observation.
+#[derive(Debug, Clone)]
+pub struct InnovationStats<T> {
+    /// The number of the innovations.
+    pub count: usize,
+    /// The root mean square of the innovations.
+    pub rms: T,
+    /// The maximum absolute value of the innovations.
+    pub max: T,
+    /// The mean of the squared innovations normalized by the measurement noise,
+    /// which is about `1` if the noise is consistent.
+    pub mean_normalized: T,
+}
+
+impl<T: RealField> InnovationStats<T> {
+    /// Returns `None` if there is no innovation.
+    pub fn new(innovation: &DVector<T>, noise: &DVector<T>) -> Option<Self> {
+        let count = innovation.len();
+        let len = T::from_usize(count).filter(|_| count > 0)?;
+        let normalized = innovation.zip_fold(noise, T::zero(), |sum, innovation, noise| {
+            sum + innovation.clone() * innovation / noise
+        });
+        Some(Self {
+            count,
+            rms: (innovation.norm_squared() / len.clone()).sqrt(),
+            max: innovation.amax(),
+            mean_normalized: normalized / len,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_innovation_stats() {
+        let innovation = DVector::from_vec(vec![0.1, -0.3, 0.2, 0.0]);
+        let noise = DVector::from_vec(vec![0.01, 0.09, 0.04, 1.0]);
+        let Some(stats) = InnovationStats::new(&innovation, &noise) else {
+            panic!("no innovation");
+        };
+        assert_eq!(stats.count, 4);
+        assert!((stats.rms - (0.14_f64 / 4.0).sqrt()).abs() < 1e-12);
+        assert!((stats.max - 0.3).abs() < 1e-12);
+        assert!((stats.mean_normalized - 0.75).abs() < 1e-12);
+
+        assert!(InnovationStats::new(&DVector::<f64>::zeros(0), &DVector::zeros(0)).is_none());
+    }
+}
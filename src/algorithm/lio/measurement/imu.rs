@@ -1,56 +1,90 @@
 mod init;
-use std::ops::Deref;
+use std::ops::{AddAssign, Deref};
 
-use nalgebra::{RealField, Scalar, stack};
+use nalgebra::{DefaultAllocator, RealField, Scalar, U3, U6, Vector6, allocator::Allocator, stack};
 use num_traits::Zero;
 
 use crate::{
-    algorithm::lio::state::State,
+    algorithm::lio::{
+        predict::ImuPropagation,
+        state::{LioState, State},
+    },
     eskf::{
-        Eskf,
+        DeltaTime, ErrorState, Eskf, StateObserver, StatePredictor,
         observe::NoModelObservation,
-        state::common::{AccState, AccWithBiasState},
+        state::{
+            KFState, SubStateOf,
+            common::{AccState, AccWithBiasState, AngularAccState, BiasState, VelocityState},
+        },
     },
     utils::ToRadians,
 };
 
-use super::{LIO, StampedMeasurement};
+use super::{
+    LIO, MeasureNoiseConfig, StampedMeasurement, ZeroAngularRateObserved, ZeroVelocityObserved,
+};
 pub use init::ImuInit;
 
-pub type ImuObserved<T> = NoModelObservation<AccWithBiasState<T>, State<T>>;
+pub type ImuObserved<T, S = State<T>> = NoModelObservation<AccWithBiasState<T>, S>;
 pub type ImuMeasured<T> = AccState<T>;
 pub type StampedImu<T> = StampedMeasurement<T, ImuMeasured<T>>;
 
-impl<T> Eskf<State<T>>
+impl<T, S> Eskf<S>
 where
     T: RealField + ToRadians,
+    S: KFState<Element = T> + LioState<T>,
+    AccWithBiasState<T>: SubStateOf<S, Element = T>,
+    AccState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    BiasState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
 {
-    fn observe_imu(
+    pub(crate) fn observe_imu(
         &self,
         gravity_factor: T,
-        measure_noise: &AccState<T>,
+        measure_noise: &MeasureNoiseConfig<T>,
         imu_acc: &ImuMeasured<T>,
-    ) -> ImuObserved<T> {
-        let AccWithBiasState {
-            acc: state_acc,
-            bias: state_acc_bias,
-        } = &self.state.acc_with_bias;
+    ) -> ImuObserved<T, S> {
+        let (measurement, noise) = imu_residual(
+            &self.state.as_ref().acc_with_bias,
+            gravity_factor,
+            &measure_noise.imu_acc,
+            imu_acc,
+        );
+        let observation = ImuObserved::new_no_model(measurement, noise);
+        match &measure_noise.imu_gate {
+            Some(gate) => observation.with_gate(gate.clone()),
+            None => observation,
+        }
+    }
+}
 
-        let measured_linear_acc = imu_acc.linear.deref() * gravity_factor
-            - state_acc.linear.deref()
-            - state_acc_bias.linear.deref();
+/// The residual of the IMU measurement against the estimated accelerations with bias,
+/// and the corresponding measurement noise.
+fn imu_residual<T: RealField>(
+    acc_with_bias: &AccWithBiasState<T>,
+    gravity_factor: T,
+    measure_noise: &AccState<T>,
+    imu_acc: &ImuMeasured<T>,
+) -> (Vector6<T>, Vector6<T>) {
+    let AccWithBiasState {
+        acc: state_acc,
+        bias: state_acc_bias,
+    } = acc_with_bias;
 
-        let measured_angular_acc =
-            imu_acc.angular.deref() - state_acc.angular.deref() - state_acc_bias.angular.deref();
+    let measured_linear_acc = imu_acc.linear.deref() * gravity_factor
+        - state_acc.linear.deref()
+        - state_acc_bias.linear.deref();
 
-        #[expect(clippy::toplevel_ref_arg)]
-        let measurement = stack![measured_linear_acc; measured_angular_acc];
+    let measured_angular_acc =
+        imu_acc.angular.deref() - state_acc.angular.deref() - state_acc_bias.angular.deref();
 
-        #[expect(clippy::toplevel_ref_arg)]
-        let noise = stack![measure_noise.linear; measure_noise.angular];
+    #[expect(clippy::toplevel_ref_arg)]
+    let measurement = stack![measured_linear_acc; measured_angular_acc];
 
-        ImuObserved::new_no_model(measurement, noise)
-    }
+    #[expect(clippy::toplevel_ref_arg)]
+    let noise = stack![measure_noise.linear; measure_noise.angular];
+
+    (measurement, noise)
 }
 
 impl<T: Scalar + Zero> StampedImu<T> {
@@ -62,22 +96,84 @@ impl<T: Scalar + Zero> StampedImu<T> {
     }
 }
 
-impl<T> Extend<StampedImu<T>> for LIO<T>
+impl<T, S> Extend<StampedImu<T>> for LIO<T, S>
 where
     T: RealField + ToRadians,
+    S: LioState<T> + AddAssign<ErrorState<S>>,
+    AccWithBiasState<T>: SubStateOf<S, Element = T>,
+    AccState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    BiasState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    VelocityState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    AngularAccState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    Eskf<S>: StatePredictor<DeltaTime<T>>
+        + StateObserver<ImuObserved<T, S>>
+        + StateObserver<ZeroVelocityObserved<T, S>>
+        + StateObserver<ZeroAngularRateObserved<T, S>>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim> + Allocator<S::Dim>,
 {
     fn extend<I>(&mut self, imus: I)
     where
         I: IntoIterator<Item = StampedImu<T>>,
     {
         imus.into_iter().for_each(|imu| {
-            self.eskf.update(imu.timestamp, |eskf| {
-                Some(eskf.observe_imu(
-                    self.gravity_factor.clone(),
-                    &self.measure_noise.imu_acc,
-                    &imu.measured,
-                ))
-            });
+            match &self.imu_propagation {
+                ImuPropagation::Observation => {
+                    self.eskf.update(imu.timestamp.clone(), |eskf| {
+                        Some(eskf.observe_imu(
+                            self.gravity_factor.clone(),
+                            &self.measure_noise,
+                            &imu.measured,
+                        ))
+                    });
+                }
+                ImuPropagation::ControlInput(noise) => {
+                    let sample_period = self
+                        .last_imu_timestamp
+                        .clone()
+                        .map(|last| imu.timestamp.clone() - last);
+                    self.eskf.propagate_imu(
+                        imu.timestamp.clone(),
+                        sample_period,
+                        self.gravity_factor.clone(),
+                        noise,
+                        &imu.measured,
+                    )
+                }
+            }
+            self.last_imu_timestamp = Some(imu.timestamp.clone());
+            self.update_stationary(&imu);
+            self.record_state(imu.timestamp);
         })
     }
 }
+
+impl<T, S> LIO<T, S>
+where
+    T: RealField + ToRadians,
+    S: LioState<T> + AddAssign<ErrorState<S>>,
+    VelocityState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    AngularAccState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    Eskf<S>: StatePredictor<DeltaTime<T>>
+        + StateObserver<ZeroVelocityObserved<T, S>>
+        + StateObserver<ZeroAngularRateObserved<T, S>>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim> + Allocator<S::Dim>,
+{
+    /// Test the stationary with the latest `imu`, and observe the zero velocity if stationary.
+    fn update_stationary(&mut self, imu: &StampedImu<T>) {
+        let Some(detector) = &mut self.stationary else {
+            return;
+        };
+        if !detector.push(&imu.measured) {
+            return;
+        }
+        let config = &detector.config;
+        self.eskf.update(imu.timestamp.clone(), |eskf| {
+            Some(eskf.observe_zero_velocity(&config.velocity_noise))
+        });
+        if let Some(noise) = &config.angular_rate_noise {
+            self.eskf.update(imu.timestamp.clone(), |eskf| {
+                Some(eskf.observe_zero_angular_rate(noise))
+            });
+        }
+    }
+}
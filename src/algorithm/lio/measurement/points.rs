@@ -1,25 +1,79 @@
-use std::ops::Deref;
+use std::{
+    ops::{AddAssign, Deref},
+    time::Instant,
+};
 
-use nalgebra::{Dyn, Point3, RealField, Scalar, Vector3, stack};
+use nalgebra::{
+    DefaultAllocator, DimName, Dyn, Point3, RealField, Rotation3, Scalar, U3, U6, Vector3, Vector6,
+    allocator::Allocator, stack,
+};
 
 use crate::{
-    algorithm::lio::{downsample::Downsample, state::State},
-    eskf::{Eskf, observe::UnbiasedObservation, state::common::PoseState, uncertain::Uncertained},
+    algorithm::lio::{
+        deskew::Deskewer,
+        downsample::Downsample,
+        state::{LioState, State},
+    },
+    eskf::{
+        DeltaTime, ErrorState, Eskf, IteratedStateObserver, StatePredictor,
+        observe::{Observation, UnbiasedObservation},
+        state::{
+            KFState, SubStateOf, SubStateOffset,
+            common::{PoseState, PositionState, RotationState},
+        },
+        uncertain::Uncertained,
+    },
     frame::{BodyPoint, CrossMatrixFramed, Framed, IsometryFramed, frames},
-    utils::{CollectTo, ToRadians},
+    utils::{ToRadians, maybe_par_iter},
     voxel_map::{
-        VoxelMap,
+        ResidualCounts, VoxelMap,
         uncertain::{UncertainBodyPoint, UncertainWorldPoint},
     },
 };
 
-use super::{LIO, StampedMeasurement};
+#[cfg(feature = "rayon")]
+use rayon::iter::{ParallelDrainRange, ParallelIterator};
+
+#[cfg(not(feature = "rayon"))]
+use crate::utils::CollectTo;
+#[cfg(feature = "rayon")]
+use crate::utils::ParCollectTo;
+
+use super::{
+    LIO, MeasureNoiseConfig, StampedMeasurement,
+    degeneracy::{Degeneracy, DegeneracyConfig, project_pose},
+    diagnostics::{
+        InnovationStats, MapUpdate, PointsObserveStats, PointsUpdateReport, UpdateTiming,
+    },
+};
 
 pub type StampedPoints<T, P> = StampedMeasurement<T, P>;
-pub type PointsObserved<T> = UnbiasedObservation<PoseState<T>, State<T>, Dyn>;
+pub type PointsObserved<T, S = State<T>> = Observation<<S as LioState<T>>::PointsState, S, Dyn>;
 
 pub trait LidarPoint<T: Scalar>: Clone {
     fn to_body_point(self) -> BodyPoint<T>;
+
+    /// The capture time of the point relative to the timestamp of the scan,
+    /// the point will be undistorted into the body frame at the scan timestamp.
+    ///
+    /// `None` means the point is captured at the scan timestamp.
+    #[inline]
+    fn time_offset(&self) -> Option<T> {
+        None
+    }
+}
+
+/// A lidar point with its time offset, see also [`LidarPoint::time_offset`].
+impl<T: Scalar, P: LidarPoint<T>> LidarPoint<T> for (P, T) {
+    #[inline]
+    fn to_body_point(self) -> BodyPoint<T> {
+        self.0.to_body_point()
+    }
+
+    #[inline]
+    fn time_offset(&self) -> Option<T> {
+        Some(self.1.clone())
+    }
 }
 
 impl<T: Scalar> LidarPoint<T> for [T; 3] {
@@ -53,137 +107,275 @@ impl<T: Scalar> LidarPoint<T> for BodyPoint<T> {
     }
 }
 
-pub type PointsProcessBuffer<T> = Vec<(
+/// A lidar point being processed, with its world point and the cross matrix of its IMU point.
+pub type ProcessingPoint<T> = (
     UncertainBodyPoint<T>,
     UncertainWorldPoint<T>,
     CrossMatrixFramed<T, frames::Imu>,
-)>;
+);
 
-impl<T> LIO<T>
+pub type PointsProcessBuffer<T> = Vec<ProcessingPoint<T>>;
+
+impl<T, S> LIO<T, S>
 where
     T: RealField + ToRadians,
+    S: LioState<T> + AddAssign<ErrorState<S>>,
+    PoseState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    RotationState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    PositionState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    Eskf<S>: StatePredictor<DeltaTime<T>> + IteratedStateObserver<S, PointsObserved<T, S>>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim> + Allocator<S::Dim>,
 {
     #[doc(alias = "update_points")]
     pub fn update_stamped_points(
         &mut self,
         stamped_points: StampedPoints<T, impl IntoIterator<Item = impl LidarPoint<T>>>,
-    ) {
+    ) -> PointsUpdateReport<T> {
         self.update_points(stamped_points.timestamp, stamped_points.measured)
     }
 
+    /// Update the state with the lidar `points` captured at `timestamp`,
+    /// which is shifted by the [`time_offset`](LIO::time_offset) onto the IMU clock.
+    ///
+    /// Returns the diagnostics of the update.
     pub fn update_points(
         &mut self,
         timestamp: T,
         points: impl IntoIterator<Item = impl LidarPoint<T>>,
-    ) {
-        let body_to_imu = &self.extrinsics;
-        let imu_to_world = self.eskf.pose.deref();
-        let body_to_world = body_to_imu * imu_to_world;
+    ) -> PointsUpdateReport<T> {
+        let start = Instant::now();
+        let timestamp = timestamp + self.time_offset().clone();
+        let body_to_imu = self.extrinsics().clone();
+        let body_to_imu = &body_to_imu;
 
         debug_assert_eq!(self.points_process_buffer.len(), 0);
 
-        // TODO: parallel optimizable
-        points
+        let mut scan_state = self.eskf.state.clone();
+        scan_state.predict(timestamp.clone() - self.eskf.last_update_time.predict.clone());
+        let body_to_world = body_to_imu * scan_state.as_ref().pose.deref();
+        let deskewer = Deskewer::new(
+            &self.state_history,
+            timestamp.clone(),
+            scan_state.as_ref(),
+            body_to_imu,
+        );
+
+        let body_point_process_cov = &self.body_point_process_cov;
+
+        let mut input_points = 0;
+        let downsampled = points
             .into_iter()
-            .map(LidarPoint::to_body_point)
-            .voxel_grid_downsample(&self.downsampler.resolution, &mut self.downsampler.grid)
-            .map(|body_point| {
-                UncertainBodyPoint::from_body_point(body_point, self.body_point_process_cov.clone())
+            .inspect(|_| input_points += 1)
+            .map(|point| {
+                let time_offset = point.time_offset();
+                let body_point = point.to_body_point();
+                match time_offset {
+                    Some(time_offset) => deskewer.deskew(body_point, time_offset),
+                    None => body_point,
+                }
             })
+            .voxel_grid_downsample(&self.downsampler.resolution, &mut self.downsampler.grid);
+        #[cfg(feature = "rayon")]
+        let downsampled = downsampled.collect::<Vec<_>>();
+        maybe_par_iter!(downsampled)
             .map(|body_point| {
+                let body_point =
+                    UncertainBodyPoint::from_body_point(body_point, body_point_process_cov.clone());
                 let imu_point = body_point.deref() * body_to_imu;
                 let cross_matrix_imu = Framed::new(imu_point.coords.cross_matrix());
-
-                let world_point = UncertainWorldPoint::from_uncertain_body_point(
-                    body_point.clone(),
-                    imu_to_world,
-                    &body_to_world,
-                    cross_matrix_imu.as_ref(),
-                    &self.eskf.cov,
-                );
+                // the covariance in the world frame is filled by the first iteration
+                let world_point = UncertainWorldPoint::new(body_point.deref() * &body_to_world);
                 (body_point, world_point, cross_matrix_imu)
             })
             .collect_to(&mut self.points_process_buffer);
+        let downsampled_points = self.points_process_buffer.len();
+        let preprocessed = Instant::now();
 
-        let is_updated = self
-            .eskf
-            .update(timestamp, |eskf| {
-                eskf.observe_points(
+        let points_process_buffer = &mut self.points_process_buffer;
+        let extrinsics = &self.extrinsics;
+        let degeneracy_config = &self.degeneracy;
+        let mut residuals = Default::default();
+        let mut innovation = None;
+        let mut degeneracy = None;
+        let iterated = self.eskf.update_iterated_constrained(
+            timestamp,
+            &self.iterated_update,
+            |eskf, _| {
+                let body_to_imu = eskf.state.extrinsics().unwrap_or(extrinsics);
+                let imu_to_world = eskf.state.as_ref().pose.deref();
+                let body_to_world = body_to_imu * imu_to_world;
+                let is_calibrated = eskf.state.extrinsics().is_some();
+                let rot_cov = eskf.cov.sub_covariance::<RotationState<T>>().into_owned();
+                let pos_cov = eskf.cov.sub_covariance::<PositionState<T>>().into_owned();
+                // re-linearize the world points around the predicted state on the first iteration,
+                // and around the updated state on the others
+                maybe_par_iter!(&mut **points_process_buffer).for_each(
+                    |(body_point, world_point, cross_matrix_imu)| {
+                        if is_calibrated {
+                            let imu_point = (*body_point).deref() * body_to_imu;
+                            *cross_matrix_imu = Framed::new(imu_point.coords.cross_matrix());
+                        }
+                        *world_point = UncertainWorldPoint::from_uncertain_body_point_with_cov(
+                            body_point.clone(),
+                            imu_to_world,
+                            &body_to_world,
+                            cross_matrix_imu.as_ref(),
+                            &rot_cov,
+                            &pos_cov,
+                        );
+                    },
+                );
+                // the diagnostics of the last iteration are kept
+                let mut stats = PointsObserveStats::default();
+                let observation = S::observe_points(
+                    eskf,
                     &self.map,
-                    &self.measure_noise.lidar_point,
+                    &self.measure_noise,
                     &body_to_world,
-                    self.points_process_buffer.iter(),
-                )
-            })
-            .is_some();
+                    points_process_buffer,
+                    &mut stats,
+                );
+                residuals = stats.residuals;
+                innovation = observation.as_ref().and_then(|observation| {
+                    InnovationStats::new(&observation.measurement, &observation.noise)
+                });
+                let observation = observation?;
+
+                let DegeneracyConfig {
+                    min_eigenvalue,
+                    project_update,
+                } = degeneracy_config;
+                let current = Degeneracy::new(stats.pose_information, min_eigenvalue);
+                let projection =
+                    (*project_update && current.is_degenerate()).then(|| current.projection());
+                degeneracy = Some(current);
+                Some((observation, projection))
+            },
+            |projection, error| {
+                // only update the pose in the well-constrained directions
+                if let Some(projection) = projection {
+                    let offset = SubStateOffset::<PoseState<T>, S>::DIM;
+                    project_pose(projection, &mut error[offset..offset + 6]);
+                }
+            },
+        );
+        let updated = Instant::now();
 
+        #[cfg(not(feature = "rayon"))]
         let processing_points = self.points_process_buffer.drain(..);
+        #[cfg(feature = "rayon")]
+        let processing_points = self.points_process_buffer.par_drain(..);
 
-        if is_updated {
-            // TODO: parallel optimizable
-            // re-compute the world points based on the updated state
-            processing_points
-                .map(|(body_point, _, cross_matrix_imu)| {
-                    UncertainWorldPoint::from_uncertain_body_point(
-                        body_point,
-                        self.eskf.pose.deref(),
-                        &body_to_world,
-                        cross_matrix_imu.as_ref(),
-                        &self.eskf.cov,
-                    )
-                })
-                .collect_to(&mut self.map);
+        let is_updated = iterated.is_some_and(|iterated| !iterated.observe.is_rejected());
+        // re-compute the world points based on the updated state,
+        // or the predicted one if the update is not applied, which initializes the map with the first scan
+        let body_to_imu = self.eskf.state.extrinsics().unwrap_or(&self.extrinsics);
+        let imu_to_world = self.eskf.state.as_ref().pose.deref();
+        let body_to_world = body_to_imu * imu_to_world;
+        let is_calibrated = self.eskf.state.extrinsics().is_some();
+        let origin = &BodyPoint::new(Point3::origin()) * &body_to_world;
+        let rot_cov = self
+            .eskf
+            .cov
+            .sub_covariance::<RotationState<T>>()
+            .into_owned();
+        let pos_cov = self
+            .eskf
+            .cov
+            .sub_covariance::<PositionState<T>>()
+            .into_owned();
+        let points = processing_points.map(|(body_point, _, mut cross_matrix_imu)| {
+            if is_calibrated {
+                let imu_point = body_point.deref() * body_to_imu;
+                cross_matrix_imu = Framed::new(imu_point.coords.cross_matrix());
+            }
+            UncertainWorldPoint::from_uncertain_body_point_with_cov(
+                body_point,
+                imu_to_world,
+                &body_to_world,
+                cross_matrix_imu.as_ref(),
+                &rot_cov,
+                &pos_cov,
+            )
+        });
+        #[cfg(feature = "rayon")]
+        let points = points.collect::<Vec<_>>();
+        let cleared_voxels = self.map.insert_scan(&origin, points);
+        let map_update = if is_updated {
+            MapUpdate::Corrected
         } else {
-            // TODO: parallel optimizable
-            processing_points
-                .map(|(_, world_point, _)| world_point)
-                .collect_to(&mut self.map);
+            MapUpdate::Predicted
         };
+
+        let position = self.position();
+        let removed_voxels = self.map.slide(&position);
+        self.map.publish();
+        let inserted = Instant::now();
+
+        // the updated state is the start of the next scan
+        self.state_history.clear();
+        self.record_state(self.eskf.last_update_time.predict.clone());
+        self.record_trajectory();
+
+        PointsUpdateReport {
+            input_points,
+            downsampled_points,
+            residuals,
+            innovation,
+            iterated,
+            degeneracy,
+            map_update,
+            map_voxels: self.map.len(),
+            removed_voxels,
+            cleared_voxels,
+            timing: UpdateTiming {
+                preprocess: preprocessed - start,
+                update: updated - preprocessed,
+                map: inserted - updated,
+            },
+        }
     }
 }
 
-impl<T> Eskf<State<T>>
+impl<T, S> Eskf<S>
 where
-    T: RealField + ToRadians,
+    T: RealField,
+    S: KFState<Element = T> + LioState<T>,
+    PoseState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
 {
-    fn observe_points<'a>(
+    /// Observe the point-to-plane distances w.r.t. the [`PoseState`] with the fixed extrinsics,
+    /// see also [`LioState::observe_points`].
+    pub(crate) fn observe_pose_points(
         &self,
         map: &VoxelMap<T>,
-        measure_noise: &T,
+        measure_noise: &MeasureNoiseConfig<T>,
         body_to_world: &IsometryFramed<T, fn(frames::Body) -> frames::World>,
-        points: impl IntoIterator<
-            Item = &'a (
-                UncertainBodyPoint<T>,
-                UncertainWorldPoint<T>,
-                CrossMatrixFramed<T, frames::Imu>,
-            ),
-        >,
-    ) -> Option<PointsObserved<T>> {
-        // TODO: could this be optimized by using `rayon`?
-        let observation = points
-            .into_iter()
-            .filter_map(|(body_point, world_point, cross_matrix_imu)| {
+        points: &[ProcessingPoint<T>],
+        stats: &mut PointsObserveStats<T>,
+    ) -> Option<UnbiasedObservation<PoseState<T>, S, Dyn>> {
+        let rotation = &self.state.as_ref().pose.rotation;
+        let observation: UnbiasedObservation<PoseState<T>, S, Dyn> = observe_point_rows(
+            points,
+            stats,
+            |(body_point, world_point, cross_matrix_imu), counts| {
                 let Uncertained {
                     state: residual,
                     cov: residual_cov,
                 } = map
-                    .get_or_nearest_residual(world_point)?
+                    .get_or_nearest_residual_counted(world_point, counts)?
                     .to_uncertained(body_point, body_to_world);
 
-                let plane_normal = residual.plane_normal();
-                let cross_matrix_rotation_t_normal =
-                    cross_matrix_imu.deref() * self.pose.rotation.transpose() * plane_normal;
-
-                #[expect(clippy::toplevel_ref_arg)]
-                let model = stack![cross_matrix_rotation_t_normal; plane_normal];
+                let model = point_model(cross_matrix_imu, rotation, residual.plane_normal());
 
                 let measurement = -residual.distance_to_plane;
 
-                let noise = measure_noise.clone() * residual_cov.to_scalar();
+                let noise =
+                    measure_noise.lidar_point_noise(&measurement, residual_cov.to_scalar())?;
 
-                Some((measurement, model, noise))
-            })
-            .collect::<PointsObserved<T>>();
+                Some((measurement, model.clone(), noise, model))
+            },
+        );
 
         if observation.get_dim().0 == 0 {
             return None;
@@ -192,9 +384,63 @@ where
     }
 }
 
-impl<T, P> Extend<StampedPoints<T, P>> for LIO<T>
+/// A row of the points observation, the measurement, the model and the noise,
+/// followed by the model w.r.t. the [`PoseState`] for the [`PointsObserveStats`].
+pub(in crate::algorithm::lio) type PointRow<T, M> = (T, M, T, Vector6<T>);
+
+/// Observe each of the `points` by `observe`, in parallel with the `rayon` feature,
+/// and collect the rows with the statistics in the order of the points,
+/// so that the observation is bit-identical to the serial one.
+pub(in crate::algorithm::lio) fn observe_point_rows<T, M, O>(
+    points: &[ProcessingPoint<T>],
+    stats: &mut PointsObserveStats<T>,
+    observe: impl Fn(&ProcessingPoint<T>, &mut ResidualCounts) -> Option<PointRow<T, M>> + Sync + Send,
+) -> O
+where
+    T: RealField,
+    M: Send,
+    O: FromIterator<(T, M, T)>,
+{
+    let rows = maybe_par_iter!(points).map(|point| {
+        let mut counts = ResidualCounts::default();
+        let row = observe(point, &mut counts);
+        (row, counts)
+    });
+    #[cfg(feature = "rayon")]
+    let rows = rows.collect::<Vec<_>>();
+    rows.into_iter()
+        .filter_map(|(row, counts)| {
+            stats.residuals += counts;
+            let (measurement, model, noise, pose_model) = row?;
+            stats.add_pose_information(&pose_model, &noise);
+            Some((measurement, model, noise))
+        })
+        .collect()
+}
+
+/// The observation model of the point-to-plane distance w.r.t. the [`PoseState`].
+pub(in crate::algorithm::lio) fn point_model<T: RealField>(
+    cross_matrix_imu: &CrossMatrixFramed<T, frames::Imu>,
+    rotation: &Rotation3<T>,
+    plane_normal: &Vector3<T>,
+) -> Vector6<T> {
+    let cross_matrix_rotation_t_normal =
+        cross_matrix_imu.deref() * rotation.transpose() * plane_normal;
+
+    #[expect(clippy::toplevel_ref_arg)]
+    let model = stack![cross_matrix_rotation_t_normal; plane_normal];
+    model
+}
+
+impl<T, S, P> Extend<StampedPoints<T, P>> for LIO<T, S>
 where
     T: RealField + ToRadians,
+    S: LioState<T> + AddAssign<ErrorState<S>>,
+    PoseState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    RotationState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    PositionState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    Eskf<S>: StatePredictor<DeltaTime<T>> + IteratedStateObserver<S, PointsObserved<T, S>>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim> + Allocator<S::Dim>,
     P: IntoIterator<Item: LidarPoint<T>>,
 {
     fn extend<I>(&mut self, iter: I)
@@ -207,3 +453,143 @@ where
         });
     }
 }
+
+#[cfg(all(test, feature = "rayon"))]
+mod tests {
+    use nalgebra::{Matrix3, Vector3};
+
+    use super::*;
+    use crate::{
+        algorithm::lio::{Config, ImuInit, ImuMeasured, StampedImu},
+        frame::WorldPoint,
+    };
+
+    fn imu(i: usize) -> StampedImu<f64> {
+        StampedImu::new(
+            i as f64 * 0.01,
+            ImuMeasured::new(0.0, 0.0, 9.81, 0.0, 0.0, 0.0),
+        )
+    }
+
+    /// The points on the floor and the walls of a room around the origin,
+    /// jittered differently in each `scan`.
+    fn room(scan: usize) -> Vec<Vector3<f64>> {
+        let grid = |i: usize| (i as f64 - 5.0) * 0.1;
+        itertools::iproduct!(0..10, 0..10)
+            .flat_map(|(i, j)| {
+                let jitter = ((i * 10 + j + scan * 7) as f64 * 12.9898).sin() * 0.01;
+                [
+                    Vector3::new(grid(i), grid(j), -0.7 + jitter),
+                    Vector3::new(0.7 + jitter, grid(i), grid(j)),
+                    Vector3::new(grid(i), -0.7 + jitter, grid(j)),
+                ]
+            })
+            .collect()
+    }
+
+    /// The observation rows and the statistics are the same as observing the points one by one.
+    #[test]
+    fn test_observe_point_rows_serial() {
+        let mut map = VoxelMap::new(Default::default());
+        map.extend(
+            room(0)
+                .into_iter()
+                .map(|point| UncertainWorldPoint::new(WorldPoint::new(point.into()))),
+        );
+        let points = room(1)
+            .into_iter()
+            .map(|point| {
+                let body_point = UncertainBodyPoint::from_body_point(
+                    BodyPoint::new(point.into()),
+                    Default::default(),
+                );
+                let mut world_point = UncertainWorldPoint::new(WorldPoint::new(point.into()));
+                world_point.cov.0 += Matrix3::identity() * 1e-4;
+                (body_point, world_point, Framed::new(point.cross_matrix()))
+            })
+            .collect::<Vec<_>>();
+
+        let measure_noise = MeasureNoiseConfig::default();
+        let body_to_world = IsometryFramed::default();
+        let rotation = Rotation3::identity();
+        let observe = |(body_point, world_point, cross_matrix_imu): &ProcessingPoint<f64>,
+                       counts: &mut ResidualCounts| {
+            let Uncertained {
+                state: residual,
+                cov: residual_cov,
+            } = map
+                .get_or_nearest_residual_counted(world_point, counts)?
+                .to_uncertained(body_point, &body_to_world);
+            let model = point_model(cross_matrix_imu, &rotation, residual.plane_normal());
+            let measurement = -residual.distance_to_plane;
+            let noise = measure_noise.lidar_point_noise(&measurement, residual_cov.to_scalar())?;
+            Some((measurement, model, noise, model))
+        };
+
+        let mut stats = PointsObserveStats::default();
+        let rows: Vec<(f64, Vector6<f64>, f64)> = observe_point_rows(&points, &mut stats, observe);
+
+        let mut serial_stats = PointsObserveStats::default();
+        let serial_rows = points
+            .iter()
+            .filter_map(|point| {
+                let (measurement, model, noise, pose_model) =
+                    observe(point, &mut serial_stats.residuals)?;
+                serial_stats.add_pose_information(&pose_model, &noise);
+                Some((measurement, model, noise))
+            })
+            .collect::<Vec<_>>();
+
+        assert!(!rows.is_empty());
+        assert_eq!(rows, serial_rows);
+        assert_eq!(stats.residuals, serial_stats.residuals);
+        assert_eq!(stats.pose_information, serial_stats.pose_information);
+    }
+
+    /// The points updates on a single thread, which runs in the serial order,
+    /// and on several threads end up with the same state and map.
+    #[test]
+    fn test_points_update_serial() {
+        let Some(imu_init) = (0..10).map(imu).collect::<Option<ImuInit<f64>>>() else {
+            panic!("failed to init the IMU");
+        };
+        // enough points in each voxel for the planes
+        let config = || Config {
+            downsample_resolution: 0.1,
+            ..Default::default()
+        };
+        let new_lio = || imu_init.clone().new_lio(config());
+        let run = |threads: usize, lio: &mut LIO<f64>| {
+            let Ok(pool) = rayon::ThreadPoolBuilder::new().num_threads(threads).build() else {
+                panic!("failed to build the thread pool");
+            };
+            pool.install(|| {
+                (1..=3)
+                    .map(|scan| {
+                        lio.extend((scan * 10..(scan + 1) * 10).map(imu));
+                        lio.update_points((scan + 1) as f64 * 0.1, room(scan))
+                            .residuals
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        let mut serial = new_lio();
+        let mut parallel = new_lio();
+        // the same hasher so that the downsampled points are in the same order
+        parallel.downsampler.grid = serial.downsampler.grid.clone();
+        let serial_residuals = run(1, &mut serial);
+        let parallel_residuals = run(4, &mut parallel);
+
+        assert!(serial_residuals.iter().any(|residuals| residuals.found > 0));
+        assert_eq!(parallel_residuals, serial_residuals);
+        assert_eq!(parallel.get_pose().deref(), serial.get_pose().deref());
+        assert_eq!(parallel.eskf.cov.deref(), serial.eskf.cov.deref());
+        let planes = |lio: &LIO<f64>| {
+            lio.planes()
+                .map(|plane| format!("{plane:?}"))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(planes(&parallel), planes(&serial));
+    }
+}
@@ -0,0 +1,154 @@
+use std::ops::AddAssign;
+
+use nalgebra::{
+    DMatrix, DVector, DefaultAllocator, DimName, IsometryMatrix3, Matrix6, RealField, U6, Vector6,
+    allocator::Allocator,
+};
+
+use crate::{
+    algorithm::lio::{LIO, state::LioState},
+    eskf::{
+        DeltaTime, ErrorState, Eskf, StatePredictor,
+        augment::{AugmentedObservation, CloneId, StateClone, relative_pose_model},
+        observe::ObserveReport,
+        state::{SubStateOf, common::PoseState},
+    },
+    utils::ToRadians,
+};
+
+impl<T, S> LIO<T, S>
+where
+    T: RealField + ToRadians,
+    S: LioState<T> + AddAssign<ErrorState<S>>,
+    PoseState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    Eskf<S>: StatePredictor<DeltaTime<T>>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim> + Allocator<S::Dim>,
+{
+    /// Clone the current pose into the augmented state, e.g. at a keyframe,
+    /// which can be observed by [`LIO::update_relative_pose`] until it is marginalized.
+    pub fn clone_pose(&mut self) -> CloneId {
+        let timestamp = self.eskf.last_update_time.predict.clone();
+        self.eskf.clone_sub_state::<PoseState<T>>(timestamp)
+    }
+
+    /// Drop the cloned pose, see also [`LIO::clone_pose`].
+    #[inline]
+    pub fn marginalize_pose(&mut self, clone: CloneId) -> Option<StateClone<S>> {
+        self.eskf.marginalize(clone)
+    }
+
+    /// Observe the relative pose `measured` from the cloned pose to the pose at `timestamp`,
+    /// e.g. the odometry delta of another sensor in the IMU frame.
+    ///
+    /// `noise` is the variance of the rotation error and then the position error.
+    /// Returns `None` if the clone is marginalized.
+    pub fn update_relative_pose(
+        &mut self,
+        timestamp: T,
+        clone: CloneId,
+        measured: &IsometryMatrix3<T>,
+        noise: Vector6<T>,
+    ) -> Option<ObserveReport> {
+        let report = self.eskf.update_augmented(timestamp.clone(), |eskf| {
+            let clone = eskf.get_clone(clone)?;
+            let (residual, clone_model) = relative_pose_model(
+                &clone.state.as_ref().pose.0,
+                &eskf.state.as_ref().pose.0,
+                measured,
+            );
+            Some(relative_pose_observation(
+                residual,
+                clone.id,
+                clone_model,
+                <PoseState<T> as SubStateOf<S>>::Offset::DIM,
+                S::Dim::DIM,
+                noise,
+            ))
+        })?;
+        self.record_state(timestamp);
+        Some(report)
+    }
+}
+
+fn relative_pose_observation<T: RealField>(
+    residual: Vector6<T>,
+    clone: CloneId,
+    clone_model: Matrix6<T>,
+    pose_offset: usize,
+    dim: usize,
+    noise: Vector6<T>,
+) -> AugmentedObservation<T> {
+    let mut model = DMatrix::zeros(6, dim);
+    model
+        .view_mut((0, pose_offset), (6, 6))
+        .fill_with_identity();
+    AugmentedObservation {
+        measurement: DVector::from_column_slice(residual.as_slice()),
+        model,
+        clone_models: vec![(
+            clone,
+            DMatrix::from_column_slice(6, 6, clone_model.as_slice()),
+        )],
+        noise: DVector::from_column_slice(noise.as_slice()),
+        gate: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Matrix6;
+
+    use super::*;
+    use crate::algorithm::lio::{Config, ImuInit, ImuMeasured, StampedImu};
+
+    /// The IMU turning around the vertical axis after the first 10 samples.
+    fn imu(i: usize) -> StampedImu<f64> {
+        let turn = if i < 10 { 0.0 } else { 0.5 };
+        StampedImu::new(
+            i as f64 * 0.01,
+            ImuMeasured::new(0.0, 0.0, 9.81, 0.0, 0.0, turn),
+        )
+    }
+
+    /// The relative pose consistent with the filter only shrinks the covariance.
+    #[test]
+    fn test_consistent_relative_pose() {
+        let Some(imu_init) = (0..10).map(imu).collect::<Option<ImuInit<f64>>>() else {
+            panic!("failed to init the IMU");
+        };
+        let mut lio = imu_init.new_lio(Config::default());
+        lio.extend((10..20).map(imu));
+        let clone = lio.clone_pose();
+        lio.extend((20..40).map(imu));
+
+        let Some(cloned) = lio.eskf.get_clone(clone) else {
+            panic!("the clone is missing");
+        };
+        let cloned = cloned.state.as_ref().pose.0.clone();
+        let pose = lio.get_pose().clone();
+        let measured = cloned.inv_mul(&pose);
+        assert!(measured.rotation.angle() > 0.05);
+        let pose_cov = |lio: &LIO<f64>| -> Matrix6<f64> {
+            lio.eskf.cov.sub_covariance::<PoseState<f64>>().into()
+        };
+        let prior_cov = pose_cov(&lio);
+
+        let timestamp = lio.eskf.last_update_time.predict;
+        let report = lio.update_relative_pose(timestamp, clone, &measured, Vector6::repeat(1e-4));
+        assert!(report.is_some_and(|report| !report.is_rejected()));
+
+        assert!((lio.get_pose().to_homogeneous() - pose.to_homogeneous()).amax() < 1e-9);
+        let Some(updated) = lio.eskf.get_clone(clone) else {
+            panic!("the clone is missing");
+        };
+        let updated = &updated.state.as_ref().pose.0;
+        assert!((updated.to_homogeneous() - cloned.to_homogeneous()).amax() < 1e-9);
+        assert!(pose_cov(&lio).trace() < prior_cov.trace());
+
+        assert!(lio.marginalize_pose(clone).is_some());
+        assert!(
+            lio.update_relative_pose(timestamp, clone, &measured, Vector6::repeat(1e-4))
+                .is_none()
+        );
+    }
+}
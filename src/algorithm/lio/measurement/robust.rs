@@ -0,0 +1,86 @@
+use nalgebra::RealField;
+
+/// The robust kernel of the point-to-plane residuals, which re-weights the noise of each point
+/// by its residual normalized by the noise, i.e. `u = |r| / sqrt(noise)`,
+/// so that the outliers, e.g. the points on the moving objects, pull the pose less.
+///
+/// The parameter of each kernel is the scale `k` of the normalized residual, the typical values are
+/// `1.345`, `2.385`, `1.0` and `4.685` for [`Huber`](Self::Huber), [`Cauchy`](Self::Cauchy),
+/// [`GemanMcClure`](Self::GemanMcClure) and [`Tukey`](Self::Tukey) respectively.
+#[derive(Debug, Clone, Default)]
+pub enum RobustKernel<T> {
+    /// No re-weighting, the residuals are Gaussian.
+    #[default]
+    Gaussian,
+    /// The weight is `1` if `u <= k`, otherwise `k / u`.
+    Huber(T),
+    /// The weight is `1 / (1 + (u / k)^2)`.
+    Cauchy(T),
+    /// The weight is `1 / (1 + (u / k)^2)^2`.
+    GemanMcClure(T),
+    /// The weight is `(1 - (u / k)^2)^2` if `u < k`, otherwise the point is rejected.
+    Tukey(T),
+}
+
+impl<T: RealField> RobustKernel<T> {
+    /// The weight of the normalized residual `u`, `None` if the point is rejected.
+    pub fn weight(&self, u: T) -> Option<T> {
+        let weight = match self {
+            Self::Gaussian => T::one(),
+            Self::Huber(k) => {
+                let u = u.abs();
+                if u <= *k { T::one() } else { k.clone() / u }
+            }
+            Self::Cauchy(k) => (T::one() + (u / k.clone()).powi(2)).recip(),
+            Self::GemanMcClure(k) => (T::one() + (u / k.clone()).powi(2)).powi(2).recip(),
+            Self::Tukey(k) => {
+                let ratio = (u / k.clone()).powi(2);
+                if ratio >= T::one() {
+                    return None;
+                }
+                (T::one() - ratio).powi(2)
+            }
+        };
+        Some(weight)
+    }
+
+    /// The re-weighted `noise` of the `residual`, `None` if the point is rejected.
+    pub fn reweight(&self, residual: &T, noise: T) -> Option<T> {
+        if let Self::Gaussian = self {
+            return Some(noise);
+        }
+        let u = residual.clone() / noise.clone().sqrt();
+        let weight = self.weight(u)?;
+        if weight <= T::zero() {
+            return None;
+        }
+        Some(noise / weight)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_robust_kernel() {
+        let kernels = [
+            RobustKernel::Huber(1.345),
+            RobustKernel::Cauchy(2.385),
+            RobustKernel::GemanMcClure(1.0),
+            RobustKernel::Tukey(4.685),
+        ];
+        for kernel in &kernels {
+            // the inliers are barely re-weighted, and the outliers are down-weighted more as they are farther
+            let inlier = kernel.reweight(&0.01, 1.0).unwrap_or(f64::INFINITY);
+            let outlier = kernel.reweight(&3.0, 1.0).unwrap_or(f64::INFINITY);
+            let far_outlier = kernel.reweight(&10.0, 1.0).unwrap_or(f64::INFINITY);
+            assert!((inlier - 1.0).abs() < 1e-3, "{kernel:?}");
+            assert!(outlier > 1.0, "{kernel:?}");
+            assert!(far_outlier > outlier, "{kernel:?}");
+        }
+        assert_eq!(RobustKernel::Gaussian.reweight(&10.0, 2.0), Some(2.0));
+        assert_eq!(RobustKernel::Huber(1.0).reweight(&-4.0, 4.0), Some(8.0));
+        assert_eq!(RobustKernel::Tukey(4.685).reweight(&5.0, 1.0), None);
+    }
+}
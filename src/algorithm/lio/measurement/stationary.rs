@@ -0,0 +1,162 @@
+use std::{collections::VecDeque, ops::Deref};
+
+use nalgebra::{DefaultAllocator, Matrix3, RealField, Scalar, U3, Vector3, allocator::Allocator};
+use simba::scalar::SupersetOf;
+
+use crate::{
+    algorithm::lio::state::{LioState, State},
+    eskf::{
+        Eskf,
+        observe::UnbiasedObservation,
+        state::{
+            KFState, SubStateOf,
+            common::{AngularAccState, VelocityState},
+            correlation::Unbiased,
+        },
+    },
+};
+
+use super::ImuMeasured;
+
+/// The zero velocity observation when the IMU is stationary, also known as the ZUPT.
+pub type ZeroVelocityObserved<T, S = State<T>> = UnbiasedObservation<VelocityState<T>, S, U3>;
+/// The zero angular rate observation when the IMU is stationary.
+pub type ZeroAngularRateObserved<T, S = State<T>> = UnbiasedObservation<AngularAccState<T>, S, U3>;
+
+/// The configuration of the stationary detection and the zero velocity update.
+#[derive(Debug, Clone)]
+pub struct StationaryConfig<T> {
+    /// The number of the latest IMU measurements tested for the stationary.
+    pub window_size: usize,
+    /// The maximum variance of the linear acceleration in the window, in the IMU measured unit.
+    pub acc_variance: T,
+    /// The maximum variance of the angular velocity in the window.
+    pub gyro_variance: T,
+    /// The measurement noise of the zero velocity.
+    pub velocity_noise: T,
+    /// The measurement noise of the zero angular rate, `None` means the angular rate is not observed.
+    pub angular_rate_noise: Option<T>,
+}
+
+impl<T: SupersetOf<f64>> Default for StationaryConfig<T> {
+    fn default() -> Self {
+        Self {
+            window_size: 20,
+            acc_variance: nalgebra::convert(0.01),
+            gyro_variance: nalgebra::convert(1e-4),
+            velocity_noise: nalgebra::convert(1e-4),
+            angular_rate_noise: None,
+        }
+    }
+}
+
+/// Detect whether the IMU is stationary by the variance tests over a window of the IMU measurements.
+#[derive(Debug, Clone)]
+pub struct StationaryDetector<T: Scalar> {
+    pub config: StationaryConfig<T>,
+    window: VecDeque<ImuMeasured<T>>,
+    is_stationary: bool,
+}
+
+impl<T: RealField> StationaryDetector<T> {
+    pub fn new(config: StationaryConfig<T>) -> Self {
+        Self {
+            window: VecDeque::with_capacity(config.window_size),
+            config,
+            is_stationary: false,
+        }
+    }
+
+    /// Whether the IMU is stationary since the latest measurement.
+    #[inline]
+    pub fn is_stationary(&self) -> bool {
+        self.is_stationary
+    }
+
+    /// Push the latest IMU measurement into the window and test it,
+    /// returns whether the IMU is stationary.
+    pub fn push(&mut self, imu: &ImuMeasured<T>) -> bool {
+        if self.window.len() >= self.config.window_size {
+            self.window.pop_front();
+        }
+        self.window.push_back(imu.clone());
+
+        self.is_stationary = self.window.len() >= self.config.window_size.max(2)
+            && variance(self.window.iter().map(|imu| imu.linear.deref()))
+                <= self.config.acc_variance
+            && variance(self.window.iter().map(|imu| imu.angular.deref()))
+                <= self.config.gyro_variance;
+        self.is_stationary
+    }
+
+    /// Clear the window, e.g. after the state is reset.
+    pub fn clear(&mut self) {
+        self.window.clear();
+        self.is_stationary = false;
+    }
+}
+
+/// The mean squared distance of the `values` to their mean.
+fn variance<'a, T: RealField>(values: impl ExactSizeIterator<Item = &'a Vector3<T>> + Clone) -> T {
+    let count = T::from_usize(values.len()).unwrap_or_else(T::one);
+    let mean = values
+        .clone()
+        .fold(Vector3::zeros(), |sum, value| sum + value)
+        / count.clone();
+    values.fold(T::zero(), |sum, value| sum + (value - &mean).norm_squared()) / count
+}
+
+impl<T, S> Eskf<S>
+where
+    T: RealField,
+    S: KFState<Element = T> + LioState<T>,
+    VelocityState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    AngularAccState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
+{
+    pub(crate) fn observe_zero_velocity(&self, noise: &T) -> ZeroVelocityObserved<T, S> {
+        observe_zero(self.state.as_ref().velocity.deref(), noise)
+    }
+
+    pub(crate) fn observe_zero_angular_rate(&self, noise: &T) -> ZeroAngularRateObserved<T, S> {
+        observe_zero(self.state.as_ref().acc_with_bias.acc.angular.deref(), noise)
+    }
+}
+
+/// Observe the sub-state `Sub` to be zero, whose estimation is `value`.
+fn observe_zero<T, Sub, S>(value: &Vector3<T>, noise: &T) -> UnbiasedObservation<Sub, S, U3>
+where
+    T: RealField,
+    S: KFState<Element = T>,
+    Sub: SubStateOf<S, Element = T, Dim = U3> + Unbiased,
+{
+    UnbiasedObservation::new(-value, Matrix3::identity(), Vector3::repeat(noise.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stationary_detection() {
+        let mut detector = StationaryDetector::new(StationaryConfig {
+            window_size: 4,
+            ..Default::default()
+        });
+        let imu =
+            |linear: f64, angular: f64| ImuMeasured::new(linear, 0.0, 9.81, angular, 0.0, 0.0);
+
+        assert!(!detector.push(&imu(0.0, 0.0)));
+        assert!(!detector.push(&imu(0.01, 0.0)));
+        assert!(!detector.push(&imu(-0.01, 0.001)));
+        assert!(detector.push(&imu(0.0, 0.0)));
+
+        // a bump of the acceleration
+        assert!(!detector.push(&imu(1.0, 0.0)));
+        (0..3).for_each(|_| {
+            detector.push(&imu(0.0, 0.0));
+        });
+        assert!(!detector.is_stationary());
+        assert!(detector.push(&imu(0.0, 0.0)));
+    }
+}
@@ -1,15 +1,22 @@
 use std::ops::{Deref, DerefMut};
 
-use super::State;
+use super::{LioState, State, measurement::ImuMeasured};
 use crate::{
     algorithm::lio::LIO,
-    eskf::{Covariance, DeltaTime, Eskf, StatePredictor, state::common::*},
+    eskf::{
+        Covariance, DeltaTime, Eskf, KFTime, StatePredictor,
+        state::{KFState, SubStateOf, common::*},
+    },
 };
 
-use nalgebra::{IsometryMatrix3, RealField, Rotation3, SMatrix, Scalar, Translation3};
+use nalgebra::{
+    DefaultAllocator, DimName, IsometryMatrix3, OMatrix, RealField, Rotation3, Scalar,
+    Translation3, U3, U6, allocator::Allocator,
+};
 use num_traits::Zero;
 use simba::scalar::SupersetOf;
 
+#[derive(Clone)]
 pub struct ProcessCovConfig<T> {
     pub velocity: T,
     pub linear_acc_bias: T,
@@ -18,33 +25,85 @@ pub struct ProcessCovConfig<T> {
     pub angular_acc: T,
 }
 
+/// How the IMU measurements are fused into the state.
+#[derive(Clone)]
+pub enum ImuPropagation<T> {
+    /// Observe the IMU measurements against the accelerations in the state,
+    /// which are predicted with the constant acceleration model and [`ProcessCovConfig`].
+    Observation,
+    /// Drive the prediction by the bias-corrected IMU measurements as the control input,
+    /// which are held until the next IMU measurement, also known as the FAST-LIO propagation.
+    ///
+    /// The process noise comes from the [`ImuNoiseConfig`], and [`ProcessCovConfig`] is ignored,
+    /// so are the IMU measurement noise and gate in [`MeasureNoiseConfig`](super::MeasureNoiseConfig).
+    ControlInput(ImuNoiseConfig<T>),
+}
+
+/// The continuous-time noise densities of the IMU, e.g. from the datasheet or the Allan variance.
+#[derive(Clone)]
+pub struct ImuNoiseConfig<T> {
+    /// The accelerometer noise density in `m/s^2/sqrt(Hz)`.
+    pub acc_noise_density: T,
+    /// The gyroscope noise density in `rad/s/sqrt(Hz)`.
+    pub gyro_noise_density: T,
+    /// The accelerometer bias random walk in `m/s^3/sqrt(Hz)`.
+    pub acc_bias_random_walk: T,
+    /// The gyroscope bias random walk in `rad/s^2/sqrt(Hz)`.
+    pub gyro_bias_random_walk: T,
+}
+
 impl<T> StatePredictor<T> for State<T>
 where
     T: RealField,
 {
     fn predict(&mut self, dt: T) {
         let acc = &self.acc_with_bias.acc;
+        let predicted_pose = self.predict_pose(dt.clone());
         let pose: &mut IsometryMatrix3<T> = self.pose.deref_mut();
 
-        let delta_rotation = Rotation3::new(acc.angular.deref() * dt.clone());
-        let delta_translation = Translation3::from(self.velocity.deref() * dt.clone());
         let delta_velocity = (pose.deref() * acc.linear.deref() + self.gravity.deref()) * dt;
 
-        *pose *= delta_rotation;
-        *pose *= delta_translation;
+        *pose = predicted_pose;
         *self.velocity += delta_velocity;
     }
 }
 
-impl<T> Eskf<State<T>>
+impl<T> State<T>
 where
     T: RealField,
 {
-    pub fn predict_cov(&mut self, dt: T) {
-        let state = &self.state;
+    /// Predict the pose after `dt` without modifying the state,
+    /// see also [`StatePredictor::predict`].
+    pub fn predict_pose(&self, dt: T) -> IsometryMatrix3<T> {
         let acc = &self.acc_with_bias.acc;
+        let pose: &IsometryMatrix3<T> = self.pose.deref();
+
+        let delta_rotation = Rotation3::new(acc.angular.deref() * dt.clone());
+        let delta_translation = Translation3::from(self.velocity.deref() * dt);
+
+        pose * delta_rotation * delta_translation
+    }
+}
+
+impl<T, S> Eskf<S>
+where
+    T: RealField,
+    S: KFState<Element = T> + LioState<T>,
+    RotationState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    PositionState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    VelocityState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    GravityState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    LinearAccState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    AngularAccState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim> + Allocator<S::Dim>,
+{
+    /// Predict the covariance of the LIO [`State`] part, the other parts of `S` are only
+    /// affected by the process noise.
+    pub fn predict_cov(&mut self, dt: T) {
+        let state: &State<T> = self.state.as_ref();
+        let acc = &state.acc_with_bias.acc;
 
-        let mut fx = Covariance::<State<T>>(SMatrix::identity());
+        let mut fx = Covariance::<S>(OMatrix::identity_generic(S::Dim::name(), S::Dim::name()));
 
         fx.sub_covariance_mut::<RotationState<T>>()
             .copy_from(Rotation3::new(acc.angular.deref() * -dt.clone()).matrix());
@@ -68,6 +127,71 @@ where
         let mut cov = self.process_cov.deref() * dt.powi(2);
         cov.quadform_tr(T::one(), &fx, self.cov.deref(), T::one());
         *self.cov = cov;
+        self.record_transition(&fx);
+    }
+}
+
+impl<T, S> Eskf<S>
+where
+    T: RealField,
+    S: KFState<Element = T> + LioState<T>,
+    AccState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    BiasState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim> + Allocator<S::Dim>,
+    Self: StatePredictor<DeltaTime<T>>,
+{
+    /// Predict the state to `timestamp` with the previous IMU measurement,
+    /// then take the bias-corrected `imu_acc` as the control input until the next one.
+    ///
+    /// The accelerations in the state are the held input, whose error is the negative bias error
+    /// plus the measurement noise, so the bias is still corrected through their correlation.
+    ///
+    /// `sample_period` is the time since the previous IMU measurement, `None` for the first one,
+    /// which scales the noise of the input regardless of the other updates in between.
+    pub(crate) fn propagate_imu(
+        &mut self,
+        timestamp: T,
+        sample_period: Option<T>,
+        gravity_factor: T,
+        noise: &ImuNoiseConfig<T>,
+        imu_acc: &ImuMeasured<T>,
+    ) {
+        let dt = KFTime::all(timestamp.clone()) - self.last_update_time.clone();
+        self.predict(dt.clone());
+        self.last_update_time = KFTime::all(timestamp);
+
+        let AccWithBiasState { acc, bias } = &mut self.state.as_mut().acc_with_bias;
+        *acc.linear = imu_acc.linear.deref() * gravity_factor - bias.linear.deref();
+        *acc.angular = imu_acc.angular.deref() - bias.angular.deref();
+
+        // X = J.X.J', the error of the input is the negative error of the bias
+        let mut jacobian =
+            Covariance::<S>(OMatrix::identity_generic(S::Dim::name(), S::Dim::name()));
+        jacobian.sub_covariance_mut::<AccState<T>>().fill(T::zero());
+        jacobian
+            .sensitivity_mut::<BiasState<T>, AccState<T>>()
+            .fill_diagonal(-T::one());
+        let mut cov = self.cov.clone();
+        cov.quadform_tr(T::one(), &jacobian, self.cov.deref(), T::zero());
+        *self.cov = cov.0;
+        self.record_transition(&jacobian);
+
+        // the first measurement has no sample period
+        if let Some(period) = sample_period.filter(|period| *period > T::zero()) {
+            let mut acc_cov = self.cov.sub_covariance_mut::<AccState<T>>();
+            (0..3).for_each(|i| {
+                acc_cov[(i, i)] += noise.acc_noise_density.clone().powi(2) / period.clone();
+                acc_cov[(i + 3, i + 3)] +=
+                    noise.gyro_noise_density.clone().powi(2) / period.clone();
+            });
+        }
+        // the bias walks over the time the covariance is just predicted, which is since the previous update
+        let dt = dt.observe;
+        let mut bias_cov = self.cov.sub_covariance_mut::<BiasState<T>>();
+        (0..3).for_each(|i| {
+            bias_cov[(i, i)] += noise.acc_bias_random_walk.clone().powi(2) * dt.clone();
+            bias_cov[(i + 3, i + 3)] += noise.gyro_bias_random_walk.clone().powi(2) * dt.clone();
+        });
     }
 }
 
@@ -81,9 +205,12 @@ where
     }
 }
 
-impl<T> StatePredictor<DeltaTime<T>> for LIO<T>
+impl<T, S> StatePredictor<DeltaTime<T>> for LIO<T, S>
 where
     T: RealField,
+    S: KFState<Element = T>,
+    Eskf<S>: StatePredictor<DeltaTime<T>>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
 {
     #[inline]
     fn predict(&mut self, dt: DeltaTime<T>) {
@@ -103,9 +230,49 @@ impl<T: SupersetOf<f64>> Default for ProcessCovConfig<T> {
     }
 }
 
-impl<T> From<ProcessCovConfig<T>> for Covariance<State<T>>
+impl<T: Zero> ProcessCovConfig<T> {
+    pub fn zeros() -> Self {
+        Self {
+            velocity: T::zero(),
+            linear_acc: T::zero(),
+            linear_acc_bias: T::zero(),
+            angular_acc: T::zero(),
+            angular_acc_bias: T::zero(),
+        }
+    }
+}
+
+impl<T: Zero> ImuPropagation<T> {
+    /// The process noise configuration of the LIO [`State`] used with this propagation.
+    pub fn state_process_cov(&self, config: ProcessCovConfig<T>) -> ProcessCovConfig<T> {
+        match self {
+            Self::Observation => config,
+            Self::ControlInput(_) => ProcessCovConfig::zeros(),
+        }
+    }
+}
+
+impl<T: SupersetOf<f64>> Default for ImuNoiseConfig<T> {
+    fn default() -> Self {
+        Self {
+            acc_noise_density: nalgebra::convert(0.02),
+            gyro_noise_density: nalgebra::convert(0.002),
+            acc_bias_random_walk: nalgebra::convert(0.001),
+            gyro_bias_random_walk: nalgebra::convert(0.0001),
+        }
+    }
+}
+
+impl<T, S> From<ProcessCovConfig<T>> for Covariance<S>
 where
     T: Scalar + Zero,
+    S: KFState<Element = T>,
+    VelocityState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    LinearAccState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    LinearAccBiasState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    AngularAccState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    AngularAccBiasState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
 {
     fn from(value: ProcessCovConfig<T>) -> Self {
         let mut cov = Self::default();
@@ -128,3 +295,125 @@ where
         cov
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::lio::{
+        LIO, MeasureNoiseConfig, NoGravityConfig, StampedImu,
+        config::ProcessCovConfig as LioProcessCovConfig,
+    };
+    use nalgebra::Matrix3;
+    use std::ops::RangeInclusive;
+
+    const PERIOD: f64 = 0.01;
+
+    fn noise() -> ImuNoiseConfig<f64> {
+        ImuNoiseConfig {
+            acc_noise_density: 0.02,
+            gyro_noise_density: 0.002,
+            acc_bias_random_walk: 0.0,
+            gyro_bias_random_walk: 0.0,
+        }
+    }
+
+    /// The LIO without any uncertainty at the beginning.
+    fn new_lio(config: NoGravityConfig<f64>) -> LIO<f64> {
+        let mut lio = LIO::new_with_gravity_factor(config, 0.0, 1.0);
+        lio.eskf.cov.fill(0.0);
+        lio
+    }
+
+    fn control_input_lio() -> LIO<f64> {
+        new_lio(NoGravityConfig {
+            imu_propagation: ImuPropagation::ControlInput(noise()),
+            ..Default::default()
+        })
+    }
+
+    /// The static IMU measurements at a fixed sample period, indexed by `samples`.
+    fn imus(samples: RangeInclusive<usize>) -> impl Iterator<Item = StampedImu<f64>> {
+        samples.map(|i| StampedImu::zeros(i as f64 * PERIOD))
+    }
+
+    /// The covariances of the velocity and the rotation.
+    fn covs(lio: &LIO<f64>) -> (Matrix3<f64>, Matrix3<f64>) {
+        (
+            lio.eskf.cov.sub_covariance::<VelocityState<f64>>().into(),
+            lio.eskf.cov.sub_covariance::<RotationState<f64>>().into(),
+        )
+    }
+
+    fn assert_relative_eq(a: &Matrix3<f64>, b: &Matrix3<f64>, relative: f64) {
+        assert!((a - b).norm() <= b.norm() * relative, "{a} != {b}");
+    }
+
+    #[test]
+    fn test_control_input_independent_of_updates() {
+        let mut lio = control_input_lio();
+        lio.extend(imus(1..=100));
+
+        let mut updated = control_input_lio();
+        imus(1..=100).enumerate().for_each(|(i, imu)| {
+            // an uninformative update between the IMU measurements like a points update,
+            // which only moves the update time
+            if i % 3 == 1 {
+                let timestamp = imu.timestamp - 0.3 * PERIOD;
+                updated
+                    .eskf
+                    .update(timestamp, |eskf| Some(eskf.observe_zero_velocity(&1e12)));
+            }
+            updated.extend([imu]);
+        });
+
+        let (velocity, rotation) = covs(&lio);
+        let (updated_velocity, updated_rotation) = covs(&updated);
+        assert!(velocity.norm() > 0.0 && rotation.norm() > 0.0);
+        assert_relative_eq(&updated_velocity, &velocity, 1e-9);
+        assert_relative_eq(&updated_rotation, &rotation, 1e-9);
+    }
+
+    #[test]
+    fn test_control_input_matches_observation() {
+        let noise = noise();
+        // the IMU measurement noise of the held input over a sample period,
+        // and the accelerations are hardly constrained by the process
+        let acc = noise.acc_noise_density.powi(2) / PERIOD;
+        let gyro = noise.gyro_noise_density.powi(2) / PERIOD;
+        let config = NoGravityConfig {
+            process_cov: LioProcessCovConfig {
+                state: ProcessCovConfig {
+                    linear_acc: 1e6,
+                    angular_acc: 1e6,
+                    ..ProcessCovConfig::zeros()
+                },
+                ..Default::default()
+            },
+            measure_noise: MeasureNoiseConfig {
+                imu_acc: AccState::new(acc, acc, acc, gyro, gyro, gyro),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut observation = new_lio(config);
+        let mut control_input = control_input_lio();
+
+        // the first measurement has no sample period as the control input
+        observation.extend(imus(1..=2));
+        control_input.extend(imus(1..=2));
+        let (observation_velocity, observation_rotation) = covs(&observation);
+        let (control_velocity, control_rotation) = covs(&control_input);
+
+        observation.extend(imus(3..=100));
+        control_input.extend(imus(3..=100));
+        let (velocity, rotation) = covs(&observation);
+        let (velocity, rotation) = (
+            velocity - observation_velocity,
+            rotation - observation_rotation,
+        );
+        let (velocity_control, rotation_control) = covs(&control_input);
+        assert!(velocity.norm() > 0.0 && rotation.norm() > 0.0);
+        assert_relative_eq(&(velocity_control - control_velocity), &velocity, 1e-2);
+        assert_relative_eq(&(rotation_control - control_rotation), &rotation, 1e-2);
+    }
+}
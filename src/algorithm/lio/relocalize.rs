@@ -0,0 +1,596 @@
+//! Relocalization of the [`LIO`] in a prior [`VoxelMap`](crate::voxel_map::VoxelMap),
+//! see also [`LIO::with_map`].
+
+use std::{collections::HashSet, ops::Deref};
+
+use nalgebra::{
+    DefaultAllocator, DimName, IsometryMatrix3, Matrix3, Matrix6, Point3, RealField, Rotation3,
+    Scalar, Translation3, U3, U6, Vector3, Vector6, allocator::Allocator,
+};
+use simba::scalar::SupersetOf;
+
+use crate::{
+    eskf::{
+        state::{
+            SubStateOf,
+            common::{PoseState, PositionState, RotationState},
+        },
+        uncertain::Uncertained,
+    },
+    frame::{CrossMatrixFramed, Framed, IsometryFramed, frames},
+    utils::ToRadians,
+    voxel_map::uncertain::{UncertainBodyPoint, UncertainWorldPoint},
+};
+
+use super::{
+    LIO,
+    downsample::Downsample,
+    measurement::{LidarPoint, point_model},
+    state::LioState,
+};
+
+type ImuToWorld<T> = IsometryFramed<T, fn(frames::Imu) -> frames::World>;
+
+pub struct RelocalizeConfig<T> {
+    /// The spacing of the finest grid where the candidate positions of the global registration are placed.
+    pub grid_size: T,
+    /// The number of the candidate yaw angles at the coarsest grid of the global registration.
+    pub yaw_steps: usize,
+    /// The maximum number of the candidates at the coarsest grid of the global registration,
+    /// the coarsest grid is the [`grid_size`](Self::grid_size) doubled until the candidates are within this bound.
+    pub max_candidates: usize,
+    /// The number of the best candidates kept at each grid of the global registration,
+    /// which are subdivided into the candidates of the next finer grid.
+    pub beam_width: usize,
+    /// The minimum absolute vertical component of the normal of a plane on the ground,
+    /// the candidate positions of the global registration are placed above the ground planes of the map.
+    pub ground_min_normal_z: T,
+    /// The height of the IMU above the ground, which only needs to be rough
+    /// since the height of the candidates is refined by the local registration.
+    pub sensor_height: T,
+    /// The maximum number of the points scoring the candidates of the global registration.
+    pub sample_points: usize,
+    /// The number of the best candidates of the global registration refined by the local registration.
+    pub refine_candidates: usize,
+    /// The variance added to the world points during the registration,
+    /// which widens the convergence basin of the point-to-plane residuals.
+    pub point_cov_inflation: T,
+    /// The maximum number of the Gauss-Newton iterations of the local registration.
+    pub max_iterations: usize,
+    /// The local registration stops once the norm of the pose increment is below this threshold.
+    pub converge_thresh: T,
+    /// The minimum fitness to consider the relocalization successful, see also [`RelocalizeReport::fitness`].
+    pub min_fitness: T,
+}
+
+#[derive(Debug, Clone)]
+pub struct RelocalizeReport<T: Scalar> {
+    /// Whether the state is seeded with the registered pose.
+    pub success: bool,
+    /// The registered pose, which is the pose before the relocalization if the registration fails.
+    pub pose: ImuToWorld<T>,
+    /// The ratio of the points with a valid residual to the registered map, in `[0, 1]`.
+    pub fitness: T,
+}
+
+/// The accumulated normal equation of the point-to-plane registration.
+struct NormalEquation<T: Scalar> {
+    information: Matrix6<T>,
+    gradient: Vector6<T>,
+    inliers: usize,
+}
+
+impl<T, S> LIO<T, S>
+where
+    T: RealField + ToRadians,
+    S: LioState<T>,
+    PoseState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    RotationState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    PositionState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
+{
+    /// Register the first `scans` against the prior map, and seed the pose and its covariance on success.
+    ///
+    /// Without an `initial_guess`, a global registration searches the candidate positions above the ground
+    /// of the map and the yaw angles from coarse to fine, keeping the current roll and pitch which are
+    /// aligned with the gravity, the number of the candidates is bounded by [`RelocalizeConfig::max_candidates`]
+    /// and [`RelocalizeConfig::beam_width`] however large the map is.
+    /// The best candidates or the `initial_guess` are then refined by the local point-to-plane registration,
+    /// whose residuals are the same as the lidar points observation,
+    /// see also [`VoxelMap::get_or_nearest_residual`](crate::voxel_map::VoxelMapView::get_or_nearest_residual).
+    ///
+    /// The scans are expected to be captured while static, so they are neither deskewed nor inserted into the map.
+    pub fn relocalize(
+        &mut self,
+        initial_guess: Option<ImuToWorld<T>>,
+        scans: impl IntoIterator<Item = impl IntoIterator<Item = impl LidarPoint<T>>>,
+    ) -> RelocalizeReport<T> {
+        let body_to_imu = self.extrinsics().clone();
+        let body_point_process_cov = &self.body_point_process_cov;
+        let points = scans
+            .into_iter()
+            .flatten()
+            .map(LidarPoint::to_body_point)
+            .voxel_grid_downsample(&self.downsampler.resolution, &mut self.downsampler.grid)
+            .map(|body_point| {
+                let body_point =
+                    UncertainBodyPoint::from_body_point(body_point, body_point_process_cov.clone());
+                let imu_point = body_point.deref() * &body_to_imu;
+                let cross_matrix_imu = Framed::new(imu_point.coords.cross_matrix());
+                (body_point, cross_matrix_imu)
+            })
+            .collect::<Vec<_>>();
+
+        let failure = |lio: &Self| RelocalizeReport {
+            success: false,
+            pose: lio.get_pose().clone(),
+            fitness: T::zero(),
+        };
+
+        if points.is_empty() {
+            return failure(self);
+        }
+        let candidates = match initial_guess {
+            Some(guess) => vec![guess],
+            None => self.global_registration(&points),
+        };
+        let Some((pose, equation)) = candidates
+            .into_iter()
+            .map(|candidate| self.local_registration(candidate, &points))
+            .max_by_key(|(_, equation)| equation.inliers)
+        else {
+            return failure(self);
+        };
+        let fitness: T = nalgebra::convert(equation.inliers as f64 / points.len() as f64);
+
+        let pose_cov = equation
+            .information
+            .try_inverse()
+            .filter(|_| fitness >= self.relocalize.min_fitness);
+        let Some(pose_cov) = pose_cov else {
+            return RelocalizeReport {
+                success: false,
+                pose,
+                fitness,
+            };
+        };
+
+        self.seed_pose(pose.clone(), &pose_cov);
+        RelocalizeReport {
+            success: true,
+            pose,
+            fitness,
+        }
+    }
+
+    /// Search the candidate poses above the ground planes of the map from the coarsest grid to the finest,
+    /// each grid halves the spacing and the yaw step around the best candidates of the previous one.
+    ///
+    /// The candidates are scored by the sample points falling in the cells occupied by the planes,
+    /// returns the [`RelocalizeConfig::refine_candidates`] ones with the highest scores.
+    fn global_registration(
+        &self,
+        points: &[(UncertainBodyPoint<T>, CrossMatrixFramed<T, frames::Imu>)],
+    ) -> Vec<ImuToWorld<T>> {
+        let config = &self.relocalize;
+        let body_to_imu = self.extrinsics();
+        let step = points.len().div_ceil(config.sample_points.max(1));
+        let samples = points
+            .iter()
+            .step_by(step.max(1))
+            .map(|(body_point, _)| (body_point.deref() * body_to_imu).deref().clone())
+            .collect::<Vec<_>>();
+
+        let centers = self
+            .map
+            .planes()
+            .map(|plane| plane.center.coords.clone())
+            .collect::<Vec<_>>();
+        let ground = self
+            .map
+            .planes()
+            .filter(|plane| plane.normal.z.clone().abs() >= config.ground_min_normal_z)
+            .map(|plane| plane.center.coords.clone())
+            .collect::<Vec<_>>();
+
+        let yaw_steps = config.yaw_steps.max(1);
+        let mut spacing = config.grid_size.clone();
+        let mut positions = ground_positions(&ground, &spacing, &config.sensor_height);
+        let mut levels = 0;
+        while positions.len() > 1 && positions.len() * yaw_steps > config.max_candidates {
+            spacing *= nalgebra::convert::<_, T>(2.0);
+            positions = ground_positions(&ground, &spacing, &config.sensor_height);
+            levels += 1;
+        }
+
+        let rotation = &self.get_pose().rotation;
+        let mut yaw_step = T::two_pi() / nalgebra::convert(yaw_steps as f64);
+        let candidates = positions.into_iter().flat_map(|position| {
+            let yaw_step = yaw_step.clone();
+            (0..yaw_steps).map(move |i| {
+                let yaw = yaw_step.clone() * nalgebra::convert(i as f64);
+                let yaw = Rotation3::from_axis_angle(&Vector3::z_axis(), yaw);
+                Framed::new(IsometryMatrix3::from_parts(
+                    Translation3::from(position.clone()),
+                    yaw * rotation,
+                ))
+            })
+        });
+        let mut beam = best_candidates(candidates, &centers, &spacing, &samples, config.beam_width);
+
+        for _ in 0..levels {
+            let quarter: T = nalgebra::convert(0.25);
+            let offset = spacing.clone() * quarter.clone();
+            let yaw_offset = yaw_step.clone() * quarter;
+            spacing /= nalgebra::convert::<_, T>(2.0);
+            yaw_step /= nalgebra::convert::<_, T>(2.0);
+
+            // the candidates at the centers of the halved cells and yaw intervals
+            let candidates = beam.iter().flat_map(|candidate| {
+                let signs = [-1.0, 1.0];
+                itertools::iproduct!(signs, signs, signs).map(|(x, y, yaw)| {
+                    let mut candidate: ImuToWorld<T> = candidate.clone();
+                    let sign = |sign: f64| nalgebra::convert::<_, T>(sign);
+                    candidate.translation.vector +=
+                        Vector3::new(sign(x), sign(y), T::zero()) * offset.clone();
+                    let yaw = Rotation3::from_axis_angle(
+                        &Vector3::z_axis(),
+                        sign(yaw) * yaw_offset.clone(),
+                    );
+                    candidate.rotation = yaw * &candidate.rotation;
+                    candidate
+                })
+            });
+            beam = best_candidates(candidates, &centers, &spacing, &samples, config.beam_width);
+        }
+
+        beam.truncate(config.refine_candidates);
+        beam
+    }
+
+    /// Refine the `pose` by the Gauss-Newton iterations of the point-to-plane residuals,
+    /// the pose is perturbed in the same way as the [`PoseState`].
+    fn local_registration(
+        &self,
+        mut pose: ImuToWorld<T>,
+        points: &[(UncertainBodyPoint<T>, CrossMatrixFramed<T, frames::Imu>)],
+    ) -> (ImuToWorld<T>, NormalEquation<T>) {
+        let config = &self.relocalize;
+        let mut equation = self.normal_equation(&pose, points);
+        for _ in 0..config.max_iterations {
+            let Some(delta_norm) = equation.solve_into(&mut pose) else {
+                break;
+            };
+            equation = self.normal_equation(&pose, points);
+            if delta_norm < config.converge_thresh {
+                break;
+            }
+        }
+        (pose, equation)
+    }
+
+    fn normal_equation(
+        &self,
+        imu_to_world: &ImuToWorld<T>,
+        points: &[(UncertainBodyPoint<T>, CrossMatrixFramed<T, frames::Imu>)],
+    ) -> NormalEquation<T> {
+        let body_to_world = self.extrinsics() * imu_to_world;
+        let mut equation = NormalEquation::new();
+
+        for (body_point, cross_matrix_imu) in points {
+            let world_point =
+                self.world_point(body_point, cross_matrix_imu, imu_to_world, &body_to_world);
+            let Some(residual) = self.map.get_or_nearest_residual(&world_point) else {
+                continue;
+            };
+            let Uncertained {
+                state: residual,
+                cov: residual_cov,
+            } = residual.to_uncertained(body_point, &body_to_world);
+
+            let model = point_model(
+                cross_matrix_imu,
+                &imu_to_world.rotation,
+                residual.plane_normal(),
+            );
+            let measurement = -residual.distance_to_plane.clone();
+            let Some(noise) = self
+                .measure_noise
+                .lidar_point_noise(&measurement, residual_cov.to_scalar())
+            else {
+                continue;
+            };
+            equation.add(&model, measurement, noise);
+        }
+        equation
+    }
+
+    fn world_point(
+        &self,
+        body_point: &UncertainBodyPoint<T>,
+        cross_matrix_imu: &CrossMatrixFramed<T, frames::Imu>,
+        imu_to_world: &ImuToWorld<T>,
+        body_to_world: &IsometryFramed<T, fn(frames::Body) -> frames::World>,
+    ) -> UncertainWorldPoint<T> {
+        let mut world_point = UncertainWorldPoint::from_uncertain_body_point(
+            body_point.clone(),
+            imu_to_world,
+            body_to_world,
+            cross_matrix_imu.as_ref(),
+            &self.eskf.cov,
+        );
+        inflate_cov(
+            &mut world_point.cov,
+            self.relocalize.point_cov_inflation.clone(),
+        );
+        world_point
+    }
+
+    /// Seed the pose of the state with the registered `pose`,
+    /// whose covariance is decoupled from the other sub-states.
+    fn seed_pose(&mut self, pose: ImuToWorld<T>, pose_cov: &Matrix6<T>) {
+        self.eskf.state.as_mut().pose = PoseState::new(pose);
+
+        let offset = <PoseState<T> as SubStateOf<S>>::Offset::DIM;
+        self.eskf.cov.fixed_rows_mut::<6>(offset).fill(T::zero());
+        self.eskf.cov.fixed_columns_mut::<6>(offset).fill(T::zero());
+        self.eskf
+            .cov
+            .sub_covariance_mut::<PoseState<T>>()
+            .copy_from(pose_cov);
+
+        // the seeded state is the start of the next scan, and the history before is not continuous
+        self.state_history.clear();
+        if let Some(smoother) = self.eskf.smoother_mut() {
+            smoother.clear();
+        }
+        self.record_state(self.eskf.last_update_time.predict.clone());
+    }
+}
+
+impl<T: RealField> NormalEquation<T> {
+    fn new() -> Self {
+        Self {
+            information: Matrix6::zeros(),
+            gradient: Vector6::zeros(),
+            inliers: 0,
+        }
+    }
+
+    /// Add a point-to-plane residual with its observation `model` and `noise`.
+    fn add(&mut self, model: &Vector6<T>, measurement: T, noise: T) {
+        let weight = noise.recip();
+        self.information += model * model.transpose() * weight.clone();
+        self.gradient += model * (measurement * weight);
+        self.inliers += 1;
+    }
+
+    /// Solve the increment and apply it to the `pose`, returns the norm of the increment.
+    fn solve_into(&self, pose: &mut ImuToWorld<T>) -> Option<T> {
+        let delta = self.information.clone().cholesky()?.solve(&self.gradient);
+        pose.rotation *= Rotation3::new(delta.fixed_rows::<3>(0).into_owned());
+        pose.translation.vector += delta.fixed_rows::<3>(3);
+        Some(delta.norm())
+    }
+}
+
+/// The index of the cell of the grid with `spacing` containing the `point`.
+fn cell_of<T: RealField>(point: &Vector3<T>, spacing: &T) -> [i64; 3] {
+    let cell = point.map(|x| {
+        let cell: f64 = (x / spacing.clone()).floor().to_subset_unchecked();
+        cell as i64
+    });
+    [cell.x, cell.y, cell.z]
+}
+
+/// The candidate positions at `sensor_height` above the `ground` plane centers,
+/// one for each cell of the grid with `spacing` containing them, at the horizontal center of the cell.
+fn ground_positions<T: RealField>(
+    ground: &[Vector3<T>],
+    spacing: &T,
+    sensor_height: &T,
+) -> Vec<Vector3<T>> {
+    let mut cells = HashSet::new();
+    ground
+        .iter()
+        .filter(|center| cells.insert(cell_of(center, spacing)))
+        .map(|center| {
+            let [x, y, _] = cell_of(center, spacing);
+            let center_of =
+                |cell: i64| (nalgebra::convert::<_, T>(cell as f64 + 0.5)) * spacing.clone();
+            Vector3::new(
+                center_of(x),
+                center_of(y),
+                center.z.clone() + sensor_height.clone(),
+            )
+        })
+        .collect()
+}
+
+/// The `width` best `candidates` scored by the `samples` in the IMU frame falling in the cells
+/// of the grid with `spacing` occupied by the plane `centers`, the ones scoring nothing are dropped.
+fn best_candidates<T: RealField>(
+    candidates: impl Iterator<Item = ImuToWorld<T>>,
+    centers: &[Vector3<T>],
+    spacing: &T,
+    samples: &[Point3<T>],
+    width: usize,
+) -> Vec<ImuToWorld<T>> {
+    let occupied = centers
+        .iter()
+        .map(|center| cell_of(center, spacing))
+        .collect::<HashSet<_>>();
+    let mut scored = candidates
+        .map(|candidate| {
+            let score = samples
+                .iter()
+                .filter(|sample| {
+                    let world_point = candidate.deref() * *sample;
+                    occupied.contains(&cell_of(&world_point.coords, spacing))
+                })
+                .count();
+            (candidate, score)
+        })
+        .filter(|(_, score)| *score > 0)
+        .collect::<Vec<_>>();
+    scored.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+    scored
+        .into_iter()
+        .take(width)
+        .map(|(candidate, _)| candidate)
+        .collect()
+}
+
+fn inflate_cov<T: RealField>(cov: &mut Matrix3<T>, variance: T) {
+    *cov += Matrix3::from_diagonal_element(variance);
+}
+
+impl<T: SupersetOf<f64>> Default for RelocalizeConfig<T> {
+    fn default() -> Self {
+        Self {
+            grid_size: nalgebra::convert(0.5),
+            yaw_steps: 12,
+            max_candidates: 10_000,
+            beam_width: 50,
+            ground_min_normal_z: nalgebra::convert(0.9),
+            sensor_height: nalgebra::convert(1.0),
+            sample_points: 100,
+            refine_candidates: 5,
+            point_cov_inflation: nalgebra::convert(0.01),
+            max_iterations: 20,
+            converge_thresh: nalgebra::convert(1e-4),
+            min_fitness: nalgebra::convert(0.8),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        algorithm::lio::NoGravityConfig,
+        frame::WorldPoint,
+        voxel_map::{Config as MapConfig, VoxelMap},
+    };
+
+    /// The points on the rectangle from `origin` spanned by `u` and `v`.
+    fn rectangle(
+        origin: Vector3<f64>,
+        u: Vector3<f64>,
+        v: Vector3<f64>,
+    ) -> impl Iterator<Item = Vector3<f64>> {
+        let step = 0.2;
+        let (nu, nv) = ((u.norm() / step) as usize, (v.norm() / step) as usize);
+        itertools::iproduct!(0..=nu, 0..=nv)
+            .map(move |(i, j)| origin + u * (i as f64 / nu as f64) + v * (j as f64 / nv as f64))
+    }
+
+    /// The ground with the walls and a box around, which has no symmetry,
+    /// offset from the voxel boundaries of the map.
+    fn scene() -> Vec<Vector3<f64>> {
+        let offset = Vector3::new(0.13, 0.07, 0.11);
+        let (x, y, z) = (Vector3::x(), Vector3::y(), Vector3::z());
+        let ground = rectangle(Vector3::new(-10.0, -10.0, 0.0), x * 20.0, y * 20.0);
+        let walls = [
+            rectangle(Vector3::new(-10.0, -10.0, 0.0), y * 20.0, z * 3.0),
+            rectangle(Vector3::new(-10.0, 10.0, 0.0), x * 20.0, z * 3.0),
+            rectangle(Vector3::new(6.0, 0.0, 0.0), y * 5.0, z * 2.0),
+        ];
+        let boxes = [
+            rectangle(Vector3::new(2.0, -6.0, 0.0), x * 2.0, z * 2.0),
+            rectangle(Vector3::new(2.0, -3.0, 0.0), x * 2.0, z * 2.0),
+            rectangle(Vector3::new(2.0, -6.0, 0.0), y * 3.0, z * 2.0),
+            rectangle(Vector3::new(4.0, -6.0, 0.0), y * 3.0, z * 2.0),
+        ];
+        ground
+            .chain(walls.into_iter().flatten())
+            .chain(boxes.into_iter().flatten())
+            .map(|point| point + offset)
+            .collect()
+    }
+
+    /// The LIO with the map of the [`scene`], whose corners have no planes to register against.
+    fn new_lio() -> LIO<f64> {
+        let mut map = VoxelMap::new(MapConfig::default());
+        map.extend(
+            scene()
+                .into_iter()
+                .map(|point| UncertainWorldPoint::new(WorldPoint::new(point.into()))),
+        );
+        let mut lio =
+            LIO::new_with_gravity_factor(NoGravityConfig::default(), 0.0, 1.0).with_map(map);
+        lio.relocalize.min_fitness = 0.6;
+        lio
+    }
+
+    fn true_pose() -> IsometryMatrix3<f64> {
+        IsometryMatrix3::new(Vector3::new(1.3, -2.1, 1.11), Vector3::z() * 2.0)
+    }
+
+    /// The scene points near the `pose` in the IMU frame.
+    fn scan(pose: &IsometryMatrix3<f64>) -> Vec<Vector3<f64>> {
+        scene()
+            .into_iter()
+            .filter(|point| (point - pose.translation.vector).norm() < 15.0)
+            .step_by(3)
+            .map(|point| pose.inverse_transform_vector(&(point - pose.translation.vector)))
+            .collect()
+    }
+
+    #[test]
+    fn test_global_relocalization() {
+        let mut lio = new_lio();
+        let truth = true_pose();
+        let report = lio.relocalize(None, [scan(&truth)]);
+
+        assert!(report.success);
+        assert!(report.fitness >= lio.relocalize.min_fitness);
+        let error = truth.inv_mul(&report.pose);
+        assert!(error.translation.vector.norm() < 0.05);
+        assert!(error.rotation.angle() < 0.01);
+        // the state is seeded with the registered pose
+        assert_eq!(lio.get_pose().deref(), report.pose.deref());
+    }
+
+    #[test]
+    fn test_fitness_threshold() {
+        let truth = true_pose();
+        let mut points = scan(&truth);
+        // as many points far away from the map
+        let outliers = points
+            .iter()
+            .map(|point| point + Vector3::new(0.0, 0.0, 30.0))
+            .collect::<Vec<_>>();
+        points.extend(outliers);
+
+        let mut lio = new_lio();
+        let pose = lio.get_pose().clone();
+        let guess = Framed::new(truth);
+        let report = lio.relocalize(Some(guess.clone()), [points.clone()]);
+        assert!(!report.success);
+        assert!(report.fitness > 0.3 && report.fitness < lio.relocalize.min_fitness);
+        assert_eq!(lio.get_pose().deref(), pose.deref());
+
+        let mut lio = new_lio();
+        lio.relocalize.min_fitness = 0.3;
+        let report = lio.relocalize(Some(guess), [points]);
+        assert!(report.success);
+    }
+
+    #[test]
+    fn test_relocalization_failure() {
+        // nothing to register against
+        let mut lio = LIO::new_with_gravity_factor(NoGravityConfig::default(), 0.0, 1.0);
+        let pose = lio.get_pose().clone();
+        let report = lio.relocalize(None, [scan(&true_pose())]);
+        assert!(!report.success);
+        assert_eq!(report.fitness, 0.0);
+        assert_eq!(report.pose.deref(), pose.deref());
+
+        // no points
+        let mut lio = new_lio();
+        let report = lio.relocalize(None, [Vec::<Vector3<f64>>::new()]);
+        assert!(!report.success);
+        assert_eq!(report.fitness, 0.0);
+    }
+}
@@ -1,10 +1,26 @@
 use odometries_macros::{KFState, VectorAddAssign, sub_state_of};
 
-use crate::eskf::state::{common::*, macro_export::*};
-
-use nalgebra::{RealField, Scalar};
-
-#[derive(KFState, VectorAddAssign)]
+use crate::{
+    eskf::{
+        Eskf, StatePredictor,
+        state::{
+            common::*,
+            correlation::{CorrelateTo, UnbiasedState},
+            macro_export::*,
+        },
+    },
+    frame::{IsometryFramed, frames},
+    voxel_map::VoxelMap,
+};
+
+use nalgebra::{ComplexField, DefaultAllocator, RealField, Scalar, allocator::Allocator};
+
+use super::{
+    extrinsic::BodyToImu,
+    measurement::{MeasureNoiseConfig, PointsObserveStats, PointsObserved, ProcessingPoint},
+};
+
+#[derive(Clone, KFState, VectorAddAssign)]
 #[element(T)]
 #[vector_add_assign(predicates(RealField))]
 pub struct State<T: Scalar> {
@@ -31,6 +47,73 @@ struct AccState<T: Scalar>(LinearAccState<T>, AngularAccState<T>);
 #[sub_state_of(State)]
 struct BiasState<T: Scalar>(LinearAccBiasState<T>, AngularAccBiasState<T>);
 
+/// A state containing the LIO [`State`], which can be estimated by [`LIO`](super::LIO),
+/// e.g. the [`State`](crate::algorithm::kilo::state::State) of Kilo.
+pub trait LioState<T: ComplexField>:
+    KFState<Element = T> + AsRef<State<T>> + AsMut<State<T>> + StatePredictor<T> + Clone + Default
+{
+    /// The sub-state observed by the lidar points, e.g. the [`PoseState`],
+    /// see also [`PointsObserved`].
+    type PointsState: CorrelateTo<Self, Element = T>;
+
+    /// The extrinsics of the IMU to the body frame estimated in the state,
+    /// `None` means the configured [`extrinsics`](super::Config::extrinsics) are fixed.
+    #[inline]
+    fn extrinsics(&self) -> Option<&BodyToImu<T>> {
+        None
+    }
+
+    /// The time offset of the lidar points to the IMU estimated in the state,
+    /// `None` means the configured [`time_offset`](super::Config::time_offset) is fixed.
+    #[inline]
+    fn time_offset(&self) -> Option<&T> {
+        None
+    }
+
+    /// Observe the point-to-plane distances of the `points` against the `map`,
+    /// and accumulate the statistics of the observed points into `stats`.
+    fn observe_points(
+        eskf: &Eskf<Self>,
+        map: &VoxelMap<T>,
+        measure_noise: &MeasureNoiseConfig<T>,
+        body_to_world: &IsometryFramed<T, fn(frames::Body) -> frames::World>,
+        points: &[ProcessingPoint<T>],
+        stats: &mut PointsObserveStats<T>,
+    ) -> Option<PointsObserved<T, Self>>
+    where
+        DefaultAllocator: Allocator<Self::Dim, Self::Dim>;
+}
+
+impl<T: RealField> LioState<T> for State<T> {
+    type PointsState = UnbiasedState<PoseState<T>>;
+
+    #[inline]
+    fn observe_points(
+        eskf: &Eskf<Self>,
+        map: &VoxelMap<T>,
+        measure_noise: &MeasureNoiseConfig<T>,
+        body_to_world: &IsometryFramed<T, fn(frames::Body) -> frames::World>,
+        points: &[ProcessingPoint<T>],
+        stats: &mut PointsObserveStats<T>,
+    ) -> Option<PointsObserved<T, Self>> {
+        eskf.observe_pose_points(map, measure_noise, body_to_world, points, stats)
+    }
+}
+
+impl<T: Scalar> AsRef<State<T>> for State<T> {
+    #[inline(always)]
+    fn as_ref(&self) -> &State<T> {
+        self
+    }
+}
+
+impl<T: Scalar> AsMut<State<T>> for State<T> {
+    #[inline(always)]
+    fn as_mut(&mut self) -> &mut State<T> {
+        self
+    }
+}
+
 impl<T> Default for State<T>
 where
     T: RealField,
@@ -0,0 +1,279 @@
+//! Drive [`LIO`] by the asynchronous streams of the IMU and lidar measurements,
+//! see also [`LIO::into_stream`].
+
+use std::{
+    ops::AddAssign,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+use nalgebra::{DefaultAllocator, RealField, U3, U6, allocator::Allocator};
+
+use super::{
+    LIO,
+    measurement::{
+        ImuObserved, LidarPoint, PointsObserved, StampedImu, StampedPoints,
+        ZeroAngularRateObserved, ZeroVelocityObserved,
+    },
+    state::LioState,
+    sync::{SyncConfig, SyncReport, SyncedMeasurement, Synchronizer},
+    trajectory_point,
+};
+use crate::{
+    eskf::{
+        DeltaTime, ErrorState, Eskf, IteratedStateObserver, StateObserver, StatePredictor,
+        state::{
+            KFState, SubStateOf,
+            common::{
+                AccState, AccWithBiasState, AngularAccState, BiasState, PoseState, PositionState,
+                RotationState, VelocityState,
+            },
+        },
+    },
+    trajectory::TrajectoryPoint,
+    utils::ToRadians,
+};
+
+/// The odometry estimated by [`LIO`] from the streams of the IMU measurements `I`
+/// and the point clouds `L`, see also [`LIO::into_stream`].
+///
+/// A [`TrajectoryPoint`] is yielded after each update of the point cloud.
+/// The input streams are only polled when the output is polled,
+/// so a slow consumer holds the input streams back.
+pub struct OdometryStream<T, S, P, I, L>
+where
+    T: RealField,
+    S: KFState<Element = T>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
+{
+    lio: LIO<T, S>,
+    synchronizer: Synchronizer<T, P>,
+    /// The input streams, `None` once ended.
+    imus: Option<I>,
+    point_clouds: Option<L>,
+    /// The timestamps of the latest measurements polled from the input streams.
+    imu_timestamp: Option<T>,
+    points_timestamp: Option<T>,
+}
+
+impl<T, S> LIO<T, S>
+where
+    T: RealField + ToRadians,
+    S: LioState<T>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
+{
+    /// Turn into a stream of the odometry driven by the `imus` and the `point_clouds`,
+    /// which are reordered by the [`Synchronizer`] with the `sync_config`.
+    ///
+    /// The input streams need to be [`Unpin`], e.g. pinned by [`Box::pin`] or [`std::pin::pin!`].
+    pub fn into_stream<P, I, L>(
+        self,
+        imus: I,
+        point_clouds: L,
+        sync_config: SyncConfig<T>,
+    ) -> OdometryStream<T, S, P, I, L>
+    where
+        I: Stream<Item = StampedImu<T>> + Unpin,
+        L: Stream<Item = StampedPoints<T, P>> + Unpin,
+    {
+        OdometryStream {
+            lio: self,
+            synchronizer: Synchronizer::new(sync_config),
+            imus: Some(imus),
+            point_clouds: Some(point_clouds),
+            imu_timestamp: None,
+            points_timestamp: None,
+        }
+    }
+}
+
+impl<T, S, P, I, L> OdometryStream<T, S, P, I, L>
+where
+    T: RealField,
+    S: KFState<Element = T>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
+{
+    #[inline]
+    pub fn lio(&self) -> &LIO<T, S> {
+        &self.lio
+    }
+
+    /// The report of the measurements pushed so far, including the late ones dropped.
+    #[inline]
+    pub fn report(&self) -> &SyncReport {
+        self.synchronizer.report()
+    }
+
+    #[inline]
+    pub fn into_lio(self) -> LIO<T, S> {
+        self.lio
+    }
+}
+
+impl<T, S, P, I, L> Stream for OdometryStream<T, S, P, I, L>
+where
+    T: RealField + ToRadians,
+    S: LioState<T> + AddAssign<ErrorState<S>>,
+    PoseState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    RotationState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    PositionState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    AccWithBiasState<T>: SubStateOf<S, Element = T>,
+    AccState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    BiasState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    VelocityState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    AngularAccState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    Eskf<S>: StatePredictor<DeltaTime<T>>
+        + StateObserver<ImuObserved<T, S>>
+        + StateObserver<ZeroVelocityObserved<T, S>>
+        + StateObserver<ZeroAngularRateObserved<T, S>>
+        + IteratedStateObserver<S, PointsObserved<T, S>>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim> + Allocator<S::Dim>,
+    P: IntoIterator<Item: LidarPoint<T>>,
+    I: Stream<Item = StampedImu<T>> + Unpin,
+    L: Stream<Item = StampedPoints<T, P>> + Unpin,
+    Self: Unpin,
+{
+    type Item = TrajectoryPoint<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            // feed the released measurements until a point cloud is updated
+            this.synchronizer
+                .set_time_offset(this.lio.time_offset().clone());
+            while let Some(measurement) = this.synchronizer.pop() {
+                match measurement {
+                    SyncedMeasurement::Imu(imu) => this.lio.extend([imu]),
+                    SyncedMeasurement::Points(points) => {
+                        this.lio.update_stamped_points(points);
+                        let timestamp = this.lio.eskf.last_update_time.predict.clone();
+                        return Poll::Ready(Some(trajectory_point(timestamp, &this.lio.eskf)));
+                    }
+                }
+                this.synchronizer
+                    .set_time_offset(this.lio.time_offset().clone());
+            }
+
+            if this.imus.is_none() && this.point_clouds.is_none() {
+                if this.synchronizer.is_empty() {
+                    return Poll::Ready(None);
+                }
+                this.synchronizer.flush();
+                continue;
+            }
+
+            // pull the input behind first, and the other one only if it is pending
+            let is_imus_behind = match (&this.imu_timestamp, &this.points_timestamp) {
+                (Some(imu), Some(points)) => imu <= points,
+                (_, None) => this.point_clouds.is_none(),
+                (None, Some(_)) => true,
+            };
+            let is_polled = if is_imus_behind {
+                this.poll_imus(cx).is_ready() || this.poll_point_clouds(cx).is_ready()
+            } else {
+                this.poll_point_clouds(cx).is_ready() || this.poll_imus(cx).is_ready()
+            };
+            if !is_polled {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+impl<T, S, P, I, L> OdometryStream<T, S, P, I, L>
+where
+    T: RealField,
+    S: KFState<Element = T>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
+    I: Stream<Item = StampedImu<T>> + Unpin,
+    L: Stream<Item = StampedPoints<T, P>> + Unpin,
+{
+    /// Poll the IMU stream into the synchronizer, `Ready` if an IMU measurement or the end is polled.
+    ///
+    /// The late measurements are dropped and counted in the report.
+    fn poll_imus(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let Some(imus) = &mut self.imus else {
+            return Poll::Pending;
+        };
+        match Pin::new(imus).poll_next(cx) {
+            Poll::Ready(Some(imu)) => {
+                self.imu_timestamp = Some(imu.timestamp.clone());
+                let _ = self.synchronizer.push_imu(imu);
+            }
+            Poll::Ready(None) => self.imus = None,
+            Poll::Pending => return Poll::Pending,
+        }
+        Poll::Ready(())
+    }
+
+    /// Poll the point cloud stream into the synchronizer, see also [`OdometryStream::poll_imus`].
+    fn poll_point_clouds(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let Some(point_clouds) = &mut self.point_clouds else {
+            return Poll::Pending;
+        };
+        match Pin::new(point_clouds).poll_next(cx) {
+            Poll::Ready(Some(points)) => {
+                self.points_timestamp = Some(points.timestamp.clone());
+                let _ = self.synchronizer.push_points(points);
+            }
+            Poll::Ready(None) => self.point_clouds = None,
+            Poll::Pending => return Poll::Pending,
+        }
+        Poll::Ready(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use smol::stream::{self, StreamExt};
+
+    use crate::algorithm::lio::{self, ImuInit, ImuMeasured};
+
+    use super::*;
+
+    #[test]
+    fn test_odometry_stream() {
+        let imu_init = (0..10)
+            .map(|i| {
+                StampedImu::new(
+                    i as f64 * 0.01,
+                    ImuMeasured::new(0.0, 0.0, 9.81, 0.0, 0.0, 0.0),
+                )
+            })
+            .collect::<Option<ImuInit<f64>>>();
+        let Some(imu_init) = imu_init else {
+            panic!("failed to init the IMU");
+        };
+        let lio = imu_init.new_lio(lio::Config::default());
+
+        let imus = stream::iter((10..200).map(|i| {
+            StampedImu::new(
+                i as f64 * 0.01,
+                ImuMeasured::new(0.0, 0.0, 9.81, 0.0, 0.0, 0.0),
+            )
+        }));
+        let point_clouds = stream::iter((1..20).map(|i| {
+            let points = (0..96)
+                .map(|t| {
+                    let angle = core::f64::consts::PI * t as f64 / 48.0;
+                    Vector3::new(
+                        1.7 * angle.cos(),
+                        1.7 * angle.sin(),
+                        0.3 + (t % 3) as f64 * 0.1,
+                    )
+                })
+                .collect::<Vec<_>>();
+            StampedPoints::new(0.1 + i as f64 * 0.1, points)
+        }));
+
+        let mut odometry = lio.into_stream(imus, point_clouds, Default::default());
+        let outputs = smol::block_on((&mut odometry).collect::<Vec<_>>());
+
+        assert_eq!(outputs.len(), 19);
+        assert!(outputs.is_sorted_by(|a, b| a.timestamp < b.timestamp));
+        assert_eq!(odometry.report().imus, 190);
+        assert_eq!(odometry.report().late_imus, 0);
+    }
+}
@@ -0,0 +1,389 @@
+//! Buffer the IMU and lidar streams arriving in any order, and feed [`LIO`] in the timestamp order.
+
+use std::{
+    collections::VecDeque,
+    fmt::{self, Debug, Display},
+    ops::AddAssign,
+};
+
+use nalgebra::{DefaultAllocator, RealField, Scalar, U3, U6, allocator::Allocator};
+use simba::scalar::SupersetOf;
+
+use super::{
+    LIO,
+    measurement::{
+        ImuObserved, LidarPoint, PointsObserved, StampedImu, StampedPoints,
+        ZeroAngularRateObserved, ZeroVelocityObserved,
+    },
+    state::LioState,
+};
+use crate::{
+    eskf::{
+        DeltaTime, ErrorState, Eskf, IteratedStateObserver, StateObserver, StatePredictor,
+        state::{
+            SubStateOf,
+            common::{
+                AccState, AccWithBiasState, AngularAccState, BiasState, PoseState, PositionState,
+                RotationState, VelocityState,
+            },
+        },
+    },
+    utils::ToRadians,
+};
+
+#[derive(Debug, Clone)]
+pub struct SyncConfig<T> {
+    /// The maximum time a measurement is held for the reordering, in seconds.
+    ///
+    /// A measurement is released once another measurement later than it by this latency arrives,
+    /// the measurements arriving after a later one is released are late and dropped.
+    pub max_latency: T,
+}
+
+impl<T: SupersetOf<f64>> Default for SyncConfig<T> {
+    fn default() -> Self {
+        Self {
+            max_latency: nalgebra::convert(0.1),
+        }
+    }
+}
+
+/// A measurement released by the [`Synchronizer`].
+pub enum SyncedMeasurement<T: Scalar, P> {
+    Imu(StampedImu<T>),
+    Points(StampedPoints<T, P>),
+}
+
+/// The numbers of the measurements pushed into the [`Synchronizer`], including the late ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    pub imus: usize,
+    pub point_clouds: usize,
+    /// The number of the IMU measurements dropped as late, see also [`LateMeasurement`].
+    pub late_imus: usize,
+    /// The number of the point clouds dropped as late, see also [`LateMeasurement`].
+    pub late_point_clouds: usize,
+}
+
+/// A measurement arriving after a later one is released, which can't be fed in order.
+#[derive(Debug)]
+pub struct LateMeasurement<T, M> {
+    pub measurement: M,
+    /// The timestamp of the measurement on the IMU clock.
+    pub timestamp: T,
+    /// The timestamp of the latest released measurement.
+    pub released: T,
+}
+
+/// Reorder the IMU measurements and the point clouds by timestamp with a bounded latency,
+/// see also [`SyncConfig::max_latency`] and [`LIO::update_synchronized`].
+///
+/// The timestamps of the point clouds are shifted by the time offset onto the IMU clock when pushed,
+/// which follows the [`LIO::time_offset`] once synchronized by the LIO.
+pub struct Synchronizer<T: Scalar, P> {
+    pub config: SyncConfig<T>,
+    imus: VecDeque<StampedImu<T>>,
+    /// The point clouds along with their timestamps on the IMU clock.
+    point_clouds: VecDeque<(T, StampedPoints<T, P>)>,
+    time_offset: T,
+    /// The timestamp of the latest measurement pushed.
+    latest: Option<T>,
+    /// The timestamp of the latest measurement released.
+    released: Option<T>,
+    is_flushing: bool,
+    report: SyncReport,
+}
+
+impl<T: RealField, P> Synchronizer<T, P> {
+    pub fn new(config: SyncConfig<T>) -> Self {
+        Self {
+            config,
+            imus: VecDeque::new(),
+            point_clouds: VecDeque::new(),
+            time_offset: T::zero(),
+            latest: None,
+            released: None,
+            is_flushing: false,
+            report: SyncReport::default(),
+        }
+    }
+
+    #[inline]
+    pub fn report(&self) -> &SyncReport {
+        &self.report
+    }
+
+    /// The number of the buffered measurements.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.imus.len() + self.point_clouds.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.imus.is_empty() && self.point_clouds.is_empty()
+    }
+
+    /// Set the time offset added to the timestamps of the point clouds to get their IMU time.
+    ///
+    /// Only the point clouds pushed afterwards are shifted by the new offset,
+    /// so the buffered ones are still released in order with the released measurements.
+    #[inline]
+    pub fn set_time_offset(&mut self, time_offset: T) {
+        self.time_offset = time_offset;
+    }
+
+    /// Buffer the IMU measurement, or return it back if it is late.
+    pub fn push_imu(
+        &mut self,
+        imu: StampedImu<T>,
+    ) -> Result<(), LateMeasurement<T, StampedImu<T>>> {
+        self.report.imus += 1;
+        let timestamp = imu.timestamp.clone();
+        if let Err(released) = self.accept(&timestamp) {
+            self.report.late_imus += 1;
+            return Err(LateMeasurement {
+                measurement: imu,
+                timestamp,
+                released,
+            });
+        }
+        let index = self
+            .imus
+            .partition_point(|buffered| buffered.timestamp <= timestamp);
+        self.imus.insert(index, imu);
+        Ok(())
+    }
+
+    /// Buffer the point cloud, or return it back if it is late.
+    pub fn push_points(
+        &mut self,
+        points: StampedPoints<T, P>,
+    ) -> Result<(), LateMeasurement<T, StampedPoints<T, P>>> {
+        self.report.point_clouds += 1;
+        let timestamp = points.timestamp.clone() + self.time_offset.clone();
+        if let Err(released) = self.accept(&timestamp) {
+            self.report.late_point_clouds += 1;
+            return Err(LateMeasurement {
+                measurement: points,
+                timestamp,
+                released,
+            });
+        }
+        let index = self
+            .point_clouds
+            .partition_point(|(buffered, _)| *buffered <= timestamp);
+        self.point_clouds.insert(index, (timestamp, points));
+        Ok(())
+    }
+
+    /// Release every buffered measurement regardless of the latency, e.g. at the end of the streams.
+    #[inline]
+    pub fn flush(&mut self) {
+        self.is_flushing = true;
+    }
+
+    /// Release the earliest buffered measurement if it is held long enough, see also [`SyncConfig::max_latency`].
+    ///
+    /// The IMU measurement is released first if it has the same timestamp as the point cloud.
+    pub fn pop(&mut self) -> Option<SyncedMeasurement<T, P>> {
+        let imu_timestamp = self.imus.front().map(|imu| imu.timestamp.clone());
+        let points_timestamp = self
+            .point_clouds
+            .front()
+            .map(|(timestamp, _)| timestamp.clone());
+
+        let (timestamp, is_imu) = match (imu_timestamp, points_timestamp) {
+            (Some(imu), Some(points)) if points < imu => (points, false),
+            (Some(imu), _) => (imu, true),
+            (None, Some(points)) => (points, false),
+            (None, None) => {
+                self.is_flushing = false;
+                return None;
+            }
+        };
+
+        let is_held = self
+            .latest
+            .as_ref()
+            .is_some_and(|latest| timestamp.clone() + self.config.max_latency.clone() <= *latest);
+        if !self.is_flushing && !is_held {
+            return None;
+        }
+
+        self.released = Some(timestamp);
+        if is_imu {
+            self.imus.pop_front().map(SyncedMeasurement::Imu)
+        } else {
+            self.point_clouds
+                .pop_front()
+                .map(|(_, points)| SyncedMeasurement::Points(points))
+        }
+    }
+
+    /// Check the `timestamp` is not earlier than the released ones, and update the latest one.
+    ///
+    /// Returns the timestamp of the latest released measurement if late.
+    fn accept(&mut self, timestamp: &T) -> Result<(), T> {
+        if let Some(released) = self
+            .released
+            .as_ref()
+            .filter(|released| timestamp < *released)
+        {
+            return Err(released.clone());
+        }
+        if self.latest.as_ref().is_none_or(|latest| timestamp > latest) {
+            self.latest = Some(timestamp.clone());
+        }
+        Ok(())
+    }
+}
+
+impl<T: RealField, P> Default for Synchronizer<T, P> {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<T, S> LIO<T, S>
+where
+    T: RealField + ToRadians,
+    S: LioState<T> + AddAssign<ErrorState<S>>,
+    PoseState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    RotationState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    PositionState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    AccWithBiasState<T>: SubStateOf<S, Element = T>,
+    AccState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    BiasState<T>: SubStateOf<S, Element = T, Dim = U6>,
+    VelocityState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    AngularAccState<T>: SubStateOf<S, Element = T, Dim = U3>,
+    Eskf<S>: StatePredictor<DeltaTime<T>>
+        + StateObserver<ImuObserved<T, S>>
+        + StateObserver<ZeroVelocityObserved<T, S>>
+        + StateObserver<ZeroAngularRateObserved<T, S>>
+        + IteratedStateObserver<S, PointsObserved<T, S>>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim> + Allocator<S::Dim>,
+{
+    /// Update with the measurements released by the `synchronizer` in the timestamp order,
+    /// and keep its time offset following [`LIO::time_offset`].
+    pub fn update_synchronized<P>(&mut self, synchronizer: &mut Synchronizer<T, P>)
+    where
+        P: IntoIterator<Item: LidarPoint<T>>,
+    {
+        loop {
+            synchronizer.set_time_offset(self.time_offset().clone());
+            match synchronizer.pop() {
+                Some(SyncedMeasurement::Imu(imu)) => self.extend([imu]),
+                Some(SyncedMeasurement::Points(points)) => {
+                    self.update_stamped_points(points);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+impl<T: Display, M> Display for LateMeasurement<T, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the measurement at {} arrives after the one at {} is released",
+            self.timestamp, self.released
+        )
+    }
+}
+
+impl<T: Debug + Display, M: Debug> std::error::Error for LateMeasurement<T, M> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn imu(timestamp: f64) -> StampedImu<f64> {
+        StampedImu::zeros(timestamp)
+    }
+
+    fn released(synchronizer: &mut Synchronizer<f64, ()>) -> Vec<(f64, bool)> {
+        std::iter::from_fn(|| synchronizer.pop())
+            .map(|measurement| match measurement {
+                SyncedMeasurement::Imu(imu) => (imu.timestamp, true),
+                SyncedMeasurement::Points(points) => (points.timestamp, false),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_synchronizer_reorder() {
+        let mut synchronizer = Synchronizer::new(SyncConfig { max_latency: 1.0 });
+
+        assert!(synchronizer.push_imu(imu(1.0)).is_ok());
+        assert!(
+            synchronizer
+                .push_points(StampedPoints::new(2.0, ()))
+                .is_ok()
+        );
+        assert!(synchronizer.push_imu(imu(0.0)).is_ok());
+        assert_eq!(released(&mut synchronizer), [(0.0, true), (1.0, true)]);
+
+        assert!(synchronizer.push_imu(imu(2.0)).is_ok());
+        assert!(released(&mut synchronizer).is_empty());
+
+        assert!(synchronizer.push_imu(imu(3.0)).is_ok());
+        assert_eq!(released(&mut synchronizer), [(2.0, true), (2.0, false)]);
+
+        // the measurements before the released ones are late
+        let late = synchronizer.push_imu(imu(1.5));
+        assert!(late.is_err_and(|late| late.released == 2.0));
+        assert!(
+            synchronizer
+                .push_points(StampedPoints::new(0.5, ()))
+                .is_err()
+        );
+
+        synchronizer.flush();
+        assert_eq!(released(&mut synchronizer), [(3.0, true)]);
+        assert!(synchronizer.is_empty());
+        assert_eq!(
+            synchronizer.report(),
+            &SyncReport {
+                imus: 5,
+                point_clouds: 2,
+                late_imus: 1,
+                late_point_clouds: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_synchronizer_time_offset_change() {
+        let mut synchronizer = Synchronizer::new(SyncConfig { max_latency: 1.0 });
+        // the timestamps of the released measurements on the IMU clock
+        let released = |synchronizer: &mut Synchronizer<f64, ()>| {
+            std::iter::from_fn(|| synchronizer.pop().and(synchronizer.released)).collect::<Vec<_>>()
+        };
+
+        assert!(synchronizer.push_imu(imu(0.0)).is_ok());
+        assert!(
+            synchronizer
+                .push_points(StampedPoints::new(2.0, ()))
+                .is_ok()
+        );
+        assert!(synchronizer.push_imu(imu(1.0)).is_ok());
+        assert!(synchronizer.push_imu(imu(2.5)).is_ok());
+        assert_eq!(released(&mut synchronizer), [0.0, 1.0]);
+
+        // the buffered point cloud keeps the offset it is pushed with
+        synchronizer.set_time_offset(-1.5);
+        assert!(synchronizer.push_imu(imu(4.0)).is_ok());
+        assert_eq!(released(&mut synchronizer), [2.0, 2.5]);
+
+        // while the ones pushed afterwards are shifted by the new offset
+        assert!(
+            synchronizer
+                .push_points(StampedPoints::new(5.0, ()))
+                .is_ok()
+        );
+        synchronizer.flush();
+        assert_eq!(released(&mut synchronizer), [3.5, 4.0]);
+    }
+}
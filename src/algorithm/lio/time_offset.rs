@@ -0,0 +1,338 @@
+//! The online estimation of the time offset of the lidar points to the IMU,
+//! which is estimated as the [`TimeOffsetState`] along with the LIO [`State`](super::State).
+//!
+//! The timestamps of the lidar points are shifted by the time offset onto the IMU clock,
+//! so the lidar points observation is then w.r.t. both the pose and the time offset,
+//! see also [`LioState::observe_points`].
+
+use std::ops::Deref;
+
+use nalgebra::{DimName, Matrix1, OVector, RealField, Rotation3, Scalar, Vector3};
+use num_traits::Zero;
+use odometries_macros::{KFState, VectorAddAssign, sub_state_of};
+use simba::scalar::SupersetOf;
+
+use super::{
+    ImuInit, LIO,
+    config::{NoGravityConfig, StateProcessCovConfig},
+    measurement::{
+        MeasureNoiseConfig, PointsObserveStats, PointsObserved, ProcessingPoint,
+        observe_point_rows, point_model,
+    },
+    state::LioState,
+};
+use crate::{
+    algorithm::lio,
+    eskf::{
+        Covariance, DeltaTime, Eskf, StatePredictor,
+        state::{StateDim, common::*, correlation::FullState, macro_export::*},
+        uncertain::Uncertained,
+    },
+    frame::{CrossMatrixFramed, IsometryFramed, frames},
+    utils::ToRadians,
+    voxel_map::VoxelMap,
+};
+
+pub struct TimeOffset;
+/// The time offset added to the timestamps of the lidar points to get their IMU time, in seconds.
+pub type TimeOffsetState<T> = ScalarState<T, TimeOffset>;
+
+/// The LIO state with the time offset estimated online, see also [`LIO::new_time_offset_estimated`].
+#[derive(Clone, KFState, VectorAddAssign)]
+#[element(T)]
+#[vector_add_assign(predicates(RealField))]
+pub struct State<T>
+where
+    T: Scalar,
+{
+    pub state: lio::state::State<T>,
+    pub time_offset: TimeOffsetState<T>,
+}
+
+type InnerState<T> = lio::state::State<T>;
+
+#[sub_state_of(State)]
+struct InnerState<T: Scalar>(
+    PoseState<T>,
+    VelocityState<T>,
+    GravityState<T>,
+    AccWithBiasState<T>,
+);
+
+#[sub_state_of(State)]
+struct PoseState<T: Scalar>(RotationState<T>, PositionState<T>);
+
+#[sub_state_of(State)]
+struct AccWithBiasState<T: Scalar>(AccState<T>, BiasState<T>);
+
+#[sub_state_of(State)]
+struct AccState<T: Scalar>(LinearAccState<T>, AngularAccState<T>);
+
+#[sub_state_of(State)]
+struct BiasState<T: Scalar>(LinearAccBiasState<T>, AngularAccBiasState<T>);
+
+/// The time offset of the lidar points to the IMU, see also [`Config::time_offset`](super::Config::time_offset).
+#[derive(Clone)]
+pub struct TimeOffsetConfig<T> {
+    /// The time offset added to the timestamps of the lidar points to get their IMU time,
+    /// which is fixed unless estimated by [`LIO::new_time_offset_estimated`].
+    pub init: T,
+
+    /// The initial variance of the estimated time offset, larger for a rougher initial guess.
+    pub init_cov: T,
+
+    /// The process noise of the estimated time offset, which drifts slowly if any.
+    pub process_cov: T,
+}
+
+impl<T: SupersetOf<f64>> Default for TimeOffsetConfig<T> {
+    fn default() -> Self {
+        Self {
+            init: nalgebra::convert(0.0),
+            init_cov: nalgebra::convert(1e-4),
+            process_cov: nalgebra::convert(1e-6),
+        }
+    }
+}
+
+impl<T> StatePredictor<T> for State<T>
+where
+    T: RealField,
+{
+    /// The time offset is constant during the prediction.
+    #[inline]
+    fn predict(&mut self, dt: T) {
+        self.state.predict(dt);
+    }
+}
+
+impl<T> StatePredictor<DeltaTime<T>> for Eskf<State<T>>
+where
+    T: RealField,
+{
+    fn predict(&mut self, dt: DeltaTime<T>) {
+        self.state.predict(dt.predict);
+        self.predict_cov(dt.observe);
+    }
+}
+
+impl<T: RealField> LioState<T> for State<T> {
+    type PointsState = FullState<Self>;
+
+    #[inline]
+    fn time_offset(&self) -> Option<&T> {
+        Some(&self.time_offset[0])
+    }
+
+    fn observe_points(
+        eskf: &Eskf<Self>,
+        map: &VoxelMap<T>,
+        measure_noise: &MeasureNoiseConfig<T>,
+        body_to_world: &IsometryFramed<T, fn(frames::Body) -> frames::World>,
+        points: &[ProcessingPoint<T>],
+        stats: &mut PointsObserveStats<T>,
+    ) -> Option<PointsObserved<T, Self>> {
+        let state = &eskf.state.state;
+        let rotation = &state.pose.rotation;
+        let angular = state.acc_with_bias.acc.angular.deref();
+        let velocity = state.velocity.deref();
+        let pose_offset = SubStateOffset::<PoseState<T>, Self>::DIM;
+        let time_offset_offset = SubStateOffset::<TimeOffsetState<T>, Self>::DIM;
+
+        let observation: PointsObserved<T, Self> = observe_point_rows(
+            points,
+            stats,
+            |(body_point, world_point, cross_matrix_imu), counts| {
+                let Uncertained {
+                    state: residual,
+                    cov: residual_cov,
+                } = map
+                    .get_or_nearest_residual_counted(world_point, counts)?
+                    .to_uncertained(body_point, body_to_world);
+
+                let plane_normal = residual.plane_normal();
+
+                let mut model = OVector::<T, StateDim<Self>>::zeros();
+                let pose_model = point_model(cross_matrix_imu, rotation, plane_normal);
+                model
+                    .fixed_rows_mut::<6>(pose_offset)
+                    .copy_from(&pose_model);
+                model[time_offset_offset] =
+                    time_offset_model(cross_matrix_imu, rotation, plane_normal, angular, velocity);
+
+                let measurement = -residual.distance_to_plane;
+                let noise =
+                    measure_noise.lidar_point_noise(&measurement, residual_cov.to_scalar())?;
+
+                Some((measurement, model, noise, pose_model))
+            },
+        );
+
+        if observation.get_dim().0 == 0 {
+            return None;
+        }
+        Some(observation)
+    }
+}
+
+impl<T: Scalar> AsRef<InnerState<T>> for State<T> {
+    #[inline(always)]
+    fn as_ref(&self) -> &InnerState<T> {
+        &self.state
+    }
+}
+
+impl<T: Scalar> AsMut<InnerState<T>> for State<T> {
+    #[inline(always)]
+    fn as_mut(&mut self) -> &mut InnerState<T> {
+        &mut self.state
+    }
+}
+
+impl<T> Default for State<T>
+where
+    T: RealField,
+{
+    fn default() -> Self {
+        Self {
+            state: Default::default(),
+            time_offset: Default::default(),
+        }
+    }
+}
+
+/// The observation model of the point-to-plane distance w.r.t. the [`TimeOffsetState`],
+/// i.e. the change of the distance per unit time as the pose is predicted by
+/// [`State::predict_pose`](lio::state::State::predict_pose) with the angular velocity and the velocity.
+fn time_offset_model<T: RealField>(
+    cross_matrix_imu: &CrossMatrixFramed<T, frames::Imu>,
+    rotation: &Rotation3<T>,
+    plane_normal: &Vector3<T>,
+    angular: &Vector3<T>,
+    velocity: &Vector3<T>,
+) -> T {
+    let rotation_t_normal = rotation.transpose() * plane_normal;
+    (cross_matrix_imu.deref() * &rotation_t_normal).dot(angular) + rotation_t_normal.dot(velocity)
+}
+
+impl<T> From<(StateProcessCovConfig<T>, &TimeOffsetConfig<T>)> for Covariance<State<T>>
+where
+    T: Scalar + Zero,
+{
+    fn from((lio, time_offset): (StateProcessCovConfig<T>, &TimeOffsetConfig<T>)) -> Self {
+        let mut cov: Self = lio.into();
+        cov.sub_covariance_mut::<TimeOffsetState<T>>()
+            .fill(time_offset.process_cov.clone());
+        cov
+    }
+}
+
+impl<T> ImuInit<T>
+where
+    T: RealField + ToRadians,
+{
+    pub fn new_time_offset_estimated_lio(self, config: lio::Config<T>) -> LIO<T, State<T>> {
+        LIO::new_time_offset_estimated(config, self)
+    }
+}
+
+impl<T> LIO<T, State<T>>
+where
+    T: RealField + ToRadians,
+{
+    /// Create a new LIO instance estimating the time offset online,
+    /// starting from the [`time_offset`](lio::Config::time_offset) in the LIO configuration.
+    pub fn new_time_offset_estimated(config: lio::Config<T>, imu_init: ImuInit<T>) -> Self {
+        let (gravity, config) = config.take_gravity();
+        let gravity_factor = gravity / imu_init.linear_acc_norm.clone();
+
+        let mut lio = Self::new_time_offset_estimated_with_gravity_factor(
+            config,
+            imu_init.timestamp_init.clone(),
+            gravity_factor,
+        );
+        lio.init_with_imu(imu_init);
+        lio
+    }
+
+    /// Create a new LIO instance estimating the time offset online with a given gravity factor.
+    ///
+    /// This does not need the `gravity` in [`lio::Config<T>`], provide [`NoGravityConfig<T>`] instead.
+    pub fn new_time_offset_estimated_with_gravity_factor(
+        config: NoGravityConfig<T>,
+        timestamp_init: T,
+        gravity_factor: T,
+    ) -> Self {
+        let lio_process_cov = config
+            .imu_propagation
+            .state_process_cov(config.process_cov.state.clone());
+        let process_cov = (lio_process_cov, &config.time_offset).into();
+        let time_offset = config.time_offset.clone();
+        let mut lio =
+            LIO::new_with_process_cov(config, process_cov, timestamp_init, gravity_factor);
+
+        lio.eskf.state.time_offset = TimeOffsetState::new(Matrix1::new(time_offset.init));
+        lio.eskf
+            .cov
+            .sub_covariance_mut::<TimeOffsetState<T>>()
+            .fill(time_offset.init_cov);
+        lio
+    }
+
+    /// The estimated time offset with its variance.
+    pub fn estimated_time_offset(&self) -> Uncertained<TimeOffsetState<T>> {
+        let cov = self.eskf.cov.sub_covariance::<TimeOffsetState<T>>();
+        Uncertained::new_with_cov(
+            self.eskf.state.time_offset.clone(),
+            Matrix1::new(cov[(0, 0)].clone()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{IsometryMatrix3, Point3, Translation3};
+
+    use crate::frame::Framed;
+
+    use super::*;
+
+    #[test]
+    fn test_error_state_impl() {
+        type State = super::State<f64>;
+        assert_eq!(StateDim::<State>::DIM, 25);
+        assert_eq!(SubStateOffset::<PoseState<f64>, State>::DIM, 0);
+        assert_eq!(SubStateOffset::<AngularAccBiasState<f64>, State>::DIM, 21);
+        assert_eq!(SubStateOffset::<TimeOffsetState<f64>, State>::DIM, 24);
+    }
+
+    /// The distance of the point observed later changes by `model * δt` to the first order.
+    #[test]
+    fn test_time_offset_model() {
+        let mut state = lio::state::State::<f64>::default();
+        **state.pose = IsometryMatrix3::from_parts(
+            Translation3::new(1.0, 2.0, -1.0),
+            Rotation3::new(Vector3::new(-0.5, 0.2, 1.0)),
+        );
+        *state.velocity = Vector3::new(0.5, -1.0, 0.2);
+        *state.acc_with_bias.acc.angular = Vector3::new(0.3, 0.1, -0.4);
+
+        let imu_point = Vector3::new(3.0, -1.0, 0.5);
+        let plane_normal = Vector3::new(1.0, 2.0, -2.0).normalize();
+        let distance =
+            |dt: f64| plane_normal.dot(&(state.predict_pose(dt) * Point3::from(imu_point)).coords);
+
+        let cross_matrix_imu: CrossMatrixFramed<f64, frames::Imu> =
+            Framed::new(imu_point.cross_matrix());
+        let model = time_offset_model(
+            &cross_matrix_imu,
+            &state.pose.rotation,
+            &plane_normal,
+            &state.acc_with_bias.acc.angular,
+            &state.velocity,
+        );
+
+        let dt = 1e-4;
+        assert!((distance(dt) - distance(0.0) - model * dt).abs() < 1e-6);
+    }
+}
@@ -1,17 +1,22 @@
 //! Error‑State Kalman Filter.
 
-use core::ops::{Deref, DerefMut, Sub};
+use core::ops::{AddAssign, Deref, DerefMut, Sub};
 
-use nalgebra::{DefaultAllocator, allocator::Allocator};
+use nalgebra::{DefaultAllocator, OMatrix, OVector, RealField, allocator::Allocator};
 
+pub mod augment;
 mod covariance;
 pub mod observe;
+pub mod smoother;
 pub mod state;
 pub use covariance::Covariance;
 pub mod uncertain;
 
+use augment::Augmentation;
 use num_traits::{One, Zero};
+use observe::ObserveReport;
 use simba::scalar::SupersetOf;
+use smoother::Smoother;
 use state::KFState;
 
 use uncertain::Uncertained;
@@ -24,6 +29,10 @@ where
     uncertainty: Uncertained<S>,
     pub process_cov: Covariance<S>,
     pub last_update_time: KFTime<S::Element>,
+    /// The recorder of the history, `None` if the smoothing is disabled.
+    smoother: Option<Smoother<S>>,
+    /// The clones of the sub-states, see also [`augment`].
+    augmentation: Augmentation<S>,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -39,7 +48,53 @@ pub trait StatePredictor<T> {
 }
 
 pub trait StateObserver<T> {
-    fn observe(&mut self, measurement: T);
+    /// Observe the `measurement` and update the filter,
+    /// the rows of the measurement rejected as outliers are reported.
+    fn observe(&mut self, measurement: T) -> ObserveReport;
+}
+
+/// The error state vector, which is the difference between two states.
+pub type ErrorState<S> = OVector<<S as KFState>::Element, <S as KFState>::Dim>;
+
+/// The product of the kalman gain and the observation model, `K * H`.
+pub type GainModel<S> = OMatrix<<S as KFState>::Element, <S as KFState>::Dim, <S as KFState>::Dim>;
+
+/// A observer which can re-linearize the measurement around the current state,
+/// see also [`Eskf::update_iterated`].
+pub trait IteratedStateObserver<S, T>
+where
+    S: KFState,
+    DefaultAllocator: Allocator<S::Dim> + Allocator<S::Dim, S::Dim>,
+{
+    /// Observe the `measurement` linearized around the current state without modifying the filter.
+    ///
+    /// `error` is the error state from the prior state to the current state.
+    ///
+    /// Returns the new error state from the prior state and the [`GainModel`] of this observation,
+    /// along with the rows rejected as outliers, both are zero if the whole measurement is rejected.
+    fn observe_iterated(
+        &self,
+        measurement: T,
+        error: &ErrorState<S>,
+    ) -> (ErrorState<S>, GainModel<S>, ObserveReport);
+}
+
+/// The report of an iterated update, see also [`Eskf::update_iterated`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IteratedReport {
+    /// The number of the iterations applied.
+    pub iterations: usize,
+    /// The rows rejected as outliers at the last iteration.
+    pub observe: ObserveReport,
+}
+
+/// The configuration of the iterated update, see also [`Eskf::update_iterated`].
+#[derive(Debug, Clone)]
+pub struct IteratedConfig<T> {
+    /// The maximum number of iterations, `1` means no iteration.
+    pub max_iterations: usize,
+    /// The iteration is converged when every element of the error state changes less than this.
+    pub converge_thresh: T,
 }
 
 impl<S> Eskf<S>
@@ -65,21 +120,26 @@ where
             uncertainty: uncertain,
             process_cov,
             last_update_time: KFTime::all(timestamp_init),
+            smoother: None,
+            augmentation: Augmentation::new(),
         }
     }
 }
 
 impl<S> Eskf<S>
 where
-    S: KFState<Element: Sub<Output = S::Element>>,
-    DefaultAllocator: Allocator<S::Dim, S::Dim>,
+    S: KFState<Element: RealField> + AddAssign<ErrorState<S>> + Clone,
+    DefaultAllocator: Allocator<S::Dim, S::Dim> + Allocator<S::Dim>,
     Self: StatePredictor<DeltaTime<S::Element>>,
 {
+    /// Predict the state to `timestamp` and observe the measurement built by `f`.
+    ///
+    /// Returns the [`ObserveReport`], or `None` if nothing is observed.
     pub fn update<OB>(
         &mut self,
         timestamp: S::Element,
         f: impl FnOnce(&Self) -> Option<OB>,
-    ) -> Option<()>
+    ) -> Option<ObserveReport>
     where
         Self: StateObserver<OB>,
     {
@@ -87,13 +147,208 @@ where
         self.predict(dt);
         self.last_update_time.predict = timestamp.clone();
 
-        f(self).map(|observation| {
-            self.observe(observation);
+        let observation = f(self)?;
+        let prior = self.needs_prior().then(|| self.uncertainty.clone());
+        let report = self.observe(observation);
+        if !report.is_rejected() {
+            if let Some(prior) = &prior {
+                self.condition_clones(&prior.cov);
+            }
+            self.record_step(timestamp.clone(), prior);
             self.last_update_time.observe = timestamp;
+        }
+        Some(report)
+    }
+}
+
+impl<S> Eskf<S>
+where
+    S: KFState<Element: Sub<Output = S::Element> + RealField> + AddAssign<ErrorState<S>> + Clone,
+    DefaultAllocator: Allocator<S::Dim> + Allocator<S::Dim, S::Dim>,
+    Self: StatePredictor<DeltaTime<S::Element>>,
+{
+    /// Iterated version of [`Eskf::update`], also known as IEKF.
+    ///
+    /// The observation is rebuilt by `f` around the updated state on every iteration,
+    /// the index of the iteration is passed to `f` as well.
+    /// The covariance is only updated once after the iteration is done.
+    ///
+    /// The gate of the observation is applied on every iteration, and the whole update is discarded
+    /// if the measurement is rejected at any iteration, the same as [`Eskf::update`].
+    ///
+    /// Returns the [`IteratedReport`], or `None` if nothing is observed.
+    pub fn update_iterated<OB>(
+        &mut self,
+        timestamp: S::Element,
+        config: &IteratedConfig<S::Element>,
+        mut f: impl FnMut(&Self, usize) -> Option<OB>,
+    ) -> Option<IteratedReport>
+    where
+        Self: IteratedStateObserver<S, OB>,
+    {
+        self.update_iterated_constrained(
+            timestamp,
+            config,
+            |eskf, iteration| Some((f(eskf, iteration)?, ())),
+            |_, _| {},
+        )
+    }
+
+    /// Same as [`Eskf::update_iterated`], and `f` builds a constraint along with the observation,
+    /// e.g. to project out the unobservable directions of the update.
+    ///
+    /// The constraint is a linear map `D` applied by `constrain` to an error vector in place,
+    /// which maps the error of every iteration, and the covariance is updated on both sides by
+    /// `(I - D K H) P (I - D K H)^T + D K R K^T D^T`, so it stays symmetric and the directions
+    /// projected out by `D` keep their prior covariance.
+    pub fn update_iterated_constrained<OB, C>(
+        &mut self,
+        timestamp: S::Element,
+        config: &IteratedConfig<S::Element>,
+        mut f: impl FnMut(&Self, usize) -> Option<(OB, C)>,
+        constrain: impl Fn(&C, &mut [S::Element]),
+    ) -> Option<IteratedReport>
+    where
+        Self: IteratedStateObserver<S, OB>,
+    {
+        let dt = KFTime::all(timestamp.clone()) - self.last_update_time.clone();
+        self.predict(dt);
+        self.last_update_time.predict = timestamp.clone();
+
+        let prior = self.state.clone();
+        let recorded_prior = self.needs_prior().then(|| self.uncertainty.clone());
+        let mut error = ErrorState::<S>::zeros();
+        let mut last = None;
+        let mut iterations = 0;
+        let mut report = ObserveReport::accepted(0);
+
+        while iterations < config.max_iterations.max(1) {
+            let Some((observation, constraint)) = f(self, iterations) else {
+                break;
+            };
+            let (mut new_error, new_gain_model, new_report) =
+                self.observe_iterated(observation, &error);
+            iterations += 1;
+            report = new_report;
+            if report.is_rejected() {
+                self.state = prior;
+                return Some(IteratedReport {
+                    iterations,
+                    observe: report,
+                });
+            }
+            constrain(&constraint, new_error.as_mut_slice());
+
+            let is_converged = (&new_error - &error).amax() < config.converge_thresh;
+
+            self.state = prior.clone();
+            self.state += new_error.clone();
+            error = new_error;
+            last = Some((new_gain_model, constraint));
+
+            if is_converged {
+                break;
+            }
+        }
+
+        let (gain_model, constraint) = last?;
+        // with the optimal gain, `K R K^T = K H P - K H P H^T K^T` and `K H P` is symmetric,
+        // so the joseph form is reduced to `P - D K H P - (D K H P)^T + D K H P D^T`
+        let dim = error.nrows();
+        let mut correction = gain_model * self.cov.deref();
+        correction
+            .as_mut_slice()
+            .chunks_exact_mut(dim)
+            .for_each(|column| constrain(&constraint, column));
+        let mut both_sides = correction.transpose();
+        both_sides
+            .as_mut_slice()
+            .chunks_exact_mut(dim)
+            .for_each(|column| constrain(&constraint, column));
+        let cov = self.cov.deref() - &correction - correction.transpose() + both_sides;
+        *self.cov = (&cov + cov.transpose()) * nalgebra::convert::<f64, S::Element>(0.5);
+        self.record_correction(error.as_slice());
+        if let Some(prior) = &recorded_prior {
+            self.condition_clones(&prior.cov);
+        }
+        self.record_step(timestamp.clone(), recorded_prior);
+        self.last_update_time.observe = timestamp;
+        Some(IteratedReport {
+            iterations,
+            observe: report,
         })
     }
 }
 
+impl<S> Eskf<S>
+where
+    S: KFState,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
+{
+    /// Record the history for the smoothing from now on, see also [`Smoother`].
+    #[inline]
+    pub fn enable_smoother(&mut self, smoother: Smoother<S>) {
+        self.smoother = Some(smoother);
+    }
+
+    #[inline]
+    pub fn smoother(&self) -> Option<&Smoother<S>> {
+        self.smoother.as_ref()
+    }
+
+    #[inline]
+    pub fn smoother_mut(&mut self) -> Option<&mut Smoother<S>> {
+        self.smoother.as_mut()
+    }
+
+    /// Take the smoother out and stop recording the history.
+    #[inline]
+    pub fn take_smoother(&mut self) -> Option<Smoother<S>> {
+        self.smoother.take()
+    }
+
+    /// Whether the prior of an update is needed by the smoother or the clones.
+    #[inline]
+    fn needs_prior(&self) -> bool {
+        self.smoother.is_some() || !self.augmentation.is_empty()
+    }
+
+    /// Record the observed update from the `prior` into the smoother if enabled.
+    fn record_step(&mut self, timestamp: S::Element, prior: Option<Uncertained<S>>)
+    where
+        S: Clone,
+    {
+        if let (Some(smoother), Some(prior)) = (&mut self.smoother, prior) {
+            smoother.push(timestamp, prior, self.uncertainty.clone());
+        }
+    }
+}
+
+impl<S> Eskf<S>
+where
+    S: KFState<Element: RealField>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
+{
+    /// Record the linearized `transition` of the error state applied to the covariance,
+    /// which should be called by every prediction for the smoothing, see also [`Smoother`].
+    #[inline]
+    pub fn record_transition(&mut self, transition: &Covariance<S>) {
+        if let Some(smoother) = &mut self.smoother {
+            smoother.record_transition(transition);
+        }
+        self.transform_clones(transition);
+    }
+
+    /// Record the `correction` applied to the state by an observation,
+    /// as a column slice of the [`ErrorState`].
+    pub(crate) fn record_correction(&mut self, correction: &[S::Element]) {
+        if let Some(smoother) = &mut self.smoother {
+            smoother.record_correction(correction);
+        }
+        self.record_clones_correction(correction);
+    }
+}
+
 impl<S> Deref for Eskf<S>
 where
     S: KFState,
@@ -116,6 +371,15 @@ where
     }
 }
 
+impl<T: SupersetOf<f64>> Default for IteratedConfig<T> {
+    fn default() -> Self {
+        Self {
+            max_iterations: 4,
+            converge_thresh: nalgebra::convert(1e-3),
+        }
+    }
+}
+
 impl<T: Clone> KFTime<T> {
     #[inline]
     pub fn all(t: T) -> Self {
@@ -139,3 +403,156 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Matrix3, U3, Vector3};
+
+    use super::*;
+    use crate::eskf::{
+        observe::{ChiSquareGate, Observation},
+        state::{common::PositionState, correlation::FullState},
+    };
+
+    type State = PositionState<f64>;
+    type SquaredObservation = Observation<FullState<State>, State, U3>;
+
+    impl StatePredictor<DeltaTime<f64>> for Eskf<State> {
+        fn predict(&mut self, _: DeltaTime<f64>) {}
+    }
+
+    fn new_eskf() -> Eskf<State> {
+        let mut eskf = Eskf::new_with_state(State::default(), Covariance(Matrix3::zeros()), 0.0);
+        eskf.state.0 = Vector3::new(1.0, 1.5, -1.0);
+        eskf.cov.0 = Matrix3::identity();
+        eskf
+    }
+
+    /// Observe the element-wise square of the position, which is nonlinear.
+    fn observe_squared(eskf: &Eskf<State>, measurement: &Vector3<f64>) -> SquaredObservation {
+        let position = &eskf.state.0;
+        Observation::new(
+            measurement - position.component_mul(position),
+            Matrix3::from_diagonal(&(position * 2.0)),
+            Vector3::repeat(1e-4),
+        )
+    }
+
+    #[test]
+    fn test_single_iteration_equals_update() {
+        let measurement = Vector3::new(4.0, 2.0, 0.5);
+        let config = IteratedConfig {
+            max_iterations: 1,
+            ..Default::default()
+        };
+
+        let mut updated = new_eskf();
+        updated.update(1.0, |eskf| Some(observe_squared(eskf, &measurement)));
+        let mut iterated = new_eskf();
+        let report = iterated.update_iterated(1.0, &config, |eskf, _| {
+            Some(observe_squared(eskf, &measurement))
+        });
+
+        assert_eq!(report.map(|report| report.iterations), Some(1));
+        assert!((iterated.state.0 - updated.state.0).amax() < 1e-12);
+        assert!((iterated.cov.0 - updated.cov.0).amax() < 1e-12);
+    }
+
+    #[test]
+    fn test_iterations_converge() {
+        let truth = Vector3::new(2.0, 1.0, -0.5);
+        let measurement = truth.component_mul(&truth);
+        let config = IteratedConfig {
+            max_iterations: 20,
+            converge_thresh: 1e-9,
+        };
+
+        let mut updated = new_eskf();
+        updated.update(1.0, |eskf| Some(observe_squared(eskf, &measurement)));
+        let mut iterated = new_eskf();
+        let report = iterated.update_iterated(1.0, &config, |eskf, _| {
+            Some(observe_squared(eskf, &measurement))
+        });
+        let Some(IteratedReport { iterations, .. }) = report else {
+            panic!("the measurement should be observed");
+        };
+
+        assert!(iterations > 1 && iterations < config.max_iterations);
+        // the single linearization is far from the truth, while the iterations converge to it
+        assert!((updated.state.0 - truth).amax() > 0.1);
+        assert!((iterated.state.0 - truth).amax() < 1e-3);
+    }
+
+    #[test]
+    fn test_constrained_update() {
+        let measurement = Vector3::new(4.0, 2.0, 0.5);
+        let config = IteratedConfig {
+            max_iterations: 20,
+            converge_thresh: 1e-9,
+        };
+        let mut eskf = new_eskf();
+        eskf.cov.0 = Matrix3::new(1.0, 0.3, 0.2, 0.3, 1.0, 0.1, 0.2, 0.1, 1.0);
+        let prior = eskf.state.0;
+        let prior_cov = eskf.cov.0;
+
+        // the x axis is projected out of the update
+        let report = eskf.update_iterated_constrained(
+            1.0,
+            &config,
+            |eskf, _| {
+                Some((
+                    observe_squared(eskf, &measurement),
+                    Vector3::new(0.0, 1.0, 1.0),
+                ))
+            },
+            |mask, error| {
+                error
+                    .iter_mut()
+                    .zip(mask.iter())
+                    .for_each(|(value, mask)| *value *= mask);
+            },
+        );
+
+        assert!(report.is_some_and(|report| !report.observe.is_rejected()));
+        assert_eq!(eskf.state.0.x, prior.x);
+        assert!((eskf.state.0.y - 2.0_f64.sqrt()).abs() < 1e-3);
+        assert!((eskf.cov.0 - eskf.cov.0.transpose()).amax() < 1e-12);
+        assert!((eskf.cov.0[(0, 0)] - prior_cov[(0, 0)]).abs() < 1e-12);
+        assert!(eskf.cov.0[(1, 1)] < 1e-3);
+        assert!(eskf.cov.0.cholesky().is_some());
+    }
+
+    #[test]
+    fn test_iterated_gate() {
+        // the last row is an outlier
+        let measurement = Vector3::new(4.0, 1.0, 100.0);
+        let config = IteratedConfig {
+            max_iterations: 20,
+            converge_thresh: 1e-9,
+        };
+
+        let mut eskf = new_eskf();
+        let report = eskf.update_iterated(1.0, &config, |eskf, _| {
+            Some(observe_squared(eskf, &measurement).with_gate(ChiSquareGate::Rows(3.841)))
+        });
+        let Some(IteratedReport { observe, .. }) = report else {
+            panic!("the measurement should be observed");
+        };
+        assert_eq!(observe.rejected_rows, 1);
+        assert!((eskf.state.0 - Vector3::new(2.0, 1.0, -1.0)).amax() < 1e-3);
+        assert_eq!(eskf.cov.0[(2, 2)], 1.0);
+
+        let mut eskf = new_eskf();
+        let report = eskf.update_iterated(1.0, &config, |eskf, _| {
+            Some(observe_squared(eskf, &measurement).with_gate(ChiSquareGate::Whole(7.815)))
+        });
+        let Some(IteratedReport { observe, .. }) = report else {
+            panic!("the measurement should be observed");
+        };
+        assert!(observe.is_rejected());
+        let prior = new_eskf();
+        assert_eq!(eskf.state.0, prior.state.0);
+        assert_eq!(eskf.cov.0, prior.cov.0);
+        assert_eq!(eskf.last_update_time.observe, 0.0);
+    }
+}
@@ -0,0 +1,719 @@
+//! The augmentation of the [`Eskf`] with the clones of its sub-states, also known as the stochastic cloning.
+//!
+//! The clones are appended after the state in the joint covariance, whose size changes with the clones:
+//! ```text
+//! ╭─────────────╮
+//! │ P_xx   P_xc │
+//! │ P_cx   P_cc │
+//! ╰─────────────╯
+//! ```
+//! The clones are static, so a prediction only transforms the cross covariance `P_xc` by the transition,
+//! see also [`Eskf::record_transition`].
+//! An observation of the state only corrects the clones through their correlation, while an
+//! [`AugmentedObservation`] observes the state and the clones jointly, e.g. the relative pose between two instants.
+
+use std::ops::{AddAssign, Deref};
+
+use nalgebra::{
+    DMatrix, DVector, DefaultAllocator, Dim, DimName, IsometryMatrix3, Matrix, Matrix6, RealField,
+    Rotation3, Scalar, Storage, UnitQuaternion, Vector6, allocator::Allocator,
+};
+use num_traits::Zero;
+
+use super::{
+    Covariance, DeltaTime, ErrorState, Eskf, KFTime, StatePredictor,
+    observe::{ChiSquareGate, ObserveReport, gate},
+    state::{KFState, SubStateOf},
+};
+use crate::utils::InverseWithSubstitute;
+
+/// The identifier of a [`StateClone`], which is unique in an [`Eskf`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CloneId(usize);
+
+/// A clone of a sub-state at `timestamp`.
+#[derive(Debug, Clone)]
+pub struct StateClone<S: KFState> {
+    pub id: CloneId,
+    pub timestamp: S::Element,
+    /// The state when cloned, only the cloned sub-state is corrected afterwards.
+    pub state: S,
+    /// The offset of the cloned sub-state in the error state.
+    offset: usize,
+    /// The dimension of the cloned sub-state.
+    dim: usize,
+}
+
+/// The clones and their covariances, see also the [module](self) documentation.
+pub(super) struct Augmentation<S: KFState> {
+    clones: Vec<StateClone<S>>,
+    /// `P_xc`, the cross covariance between the state and the clones.
+    cross_cov: DMatrix<S::Element>,
+    /// `P_cc`, the covariance of the clones.
+    cov: DMatrix<S::Element>,
+    /// The correction applied to the state since the last step, empty means zero.
+    correction: Vec<S::Element>,
+    next_id: usize,
+}
+
+/// An observation of the state and the clones, `measurement` is the residual `z - h(x)`.
+pub struct AugmentedObservation<T: Scalar> {
+    pub measurement: DVector<T>,
+    /// The observation model of the state, whose columns are the dimension of the error state.
+    pub model: DMatrix<T>,
+    /// The observation models of the observed clones, whose columns are the dimension of the clone.
+    pub clone_models: Vec<(CloneId, DMatrix<T>)>,
+    /// The measurement noise, larger `noise` means more uncertain.
+    pub noise: DVector<T>,
+    /// The outlier gate of the innovation, `None` means every row of the measurement is applied.
+    pub gate: Option<ChiSquareGate<T>>,
+}
+
+impl<S: KFState<Element: Zero>> Augmentation<S> {
+    pub(super) fn new() -> Self {
+        Self {
+            clones: Vec::new(),
+            cross_cov: DMatrix::zeros(S::Dim::DIM, 0),
+            cov: DMatrix::zeros(0, 0),
+            correction: Vec::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<S: KFState> Augmentation<S> {
+    #[inline]
+    pub(super) fn is_empty(&self) -> bool {
+        self.clones.is_empty()
+    }
+
+    /// The offset of the clone at `index` in the clones part of the joint covariance.
+    fn offset(&self, index: usize) -> usize {
+        self.clones[..index].iter().map(|clone| clone.dim).sum()
+    }
+
+    fn position(&self, id: CloneId) -> Option<usize> {
+        self.clones.iter().position(|clone| clone.id == id)
+    }
+}
+
+impl<S> Eskf<S>
+where
+    S: KFState<Element: RealField> + Clone,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
+{
+    /// Clone the sub-state `C` at `timestamp` into the augmented state with its covariances,
+    /// the clone can be observed by an [`AugmentedObservation`] until it is marginalized.
+    pub fn clone_sub_state<C>(&mut self, timestamp: S::Element) -> CloneId
+    where
+        C: SubStateOf<S>,
+    {
+        let offset = C::Offset::DIM;
+        let dim = C::Dim::DIM;
+        let cov = to_dmatrix(self.cov.deref());
+        let augmentation = &mut self.augmentation;
+
+        // P_cx of the new clone is the rows of P_xx, and P_cc of the new clone is the rows of P_xc
+        let cross_cov = cov.columns(offset, dim);
+        let clones_cross_cov = augmentation.cross_cov.rows(offset, dim).transpose();
+        let clones = augmentation.cov.nrows();
+
+        let mut new_cov = DMatrix::zeros(clones + dim, clones + dim);
+        new_cov
+            .view_mut((0, 0), (clones, clones))
+            .copy_from(&augmentation.cov);
+        new_cov
+            .view_mut((0, clones), (clones, dim))
+            .copy_from(&clones_cross_cov);
+        new_cov
+            .view_mut((clones, 0), (dim, clones))
+            .copy_from(&clones_cross_cov.transpose());
+        new_cov
+            .view_mut((clones, clones), (dim, dim))
+            .copy_from(&cov.view((offset, offset), (dim, dim)));
+        augmentation.cov = new_cov;
+
+        let cross_cov = cross_cov.into_owned();
+        let old_cross_cov = std::mem::replace(&mut augmentation.cross_cov, DMatrix::zeros(0, 0));
+        augmentation.cross_cov = old_cross_cov.insert_columns(clones, dim, S::Element::zero());
+        augmentation
+            .cross_cov
+            .columns_mut(clones, dim)
+            .copy_from(&cross_cov);
+
+        let id = CloneId(augmentation.next_id);
+        augmentation.next_id += 1;
+        augmentation.clones.push(StateClone {
+            id,
+            timestamp,
+            state: self.uncertainty.state.clone(),
+            offset,
+            dim,
+        });
+        id
+    }
+
+    /// Remove the clone from the augmented state, which is marginalized out of the covariance.
+    pub fn marginalize(&mut self, id: CloneId) -> Option<StateClone<S>> {
+        let augmentation = &mut self.augmentation;
+        let index = augmentation.position(id)?;
+        let offset = augmentation.offset(index);
+        let clone = augmentation.clones.remove(index);
+
+        let cross_cov = std::mem::replace(&mut augmentation.cross_cov, DMatrix::zeros(0, 0));
+        augmentation.cross_cov = cross_cov.remove_columns(offset, clone.dim);
+        let cov = std::mem::replace(&mut augmentation.cov, DMatrix::zeros(0, 0));
+        augmentation.cov = cov
+            .remove_rows(offset, clone.dim)
+            .remove_columns(offset, clone.dim);
+        Some(clone)
+    }
+
+    #[inline]
+    pub fn clones(&self) -> &[StateClone<S>] {
+        &self.augmentation.clones
+    }
+
+    #[inline]
+    pub fn get_clone(&self, id: CloneId) -> Option<&StateClone<S>> {
+        let index = self.augmentation.position(id)?;
+        Some(&self.augmentation.clones[index])
+    }
+
+    /// The covariance of the clone, whose size is the dimension of the cloned sub-state.
+    pub fn clone_cov(&self, id: CloneId) -> Option<DMatrix<S::Element>> {
+        let augmentation = &self.augmentation;
+        let index = augmentation.position(id)?;
+        let offset = augmentation.offset(index);
+        let dim = augmentation.clones[index].dim;
+        Some(
+            augmentation
+                .cov
+                .view((offset, offset), (dim, dim))
+                .into_owned(),
+        )
+    }
+}
+
+impl<S> Eskf<S>
+where
+    S: KFState<Element: RealField>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
+{
+    /// Transform the cross covariance by the `transition` of the state.
+    pub(super) fn transform_clones(&mut self, transition: &Covariance<S>) {
+        if self.augmentation.is_empty() {
+            return;
+        }
+        let cross_cov = to_dmatrix(transition.deref()) * &self.augmentation.cross_cov;
+        self.augmentation.cross_cov = cross_cov;
+    }
+
+    /// Accumulate the `correction` applied to the state as a column slice of the [`ErrorState`].
+    pub(super) fn record_clones_correction(&mut self, correction: &[S::Element]) {
+        if self.augmentation.is_empty() {
+            return;
+        }
+        let accumulated = &mut self.augmentation.correction;
+        if accumulated.is_empty() {
+            accumulated.extend_from_slice(correction);
+        } else {
+            accumulated
+                .iter_mut()
+                .zip(correction)
+                .for_each(|(accumulated, correction)| *accumulated += correction.clone());
+        }
+    }
+}
+
+impl<S> Eskf<S>
+where
+    S: KFState<Element: RealField> + AddAssign<ErrorState<S>> + Clone,
+    DefaultAllocator: Allocator<S::Dim, S::Dim> + Allocator<S::Dim>,
+{
+    /// Correct the clones through their correlation after the state is observed from `prior_cov`.
+    ///
+    /// With `B = P_xx^-1 * P_xc` of the prior, the clones are conditioned on the state:
+    /// ```text
+    /// c += B' * δx
+    /// P_xc = P_xx+ * B
+    /// P_cc -= B' * (P_xx - P_xx+) * B
+    /// ```
+    pub(super) fn condition_clones(&mut self, prior_cov: &Covariance<S>) {
+        let correction = std::mem::take(&mut self.augmentation.correction);
+        if self.augmentation.is_empty() {
+            return;
+        }
+        let prior_cov = to_dmatrix(prior_cov.deref());
+        let posterior_cov = to_dmatrix(self.cov.deref());
+        let augmentation = &mut self.augmentation;
+
+        let clones_correction = condition(
+            prior_cov,
+            &posterior_cov,
+            &mut augmentation.cross_cov,
+            &mut augmentation.cov,
+            &correction,
+        );
+        if let Some(correction) = clones_correction {
+            correct_clones(&mut augmentation.clones, &correction);
+        }
+    }
+}
+
+impl<S> Eskf<S>
+where
+    S: KFState<Element: RealField> + AddAssign<ErrorState<S>> + Clone,
+    DefaultAllocator: Allocator<S::Dim, S::Dim> + Allocator<S::Dim>,
+    Self: StatePredictor<DeltaTime<S::Element>>,
+{
+    /// Predict the state to `timestamp` and observe the state and the clones jointly by the observation built by `f`.
+    ///
+    /// Returns the [`ObserveReport`], or `None` if nothing is observed.
+    pub fn update_augmented(
+        &mut self,
+        timestamp: S::Element,
+        f: impl FnOnce(&Self) -> Option<AugmentedObservation<S::Element>>,
+    ) -> Option<ObserveReport> {
+        let dt = KFTime::all(timestamp.clone()) - self.last_update_time.clone();
+        self.predict(dt);
+        self.last_update_time.predict = timestamp.clone();
+
+        let observation = f(self)?;
+        let prior = self.smoother.is_some().then(|| self.uncertainty.clone());
+        let report = self.observe_augmented(observation);
+        if !report.is_rejected() {
+            self.record_step(timestamp.clone(), prior);
+            self.last_update_time.observe = timestamp;
+        }
+        Some(report)
+    }
+
+    fn observe_augmented(
+        &mut self,
+        AugmentedObservation {
+            measurement,
+            model,
+            clone_models,
+            noise,
+            gate,
+        }: AugmentedObservation<S::Element>,
+    ) -> ObserveReport {
+        let rows = measurement.len();
+        let dim = S::Dim::DIM;
+        let augmentation = &self.augmentation;
+        let clones_dim = augmentation.cov.nrows();
+
+        let mut clone_columns = Vec::with_capacity(clone_models.len());
+        for (id, clone_model) in &clone_models {
+            let Some(index) = augmentation.position(*id) else {
+                // the clone is marginalized
+                return ObserveReport::rejected(rows);
+            };
+            clone_columns.push((dim + augmentation.offset(index), clone_model));
+        }
+        let joint_model = joint_model(model, dim + clones_dim, &clone_columns);
+
+        let joint_cov = joint_cov(
+            to_dmatrix(self.cov.deref()),
+            &augmentation.cross_cov,
+            &augmentation.cov,
+        );
+        let (correction, joint_cov, rejected_rows) =
+            match joint_update(joint_cov, &joint_model, measurement, noise, gate.as_ref()) {
+                Ok(updated) => updated,
+                Err(report) => return report,
+            };
+
+        let state_correction =
+            ErrorState::<S>::from_iterator(correction.rows(0, dim).iter().cloned());
+        // the clones are corrected jointly
+        if let Some(smoother) = &mut self.smoother {
+            smoother.record_correction(state_correction.as_slice());
+        }
+        self.uncertainty.state += state_correction;
+        self.cov.copy_from(&joint_cov.view((0, 0), (dim, dim)));
+
+        let augmentation = &mut self.augmentation;
+        augmentation.cross_cov = joint_cov.view((0, dim), (dim, clones_dim)).into_owned();
+        augmentation.cov = joint_cov
+            .view((dim, dim), (clones_dim, clones_dim))
+            .into_owned();
+        correct_clones(
+            &mut augmentation.clones,
+            &correction.rows(dim, clones_dim).into_owned(),
+        );
+
+        ObserveReport {
+            rows,
+            rejected_rows,
+        }
+    }
+}
+
+/// Condition the clones on the state observed from `prior_cov` to `posterior_cov`,
+/// returns the correction of the clones.
+fn condition<T: RealField>(
+    prior_cov: DMatrix<T>,
+    posterior_cov: &DMatrix<T>,
+    cross_cov: &mut DMatrix<T>,
+    cov: &mut DMatrix<T>,
+    correction: &[T],
+) -> Option<DVector<T>> {
+    let gain = prior_cov.clone().cholesky_inverse_with_substitute() * &*cross_cov;
+    *cov -= gain.transpose() * (prior_cov - posterior_cov) * &gain;
+    *cross_cov = posterior_cov * &gain;
+
+    (!correction.is_empty()).then(|| gain.transpose() * DVector::from_column_slice(correction))
+}
+
+/// The model of the joint state, the `clone_columns` are the offsets and the models of the observed clones.
+fn joint_model<T: RealField>(
+    model: DMatrix<T>,
+    dim: usize,
+    clone_columns: &[(usize, &DMatrix<T>)],
+) -> DMatrix<T> {
+    let mut joint_model = model.resize_horizontally(dim, T::zero());
+    for (offset, clone_model) in clone_columns {
+        let mut columns = joint_model.columns_mut(*offset, clone_model.ncols());
+        columns += *clone_model;
+    }
+    joint_model
+}
+
+fn joint_cov<T: RealField>(
+    state_cov: DMatrix<T>,
+    cross_cov: &DMatrix<T>,
+    clones_cov: &DMatrix<T>,
+) -> DMatrix<T> {
+    let dim = state_cov.nrows();
+    let clones_dim = clones_cov.nrows();
+    let mut joint_cov = state_cov.resize(dim + clones_dim, dim + clones_dim, T::zero());
+    joint_cov
+        .view_mut((0, dim), (dim, clones_dim))
+        .copy_from(cross_cov);
+    joint_cov
+        .view_mut((dim, 0), (clones_dim, dim))
+        .copy_from(&cross_cov.transpose());
+    joint_cov
+        .view_mut((dim, dim), (clones_dim, clones_dim))
+        .copy_from(clones_cov);
+    joint_cov
+}
+
+/// Update the `joint_cov` by the observation,
+/// returns the correction of the joint state, the updated covariance and the number of the rejected rows.
+fn joint_update<T: RealField>(
+    mut joint_cov: DMatrix<T>,
+    joint_model: &DMatrix<T>,
+    mut measurement: DVector<T>,
+    noise: DVector<T>,
+    gate: Option<&ChiSquareGate<T>>,
+) -> Result<(DVector<T>, DMatrix<T>, usize), ObserveReport> {
+    let rows = measurement.len();
+    let mut cross_cov = &joint_cov * joint_model.transpose();
+    let mut innovation_cov = joint_model * &cross_cov;
+    innovation_cov.set_diagonal(&(innovation_cov.diagonal() + noise));
+
+    let rejected_rows = match gate {
+        Some(ChiSquareGate::Rows(threshold)) => gate::reject_rows(
+            threshold,
+            &mut measurement,
+            &mut cross_cov,
+            &mut innovation_cov,
+        ),
+        _ => 0,
+    };
+    if rejected_rows == rows {
+        return Err(ObserveReport::rejected(rows));
+    }
+
+    let innovation_cov_inv = innovation_cov.cholesky_inverse_with_substitute();
+    if let Some(ChiSquareGate::Whole(threshold)) = gate {
+        let distance = measurement.dot(&(&innovation_cov_inv * &measurement));
+        if distance > *threshold {
+            return Err(ObserveReport::rejected(rows));
+        }
+    }
+
+    let kalman_gain = &cross_cov * innovation_cov_inv;
+    let correction = &kalman_gain * measurement;
+    joint_cov -= kalman_gain * cross_cov.transpose();
+    Ok((correction, joint_cov, rejected_rows))
+}
+
+/// Apply the `correction` of the clones part to every clone.
+fn correct_clones<S>(clones: &mut [StateClone<S>], correction: &DVector<S::Element>)
+where
+    S: KFState<Element: RealField> + AddAssign<ErrorState<S>>,
+    DefaultAllocator: Allocator<S::Dim>,
+{
+    let mut offset = 0;
+    for clone in clones {
+        let mut error = ErrorState::<S>::zeros();
+        error
+            .rows_mut(clone.offset, clone.dim)
+            .copy_from(&correction.rows(offset, clone.dim));
+        clone.state += error;
+        offset += clone.dim;
+    }
+}
+
+fn to_dmatrix<T, R, C, St>(matrix: &Matrix<T, R, C, St>) -> DMatrix<T>
+where
+    T: Scalar,
+    R: Dim,
+    C: Dim,
+    St: Storage<T, R, C>,
+{
+    DMatrix::from_iterator(matrix.nrows(), matrix.ncols(), matrix.iter().cloned())
+}
+
+/// The residual and the observation model of the relative pose `measured` from the `clone` pose
+/// to the `current` pose, i.e. `clone^-1 * current`.
+///
+/// The model of the current pose is the identity, and the returned model is of the clone pose.
+pub fn relative_pose_model<T: RealField>(
+    clone: &IsometryMatrix3<T>,
+    current: &IsometryMatrix3<T>,
+    measured: &IsometryMatrix3<T>,
+) -> (Vector6<T>, Matrix6<T>) {
+    let estimated = clone.inv_mul(current);
+
+    // measured = estimated * Exp(δθ) * Trans(δp)
+    let rotation_error = UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix_unchecked(
+        estimated.rotation.matrix().transpose() * measured.rotation.matrix(),
+    ))
+    .scaled_axis();
+    let translation_error = measured.rotation.inverse()
+        * (measured.translation.vector.clone() - estimated.translation.vector.clone());
+
+    let mut residual = Vector6::zeros();
+    residual.fixed_rows_mut::<3>(0).copy_from(&rotation_error);
+    residual
+        .fixed_rows_mut::<3>(3)
+        .copy_from(&translation_error);
+
+    let rotation_tr = estimated.rotation.matrix().transpose();
+    let translation_cross = estimated.translation.vector.cross_matrix();
+    let mut model = Matrix6::zeros();
+    model
+        .fixed_view_mut::<3, 3>(0, 0)
+        .copy_from(&(-rotation_tr.clone()));
+    model
+        .fixed_view_mut::<3, 3>(3, 0)
+        .copy_from(&(&rotation_tr * translation_cross));
+    model
+        .fixed_view_mut::<3, 3>(3, 3)
+        .copy_from(&(-rotation_tr));
+
+    (residual, model)
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Matrix3, Translation3, Vector3};
+
+    use super::*;
+    use crate::eskf::{
+        observe::Observation,
+        state::{common::VelocityState, correlation::FullState},
+    };
+
+    type State = VelocityState<f64>;
+
+    /// The transition of the prediction, which mixes the axes.
+    fn transition() -> Matrix3<f64> {
+        Matrix3::new(1.0, 0.1, 0.0, 0.0, 1.0, 0.2, 0.0, 0.0, 0.9)
+    }
+
+    impl StatePredictor<DeltaTime<f64>> for Eskf<State> {
+        fn predict(&mut self, dt: DeltaTime<f64>) {
+            if dt.predict == 0.0 {
+                return;
+            }
+            let transition = transition();
+            self.state.0 = transition * self.state.0;
+            self.cov.0 = transition * self.cov.0 * transition.transpose() + self.process_cov.0;
+            self.record_transition(&Covariance(transition));
+        }
+    }
+
+    /// The brute-force filter of the state stacked with its clones explicitly.
+    struct JointFilter {
+        state: DVector<f64>,
+        cov: DMatrix<f64>,
+    }
+
+    impl JointFilter {
+        fn clone_state(&mut self) {
+            let dim = self.state.len();
+            let mut augment = DMatrix::identity(dim + 3, dim);
+            augment.view_mut((dim, 0), (3, 3)).fill_with_identity();
+            self.state = &augment * &self.state;
+            self.cov = &augment * &self.cov * augment.transpose();
+        }
+
+        fn predict(&mut self, process_cov: &Matrix3<f64>) {
+            let dim = self.state.len();
+            let mut transition = DMatrix::identity(dim, dim);
+            transition
+                .view_mut((0, 0), (3, 3))
+                .copy_from(&self::transition());
+            self.state = &transition * &self.state;
+            self.cov = &transition * &self.cov * transition.transpose();
+            let mut cov = self.cov.view_mut((0, 0), (3, 3));
+            cov += process_cov;
+        }
+
+        /// Observe `measured = model * state` with the `noise`.
+        fn update(&mut self, model: &DMatrix<f64>, measured: &DVector<f64>, noise: &DVector<f64>) {
+            let innovation_cov =
+                model * &self.cov * model.transpose() + DMatrix::from_diagonal(noise);
+            let Some(innovation_cov_inv) = innovation_cov.try_inverse() else {
+                panic!("the innovation covariance is singular");
+            };
+            let gain = &self.cov * model.transpose() * innovation_cov_inv;
+            self.state += &gain * (measured - model * &self.state);
+            self.cov -= gain * model * &self.cov;
+        }
+
+        fn marginalize(&mut self, offset: usize) {
+            self.state = self.state.clone().remove_rows(offset, 3);
+            self.cov = self
+                .cov
+                .clone()
+                .remove_rows(offset, 3)
+                .remove_columns(offset, 3);
+        }
+    }
+
+    fn assert_matches(eskf: &Eskf<State>, joint: &JointFilter) {
+        let dim = joint.state.len();
+        let augmentation = &eskf.augmentation;
+        assert_eq!(3 + augmentation.cov.nrows(), dim);
+        assert!((eskf.state.0 - joint.state.rows(0, 3)).amax() < 1e-9);
+        eskf.clones().iter().enumerate().for_each(|(i, clone)| {
+            assert!((clone.state.0 - joint.state.rows(3 + 3 * i, 3)).amax() < 1e-9);
+            let Some(clone_cov) = eskf.clone_cov(clone.id) else {
+                panic!("the clone is missing");
+            };
+            assert!((clone_cov - joint.cov.view((3 + 3 * i, 3 + 3 * i), (3, 3))).amax() < 1e-9);
+        });
+        assert!((eskf.cov.0 - joint.cov.view((0, 0), (3, 3))).amax() < 1e-9);
+        assert!((&augmentation.cross_cov - joint.cov.view((0, 3), (3, dim - 3))).amax() < 1e-9);
+        assert!((&augmentation.cov - joint.cov.view((3, 3), (dim - 3, dim - 3))).amax() < 1e-9);
+    }
+
+    /// The clones bookkept through the predictions, the plain and the joint updates and the marginalization
+    /// match the joint filter of the state stacked with the clones.
+    #[test]
+    fn test_clones_match_joint_filter() {
+        let process_cov = Matrix3::from_diagonal(&Vector3::new(0.1, 0.2, 0.3));
+        let mut eskf = Eskf::new_with_state(
+            State::new(Vector3::new(1.0, -2.0, 0.5)),
+            Covariance(process_cov),
+            0.0,
+        );
+        eskf.cov.0 = Matrix3::new(1.0, 0.2, 0.1, 0.2, 2.0, 0.3, 0.1, 0.3, 1.5);
+        let mut joint = JointFilter {
+            state: DVector::from_column_slice(eskf.state.0.as_slice()),
+            cov: DMatrix::from_column_slice(3, 3, eskf.cov.0.as_slice()),
+        };
+        let noise = Vector3::new(0.5, 0.2, 0.1);
+
+        // the plain update observes the first two axes of the state only
+        let model = Matrix3::from_diagonal(&Vector3::new(1.0, 2.0, 0.0));
+        let plain_update = |eskf: &mut Eskf<State>, joint: &mut JointFilter, timestamp: f64| {
+            let measured = Vector3::new(timestamp, -timestamp, 0.0);
+            eskf.update(timestamp, |eskf| {
+                Some(Observation::<FullState<State>, State, _>::new(
+                    measured - model * eskf.state.0,
+                    model,
+                    noise,
+                ))
+            });
+            joint.predict(&process_cov);
+            let mut joint_model = DMatrix::zeros(3, joint.state.len());
+            joint_model.view_mut((0, 0), (3, 3)).copy_from(&model);
+            joint.update(
+                &joint_model,
+                &DVector::from_column_slice(measured.as_slice()),
+                &DVector::from_column_slice(noise.as_slice()),
+            );
+        };
+
+        let first = eskf.clone_sub_state::<FullState<State>>(0.0);
+        joint.clone_state();
+        assert_matches(&eskf, &joint);
+
+        plain_update(&mut eskf, &mut joint, 1.0);
+        assert_matches(&eskf, &joint);
+
+        let second = eskf.clone_sub_state::<FullState<State>>(1.0);
+        joint.clone_state();
+        assert_matches(&eskf, &joint);
+
+        // the difference between the state and the first clone
+        let measured = Vector3::new(0.3, -0.1, 0.2);
+        let report = eskf.update_augmented(2.0, |eskf| {
+            let Some(clone) = eskf.get_clone(first) else {
+                panic!("the clone is missing");
+            };
+            Some(AugmentedObservation {
+                measurement: DVector::from_column_slice(
+                    (measured - (eskf.state.0 - clone.state.0)).as_slice(),
+                ),
+                model: DMatrix::identity(3, 3),
+                clone_models: vec![(first, -DMatrix::identity(3, 3))],
+                noise: DVector::from_column_slice(noise.as_slice()),
+                gate: None,
+            })
+        });
+        assert!(report.is_some_and(|report| !report.is_rejected()));
+        joint.predict(&process_cov);
+        let mut joint_model = DMatrix::zeros(3, 9);
+        joint_model.view_mut((0, 0), (3, 3)).fill_with_identity();
+        joint_model
+            .view_mut((0, 3), (3, 3))
+            .copy_from(&-Matrix3::identity());
+        joint.update(
+            &joint_model,
+            &DVector::from_column_slice(measured.as_slice()),
+            &DVector::from_column_slice(noise.as_slice()),
+        );
+        assert_matches(&eskf, &joint);
+
+        assert!(eskf.marginalize(first).is_some());
+        assert!(eskf.get_clone(first).is_none());
+        joint.marginalize(3);
+        assert_matches(&eskf, &joint);
+        assert_eq!(eskf.clones()[0].id, second);
+
+        plain_update(&mut eskf, &mut joint, 3.0);
+        assert_matches(&eskf, &joint);
+    }
+
+    /// The residual of the perturbed clone pose is `-H * δ` to the first order.
+    #[test]
+    fn test_relative_pose_model() {
+        let clone = IsometryMatrix3::from_parts(
+            Translation3::new(1.0, -2.0, 0.5),
+            Rotation3::new(Vector3::new(0.3, -0.2, 0.8)),
+        );
+        let current = IsometryMatrix3::from_parts(
+            Translation3::new(2.0, 1.0, -0.5),
+            Rotation3::new(Vector3::new(-0.1, 0.4, 1.2)),
+        );
+        let measured = clone.inv_mul(&current);
+        let (residual, model) = relative_pose_model(&clone, &current, &measured);
+        assert!(residual.amax() < 1e-12);
+
+        let error = Vector6::new(1e-4, -2e-4, 3e-4, -1e-4, 2e-4, 1e-4);
+        let mut perturbed = clone;
+        perturbed *= Rotation3::new(error.fixed_rows::<3>(0).into_owned());
+        perturbed *= Translation3::from(error.fixed_rows::<3>(3).into_owned());
+        let (residual, _) = relative_pose_model(&perturbed, &current, &measured);
+        assert!((residual + model * error).amax() < 1e-6);
+    }
+}
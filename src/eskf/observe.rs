@@ -1,3 +1,4 @@
+pub(super) mod gate;
 mod model;
 
 use std::{
@@ -5,9 +6,11 @@ use std::{
     ops::{AddAssign, Deref},
 };
 
+pub use gate::{ChiSquareGate, ObserveReport};
 pub use model::ObserveModel;
 use nalgebra::{
-    ClosedMulAssign, DefaultAllocator, Dim, DimAdd, DimMin, OVector, U1, allocator::Allocator,
+    ClosedMulAssign, DefaultAllocator, Dim, DimAdd, DimMin, OMatrix, OVector, RealField, U1,
+    allocator::Allocator,
 };
 use num_traits::Zero;
 
@@ -22,7 +25,7 @@ use crate::{
     utils::{InverseWithSubstitute, Substitutive, ViewDiagonalMut},
 };
 
-use super::{Eskf, StateObserver};
+use super::{ErrorState, Eskf, GainModel, IteratedStateObserver, StateObserver};
 
 /// The most generic eskf observation.
 pub struct Observation<S, Super: KFState, D: Dim, M = DefaultModel<S, Super, D>>
@@ -37,6 +40,8 @@ where
     pub model: M,
     /// The measurement noise, larger `noise` means more uncertain.
     pub noise: OVector<S::Element, D>,
+    /// The outlier gate of the innovation, `None` means every row of the measurement is applied.
+    pub gate: Option<ChiSquareGate<S::Element>>,
 
     _marker: PhantomData<Super>,
 }
@@ -56,6 +61,7 @@ where
             measurement: OVector::zeros_generic(dim, U1),
             model: M::new_with_dim(dim),
             noise: OVector::zeros_generic(dim, U1),
+            gate: None,
             _marker: PhantomData,
         }
     }
@@ -73,14 +79,24 @@ where
             measurement,
             model,
             noise,
+            gate: None,
             _marker: PhantomData,
         }
     }
+
+    /// Gate the outliers of this observation, see also [`ChiSquareGate`].
+    #[inline]
+    pub fn with_gate(self, gate: ChiSquareGate<S::Element>) -> Self {
+        Self {
+            gate: Some(gate),
+            ..self
+        }
+    }
 }
 
 impl<S, Super, D: Dim, M> StateObserver<Observation<S, Super, D, M>> for Eskf<Super>
 where
-    Super: KFState<Element: Substitutive + ClosedMulAssign>
+    Super: KFState<Element: Substitutive + ClosedMulAssign + RealField>
         + AddAssign<OVector<Super::Element, Super::Dim>>,
     S: CorrelateTo<Super, Element = Super::Element>,
     M: ObserveModel<S, Super, D>,
@@ -100,23 +116,144 @@ where
     fn observe(
         &mut self,
         Observation {
-            measurement,
+            mut measurement,
             model,
             noise,
+            gate,
             ..
         }: Observation<S, Super, D, M>,
-    ) {
-        let cross_cov = model.tr_mul(S::correlate_to(&self.cov));
+    ) -> ObserveReport {
+        let rows = measurement.len();
+        let mut cross_cov = model.tr_mul(S::correlate_to(&self.cov)).into_owned();
 
-        let innovation_cov = model
+        let mut innovation_cov = model
             .mul(S::correlate_from(&cross_cov))
             .into_owned()
             .diagonal_add(noise);
 
-        let kalman_gain = cross_cov * innovation_cov.cholesky_inverse_with_substitute();
+        let rejected_rows = match &gate {
+            Some(ChiSquareGate::Rows(threshold)) => gate::reject_rows(
+                threshold,
+                &mut measurement,
+                &mut cross_cov,
+                &mut innovation_cov,
+            ),
+            _ => 0,
+        };
+        if rejected_rows == rows {
+            return ObserveReport::rejected(rows);
+        }
 
-        self.state += &kalman_gain * measurement;
+        let innovation_cov_inv = innovation_cov.cholesky_inverse_with_substitute();
+
+        if let Some(ChiSquareGate::Whole(threshold)) = &gate {
+            // the squared mahalanobis distance of the innovation
+            let distance = measurement.dot(&(&innovation_cov_inv * &measurement));
+            if distance > *threshold {
+                return ObserveReport::rejected(rows);
+            }
+        }
+
+        let kalman_gain = cross_cov * innovation_cov_inv;
+
+        let correction = &kalman_gain * measurement;
+        self.record_correction(correction.as_slice());
+        self.state += correction;
 
         *self.cov = self.cov.deref() - kalman_gain * model.mul(S::correlate_from(&self.cov));
+
+        ObserveReport {
+            rows,
+            rejected_rows,
+        }
+    }
+}
+
+impl<S, Super, D: Dim, M> IteratedStateObserver<Super, Observation<S, Super, D, M>> for Eskf<Super>
+where
+    Super: KFState<Element: Substitutive + ClosedMulAssign + RealField>,
+    S: CorrelateTo<Super, Element = Super::Element>,
+    M: ObserveModel<S, Super, D>,
+    // for diagonal view
+    D: DimMin<D, Output = D> + DimAdd<U1>,
+    DefaultAllocator: Allocator<Super::Dim, Super::Dim>
+        + Allocator<D, D>
+        + Allocator<Super::Dim>
+        + Allocator<D>
+        + Allocator<D, S::CorDim>
+        + Allocator<S::CorDim, D>
+        + Allocator<Super::Dim, D>
+        + Allocator<D, Super::Dim>
+        + Allocator<Super::Dim, S::CorDim>
+        + Allocator<S::CorDim, Super::Dim>
+        + Allocator<S::CorDim>,
+{
+    fn observe_iterated(
+        &self,
+        Observation {
+            measurement,
+            model,
+            noise,
+            gate,
+            ..
+        }: Observation<S, Super, D, M>,
+        error: &ErrorState<Super>,
+    ) -> (ErrorState<Super>, GainModel<Super>, ObserveReport) {
+        let rows = measurement.len();
+        let mut cross_cov = model.tr_mul(S::correlate_to(&self.cov)).into_owned();
+
+        let mut innovation_cov = model
+            .mul(S::correlate_from(&cross_cov))
+            .into_owned()
+            .diagonal_add(noise);
+
+        // the innovation is re-linearized around the current state:
+        // z - h(x_i) + H * (x_i - x_prior)
+        let mut innovation = measurement + model.mul(S::correlate_from(error));
+
+        let rejected = || {
+            (
+                ErrorState::<Super>::zeros(),
+                GainModel::<Super>::zeros(),
+                ObserveReport::rejected(rows),
+            )
+        };
+        let rejected_rows = match &gate {
+            Some(ChiSquareGate::Rows(threshold)) => gate::reject_rows(
+                threshold,
+                &mut innovation,
+                &mut cross_cov,
+                &mut innovation_cov,
+            ),
+            _ => 0,
+        };
+        if rejected_rows == rows {
+            return rejected();
+        }
+
+        let innovation_cov_inv = innovation_cov.cholesky_inverse_with_substitute();
+
+        if let Some(ChiSquareGate::Whole(threshold)) = &gate {
+            // the squared mahalanobis distance of the innovation
+            let distance = innovation.dot(&(&innovation_cov_inv * &innovation));
+            if distance > *threshold {
+                return rejected();
+            }
+        }
+
+        let kalman_gain = cross_cov * innovation_cov_inv;
+        let error = &kalman_gain * innovation;
+
+        let identity = OMatrix::<Super::Element, Super::Dim, Super::Dim>::identity();
+        let gain_model = kalman_gain * model.mul(S::correlate_from(&identity));
+
+        (
+            error,
+            gain_model,
+            ObserveReport {
+                rows,
+                rejected_rows,
+            },
+        )
     }
 }
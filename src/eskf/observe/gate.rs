@@ -0,0 +1,108 @@
+use nalgebra::{DefaultAllocator, Dim, OMatrix, OVector, RealField, allocator::Allocator};
+
+/// The chi-square gate of the innovation, which rejects the outliers of an [`Observation`](super::Observation).
+///
+/// The threshold is the chi-square quantile of the degrees of freedom,
+/// e.g. `3.841`, `7.815` and `12.592` for the 95% quantile of 1, 3 and 6 degrees of freedom.
+#[derive(Debug, Clone)]
+pub enum ChiSquareGate<T> {
+    /// Reject the whole measurement if the squared Mahalanobis distance of the innovation exceeds the threshold.
+    Whole(T),
+    /// Reject each row of the measurement whose squared normalized innovation exceeds the threshold,
+    /// the threshold is usually the quantile of 1 degree of freedom.
+    Rows(T),
+}
+
+/// The report of an observation, see also [`StateObserver::observe`](crate::eskf::StateObserver::observe).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObserveReport {
+    /// The number of the measurement rows.
+    pub rows: usize,
+    /// The number of the measurement rows rejected by the [`ChiSquareGate`].
+    pub rejected_rows: usize,
+}
+
+impl ObserveReport {
+    #[inline]
+    pub const fn accepted(rows: usize) -> Self {
+        Self {
+            rows,
+            rejected_rows: 0,
+        }
+    }
+
+    #[inline]
+    pub const fn rejected(rows: usize) -> Self {
+        Self {
+            rows,
+            rejected_rows: rows,
+        }
+    }
+
+    /// Whether the whole measurement is rejected, the state is not updated in this case.
+    #[inline]
+    pub const fn is_rejected(&self) -> bool {
+        self.rejected_rows == self.rows
+    }
+
+    #[inline]
+    pub const fn accepted_rows(&self) -> usize {
+        self.rows - self.rejected_rows
+    }
+}
+
+/// Reject the rows of the `innovation` whose squared normalized innovation exceeds the `threshold`.
+///
+/// The rejected rows are decoupled from the others, and the corresponding innovation and columns of
+/// the `cross_cov` are zeroed, so that they have no effect on the update, which is the same as removing them.
+///
+/// Returns the number of the rejected rows.
+pub(crate) fn reject_rows<T, R, D>(
+    threshold: &T,
+    innovation: &mut OVector<T, D>,
+    cross_cov: &mut OMatrix<T, R, D>,
+    innovation_cov: &mut OMatrix<T, D, D>,
+) -> usize
+where
+    T: RealField,
+    R: Dim,
+    D: Dim,
+    DefaultAllocator: Allocator<D> + Allocator<R, D> + Allocator<D, D>,
+{
+    let mut rejected_rows = 0;
+    for i in 0..innovation.len() {
+        let variance = innovation_cov[(i, i)].clone();
+        if innovation[i].clone().powi(2) <= threshold.clone() * variance {
+            continue;
+        }
+        innovation[i] = T::zero();
+        cross_cov.column_mut(i).fill(T::zero());
+        innovation_cov.row_mut(i).fill(T::zero());
+        innovation_cov.column_mut(i).fill(T::zero());
+        innovation_cov[(i, i)] = T::one();
+        rejected_rows += 1;
+    }
+    rejected_rows
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Matrix2, Matrix3x2, Vector2};
+
+    use super::*;
+
+    #[test]
+    fn test_reject_rows() {
+        let mut innovation = Vector2::new(0.1, 10.0);
+        let mut cross_cov = Matrix3x2::from_element(1.0);
+        let mut innovation_cov = Matrix2::new(1.0, 0.5, 0.5, 4.0);
+
+        let rejected_rows =
+            reject_rows(&3.841, &mut innovation, &mut cross_cov, &mut innovation_cov);
+
+        assert_eq!(rejected_rows, 1);
+        assert_eq!(innovation, Vector2::new(0.1, 0.0));
+        assert_eq!(cross_cov.column(1).sum(), 0.0);
+        assert_eq!(innovation_cov, Matrix2::new(1.0, 0.0, 0.0, 1.0));
+    }
+}
@@ -0,0 +1,278 @@
+//! The Rauch–Tung–Striebel smoother over the history of the [`Eskf`](super::Eskf).
+//!
+//! Every observed update of the filter is recorded as a step, including the prior and the posterior
+//! estimation, the applied correction and the transition from the previous step, which is accumulated
+//! from every prediction by [`Eskf::record_transition`](super::Eskf::record_transition).
+//!
+//! The smoothed error of a step is relative to its posterior:
+//! ```text
+//! G_k = P_k * F_k+1' * (P-_k+1)^-1
+//! e_k = G_k * (e_k+1 + c_k+1)
+//! Ps_k = P_k + G_k * (Ps_k+1 - P-_k+1) * G_k'
+//! ```
+//! where `c` is the correction from the prior to the posterior,
+//! so the state difference is not needed for the generic [`KFState`].
+
+use std::{collections::VecDeque, ops::AddAssign};
+
+use nalgebra::{DefaultAllocator, RealField, allocator::Allocator};
+
+use super::{Covariance, ErrorState, state::KFState, uncertain::Uncertained};
+use crate::utils::InverseWithSubstitute;
+
+/// The recorder and the smoother of the filter history, see also the [module](self) documentation.
+pub struct Smoother<S>
+where
+    S: KFState,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
+{
+    steps: VecDeque<SmoothStep<S>>,
+    /// The transition since the last step, `None` means the identity.
+    transition: Option<Covariance<S>>,
+    /// The correction applied since the last step, empty means zero.
+    correction: Vec<S::Element>,
+    /// The number of the latest steps kept for the fixed-lag smoothing, `None` keeps every step.
+    lag: Option<usize>,
+}
+
+struct SmoothStep<S>
+where
+    S: KFState,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
+{
+    timestamp: S::Element,
+    prior: Uncertained<S>,
+    posterior: Uncertained<S>,
+    /// The column slice of the [`ErrorState`] from the prior to the posterior.
+    correction: Vec<S::Element>,
+    /// The transition from the posterior of the previous step to the prior of this step.
+    transition: Option<Covariance<S>>,
+}
+
+/// A smoothed estimation at `timestamp`.
+#[derive(Debug, Clone)]
+pub struct SmoothedState<S>
+where
+    S: KFState,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
+{
+    pub timestamp: S::Element,
+    pub state: Uncertained<S>,
+}
+
+impl<S> Smoother<S>
+where
+    S: KFState,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
+{
+    /// Create a smoother keeping every step for the offline backward pass, see also [`Smoother::smooth`].
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            steps: VecDeque::new(),
+            transition: None,
+            correction: Vec::new(),
+            lag: None,
+        }
+    }
+
+    /// Create a fixed-lag smoother keeping the latest `lag` steps, see also [`Smoother::pop_lagged`].
+    #[inline]
+    pub fn with_lag(lag: usize) -> Self {
+        Self {
+            steps: VecDeque::with_capacity(lag + 1),
+            lag: Some(lag),
+            ..Self::new()
+        }
+    }
+
+    #[inline]
+    pub fn lag(&self) -> Option<usize> {
+        self.lag
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Remove every recorded step, e.g. after the state is reset.
+    pub fn clear(&mut self) {
+        self.steps.clear();
+        self.transition = None;
+        self.correction.clear();
+    }
+
+    /// Record an observed update from the `prior` to the `posterior` at `timestamp`.
+    pub(crate) fn push(
+        &mut self,
+        timestamp: S::Element,
+        prior: Uncertained<S>,
+        posterior: Uncertained<S>,
+    ) {
+        self.steps.push_back(SmoothStep {
+            timestamp,
+            prior,
+            posterior,
+            correction: std::mem::take(&mut self.correction),
+            transition: self.transition.take(),
+        });
+    }
+}
+
+impl<S> Smoother<S>
+where
+    S: KFState<Element: RealField>,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
+{
+    /// Accumulate the linearized `transition` of the error state, i.e. `F`.
+    pub(crate) fn record_transition(&mut self, transition: &Covariance<S>) {
+        let transition = match self.transition.take() {
+            Some(accumulated) => Covariance(&transition.0 * accumulated.0),
+            None => Covariance(transition.0.clone()),
+        };
+        self.transition = Some(transition);
+    }
+
+    /// Accumulate the `correction` applied to the state as a column slice of the [`ErrorState`].
+    pub(crate) fn record_correction(&mut self, correction: &[S::Element]) {
+        if self.correction.is_empty() {
+            self.correction.extend_from_slice(correction);
+        } else {
+            self.correction
+                .iter_mut()
+                .zip(correction)
+                .for_each(|(accumulated, correction)| *accumulated += correction.clone());
+        }
+    }
+}
+
+impl<S> Smoother<S>
+where
+    S: KFState<Element: RealField> + AddAssign<ErrorState<S>> + Clone,
+    DefaultAllocator: Allocator<S::Dim, S::Dim> + Allocator<S::Dim>,
+{
+    /// Run the backward pass over every recorded step, returns the smoothed states in the recorded order.
+    pub fn smooth(&self) -> Vec<SmoothedState<S>> {
+        let Some(last) = self.steps.back() else {
+            return Vec::new();
+        };
+
+        let mut smoothed = Vec::with_capacity(self.steps.len());
+        smoothed.push(SmoothedState {
+            timestamp: last.timestamp.clone(),
+            state: last.posterior.clone(),
+        });
+
+        // the smoothed error and covariance of the later step
+        let mut error = ErrorState::<S>::zeros();
+        let mut cov = last.posterior.cov.0.clone();
+
+        let later_steps = self.steps.iter().rev();
+        for (step, next) in self.steps.iter().rev().skip(1).zip(later_steps) {
+            let cross_cov = match &next.transition {
+                Some(transition) => &step.posterior.cov.0 * transition.transpose(),
+                None => step.posterior.cov.0.clone(),
+            };
+            let gain = cross_cov * next.prior.cov.0.clone().cholesky_inverse_with_substitute();
+
+            if !next.correction.is_empty() {
+                error += ErrorState::<S>::from_column_slice(&next.correction);
+            }
+            error = &gain * error;
+            cov = &step.posterior.cov.0 + &gain * (cov - &next.prior.cov.0) * gain.transpose();
+
+            let mut state = step.posterior.state.clone();
+            state += error.clone();
+            smoothed.push(SmoothedState {
+                timestamp: step.timestamp.clone(),
+                state: Uncertained {
+                    state,
+                    cov: Covariance(cov.clone()),
+                },
+            });
+        }
+
+        smoothed.reverse();
+        smoothed
+    }
+
+    /// Pop the steps older than the lag window, which are smoothed by the backward pass over the window.
+    ///
+    /// Returns nothing if the smoother keeps every step, see also [`Smoother::with_lag`].
+    pub fn pop_lagged(&mut self) -> Vec<SmoothedState<S>> {
+        let Some(lag) = self.lag else {
+            return Vec::new();
+        };
+        let popped = self.steps.len().saturating_sub(lag);
+        if popped == 0 {
+            return Vec::new();
+        }
+        let mut smoothed = self.smooth();
+        smoothed.truncate(popped);
+        self.steps.drain(..popped);
+        smoothed
+    }
+}
+
+impl<S> Default for Smoother<S>
+where
+    S: KFState,
+    DefaultAllocator: Allocator<S::Dim, S::Dim>,
+{
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Matrix3, Vector3};
+
+    use super::*;
+    use crate::eskf::state::common::PositionState;
+
+    /// A static position observed directly, whose smoothed estimations are all the final filtered one.
+    #[test]
+    fn test_static_smoothing() {
+        let measurements = [1.0, 3.0, 2.0, 2.5, 1.5];
+        let noise = 0.5;
+
+        let mut smoother = Smoother::<PositionState<f64>>::with_lag(2);
+        let mut filtered = Uncertained::new_with_cov(PositionState::default(), Matrix3::identity());
+        for (i, z) in measurements.into_iter().enumerate() {
+            smoother.record_transition(&Covariance(Matrix3::identity()));
+            let prior = filtered.clone();
+
+            let gain: Matrix3<f64> = filtered.cov.0 / (filtered.cov.0[(0, 0)] + noise);
+            let correction = gain * (Vector3::repeat(z) - filtered.state.0);
+            smoother.record_correction(correction.as_slice());
+            filtered.state += correction;
+            filtered.cov.0 -= gain * filtered.cov.0;
+
+            smoother.push(i as f64, prior, filtered.clone());
+        }
+
+        let smoothed = smoother.smooth();
+        assert_eq!(smoothed.len(), measurements.len());
+        assert!(smoothed.iter().all(|smoothed| {
+            (smoothed.state.state.0 - filtered.state.0).amax() < 1e-9
+                && (smoothed.state.cov.0 - filtered.cov.0).amax() < 1e-9
+        }));
+
+        let popped = smoother.pop_lagged();
+        assert_eq!(popped.len(), measurements.len() - 2);
+        assert_eq!(smoother.len(), 2);
+        assert!(
+            popped
+                .iter()
+                .all(|smoothed| { (smoothed.state.state.0 - filtered.state.0).amax() < 1e-9 })
+        );
+    }
+}
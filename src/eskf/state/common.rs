@@ -16,7 +16,7 @@ use std::{
 
 use nalgebra::{
     ClosedAddAssign, DefaultAllocator, Dim, IsometryMatrix3, RealField, Rotation3, Scalar, Storage,
-    Translation3, U0, U3, U6, Vector, Vector3, allocator::Allocator,
+    Translation3, U0, U1, U3, U6, Vector, Vector1, Vector3, allocator::Allocator,
 };
 use num_traits::Zero;
 use odometries_macros::{KFState, Unbiased, VectorAddAssign};
@@ -24,9 +24,11 @@ use odometries_macros::{KFState, Unbiased, VectorAddAssign};
 #[derive(Debug)]
 pub struct MarkedState<S, M>(pub S, PhantomData<M>);
 
+pub type ScalarState<T, S> = MarkedState<Vector1<T>, S>;
 pub type Vector3State<T, S> = MarkedState<Vector3<T>, S>;
 pub type IsometryState<T, F, S> = MarkedState<IsometryFramed<T, F>, S>;
 
+impl<T, S> Unbiased for ScalarState<T, S> {}
 impl<T, S> Unbiased for Vector3State<T, S> {}
 impl<T, F, S> Unbiased for IsometryState<T, F, S> {}
 
@@ -40,7 +42,7 @@ pub type LinearAccBiasState<T> = Vector3State<T, marker::AccBias>;
 pub type AngularAccState<T> = Vector3State<T, marker::AngularAcc>;
 pub type AngularAccBiasState<T> = Vector3State<T, marker::GyroBias>;
 
-#[derive(KFState, VectorAddAssign)]
+#[derive(Clone, KFState, VectorAddAssign)]
 #[element(T)]
 #[vector_add_assign(predicates(ClosedAddAssign))]
 pub struct AccWithBiasState<T: Scalar> {
@@ -48,7 +50,7 @@ pub struct AccWithBiasState<T: Scalar> {
     pub bias: BiasState<T>,
 }
 
-#[derive(Debug, KFState, VectorAddAssign, Unbiased)]
+#[derive(Debug, Clone, KFState, VectorAddAssign, Unbiased)]
 #[element(T)]
 #[vector_add_assign(predicates(ClosedAddAssign))]
 pub struct AccState<T: Scalar> {
@@ -57,7 +59,7 @@ pub struct AccState<T: Scalar> {
     /// Unit: rad/s
     pub angular: AngularAccState<T>,
 }
-#[derive(KFState, VectorAddAssign, Unbiased)]
+#[derive(Clone, KFState, VectorAddAssign, Unbiased)]
 #[element(T)]
 #[vector_add_assign(predicates(ClosedAddAssign))]
 pub struct BiasState<T: Scalar> {
@@ -65,6 +67,21 @@ pub struct BiasState<T: Scalar> {
     pub angular: AngularAccBiasState<T>,
 }
 
+impl<T: Scalar, M> super::KFState for ScalarState<T, M> {
+    type Element = T;
+    type Dim = U1;
+}
+
+impl<T, S, M> AddAssign<Vector<T, U1, S>> for ScalarState<T, M>
+where
+    T: Scalar + ClosedAddAssign,
+    S: Storage<T, U1>,
+{
+    fn add_assign(&mut self, rhs: Vector<T, U1, S>) {
+        self.0 += rhs;
+    }
+}
+
 impl<T: Scalar, M> super::KFState for Vector3State<T, M> {
     type Element = T;
     type Dim = U3;
@@ -184,6 +201,16 @@ impl<S: Clone, M> Clone for MarkedState<S, M> {
     }
 }
 
+impl<T, M> Default for ScalarState<T, M>
+where
+    T: Scalar + Zero,
+{
+    #[inline]
+    fn default() -> Self {
+        Self(Vector1::zeros(), PhantomData)
+    }
+}
+
 impl<T, M> Default for Vector3State<T, M>
 where
     T: Scalar + Zero,
@@ -1,7 +1,7 @@
 use std::marker::PhantomData;
 
 use crate::utils::AnyStorageMatrix;
-use nalgebra::{DefaultAllocator, Dim, DimName, allocator::Allocator};
+use nalgebra::{DefaultAllocator, Dim, DimName, U0, allocator::Allocator};
 
 use super::{KFState, SubStateOf};
 
@@ -67,3 +67,35 @@ where
         s.rows_generic(S::Offset::DIM, Self::CorDim::name())
     }
 }
+
+/// The whole state `S` as a sub-state of itself,
+/// which is used to observe the sub-states scattered in `S` at once.
+#[derive(Debug)]
+pub struct FullState<S>(PhantomData<S>);
+
+impl<S: KFState> KFState for FullState<S> {
+    type Element = S::Element;
+    type Dim = S::Dim;
+}
+
+impl<S: KFState> SubStateOf<S> for FullState<S> {
+    type Offset = U0;
+}
+
+impl<S: KFState> CorrelateTo<S> for FullState<S> {
+    type CorDim = S::Dim;
+
+    #[inline(always)]
+    fn correlate_to<D: Dim>(
+        s: &AnyStorageMatrix!(S::Element, D, S::Dim),
+    ) -> AnyStorageMatrix!(S::Element, D, Self::CorDim) {
+        s.columns_generic(0, Self::CorDim::name())
+    }
+
+    #[inline(always)]
+    fn correlate_from<D: Dim>(
+        s: &AnyStorageMatrix!(S::Element, S::Dim, D),
+    ) -> AnyStorageMatrix!(S::Element, Self::CorDim, D) {
+        s.rows_generic(0, Self::CorDim::name())
+    }
+}
@@ -0,0 +1,301 @@
+//! Incremental k-d tree map, most of ideas comes from ikd-Tree.
+//!
+//! The tree supports point-wise insertion with optional on-tree downsampling,
+//! lazy deletion by point or by box, and k-nearest search.
+//! Unbalanced or mostly deleted subtrees are rebuilt on the fly.
+
+mod node;
+mod search;
+
+use std::ops::Deref;
+
+use nalgebra::{Point3, RealField, Scalar};
+use simba::scalar::SupersetOf;
+
+use crate::frame::WorldPoint;
+
+use node::Node;
+pub use search::Neighbour;
+
+pub struct KdTree<T: Scalar> {
+    root: Option<Box<Node<T>>>,
+    config: Config<T>,
+}
+
+pub struct Config<T> {
+    /// A subtree is rebuilt if one of its children holds more than this ratio of its points.
+    pub balance_alpha: T,
+    /// A subtree is rebuilt if more than this ratio of its points are deleted.
+    pub delete_alpha: T,
+    /// Subtrees smaller than this are never rebuilt.
+    pub min_rebuild_size: usize,
+    /// Leaf size of the downsample grid used by [`KdTree::insert_downsampled`].
+    pub downsample_resolution: T,
+}
+
+/// An axis aligned box.
+#[derive(Debug, Clone)]
+pub struct BoxRegion<T: Scalar> {
+    pub min: Point3<T>,
+    pub max: Point3<T>,
+}
+
+impl<T> Default for Config<T>
+where
+    T: SupersetOf<f64>,
+{
+    fn default() -> Self {
+        Self {
+            balance_alpha: nalgebra::convert(0.7),
+            delete_alpha: nalgebra::convert(0.5),
+            min_rebuild_size: 10,
+            downsample_resolution: nalgebra::convert(0.5),
+        }
+    }
+}
+
+impl<T: Scalar> KdTree<T> {
+    pub const fn new(config: Config<T>) -> Self {
+        Self { root: None, config }
+    }
+
+    /// The number of the points in the tree, excluding the deleted ones.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.root
+            .as_ref()
+            .map_or(0, |root| root.size - root.deleted_size)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &WorldPoint<T>> {
+        let mut stack = Vec::from_iter(self.root.as_deref());
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.left.as_deref());
+            stack.extend(node.right.as_deref());
+            Some(node)
+        })
+        .filter(|node| !node.is_deleted)
+        .map(|node| &node.point)
+    }
+}
+
+impl<T: RealField> KdTree<T> {
+    pub fn insert(&mut self, point: WorldPoint<T>) {
+        Node::insert(&mut self.root, point, &self.config);
+    }
+
+    /// Insert the `point` only if it is the nearest point to the center of its downsample grid,
+    /// the other points in the same grid will be deleted.
+    ///
+    /// Returns `true` if the point is inserted.
+    pub fn insert_downsampled(&mut self, point: WorldPoint<T>) -> bool {
+        let resolution = self.config.downsample_resolution.clone();
+        let min = point.map(|x| (x / resolution.clone()).floor() * resolution.clone());
+        let max = min.map(|x| x + resolution.clone());
+        let center = nalgebra::center(&min, &max);
+        let region = BoxRegion { min, max };
+
+        let distance = nalgebra::distance_squared(point.deref(), &center);
+        let has_nearer = self
+            .search_box(&region)
+            .any(|exist| nalgebra::distance_squared(exist, &center) <= distance);
+        if has_nearer {
+            return false;
+        }
+
+        self.delete_box(&region);
+        self.insert(point);
+        true
+    }
+
+    /// Lazily delete the point which equals to the given `point`.
+    ///
+    /// Returns `true` if a point is deleted.
+    pub fn delete(&mut self, point: &WorldPoint<T>) -> bool {
+        let region = BoxRegion {
+            min: point.deref().clone(),
+            max: point.deref().clone(),
+        };
+        self.delete_box(&region) > 0
+    }
+
+    /// Lazily delete all the points inside the `region`.
+    ///
+    /// Returns the number of deleted points.
+    pub fn delete_box(&mut self, region: &BoxRegion<T>) -> usize {
+        Node::delete_if(
+            &mut self.root,
+            &|node| !region.intersects(&node.bounds),
+            &|point| region.contains(point),
+            &self.config,
+        )
+    }
+
+    /// Lazily delete all the points outside the `region`.
+    ///
+    /// Returns the number of deleted points.
+    pub fn delete_outside_box(&mut self, region: &BoxRegion<T>) -> usize {
+        Node::delete_if(
+            &mut self.root,
+            &|node| region.contains_box(&node.bounds),
+            &|point| !region.contains(point),
+            &self.config,
+        )
+    }
+
+    /// Search the `k` nearest points of the given `point`,
+    /// ordered from the nearest to the farthest.
+    pub fn nearest(&self, point: &Point3<T>, k: usize) -> Vec<Neighbour<'_, T>> {
+        search::nearest(self.root.as_deref(), point, k)
+    }
+
+    /// Iterate the points inside the `region`.
+    pub fn search_box<'a>(
+        &'a self,
+        region: &'a BoxRegion<T>,
+    ) -> impl Iterator<Item = &'a WorldPoint<T>> {
+        search::search_box(self.root.as_deref(), region)
+    }
+}
+
+impl<T> Extend<WorldPoint<T>> for KdTree<T>
+where
+    T: RealField,
+{
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = WorldPoint<T>>,
+    {
+        iter.into_iter().for_each(|point| self.insert(point));
+    }
+}
+
+impl<T: RealField> BoxRegion<T> {
+    /// A cube centered at `center` with the given `side_length`.
+    pub fn new_cube(center: &Point3<T>, side_length: T) -> Self {
+        let half: T = side_length / nalgebra::convert(2.0);
+        Self {
+            min: center.map(|x| x - half.clone()),
+            max: center.map(|x| x + half.clone()),
+        }
+    }
+
+    #[inline]
+    pub fn contains(&self, point: &Point3<T>) -> bool {
+        itertools::multizip((self.min.iter(), self.max.iter(), point.iter()))
+            .all(|(min, max, x)| min <= x && x <= max)
+    }
+
+    #[inline]
+    pub fn contains_box(&self, other: &Self) -> bool {
+        self.contains(&other.min) && self.contains(&other.max)
+    }
+
+    #[inline]
+    pub fn intersects(&self, other: &Self) -> bool {
+        itertools::multizip((
+            self.min.iter(),
+            self.max.iter(),
+            other.min.iter(),
+            other.max.iter(),
+        ))
+        .all(|(min, max, other_min, other_max)| min <= other_max && other_min <= max)
+    }
+
+    /// The squared distance from the `point` to this box, zero if the point is inside.
+    pub fn distance_squared(&self, point: &Point3<T>) -> T {
+        itertools::multizip((self.min.iter(), self.max.iter(), point.iter()))
+            .map(|(min, max, x)| {
+                if x < min {
+                    (min.clone() - x.clone()).powi(2)
+                } else if x > max {
+                    (x.clone() - max.clone()).powi(2)
+                } else {
+                    T::zero()
+                }
+            })
+            .fold(T::zero(), |acc, x| acc + x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    /// The points drawn from the `seed`, so the same on every run.
+    fn random_points(seed: u64, n: usize) -> Vec<WorldPoint<f64>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..n)
+            .map(|_| {
+                let point = Point3::new(
+                    rng.random_range(-10.0..10.0),
+                    rng.random_range(-10.0..10.0),
+                    rng.random_range(-10.0..10.0),
+                );
+                WorldPoint::new(point)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_nearest_matches_brute_force() {
+        let points = random_points(0, 2000);
+        let mut tree = KdTree::new(Config::default());
+        tree.extend(points.iter().cloned());
+        assert_eq!(tree.len(), points.len());
+
+        random_points(1, 50).iter().for_each(|query| {
+            let neighbours = tree.nearest(query, 5);
+
+            let mut expected = points
+                .iter()
+                .map(|point| nalgebra::distance_squared(point, query))
+                .collect::<Vec<_>>();
+            expected.sort_by(f64::total_cmp);
+
+            let distances = neighbours
+                .iter()
+                .map(|neighbour| neighbour.distance_squared)
+                .collect::<Vec<_>>();
+            assert_eq!(distances, expected[..5]);
+        });
+    }
+
+    #[test]
+    fn test_delete_box() {
+        let points = random_points(2, 2000);
+        let mut tree = KdTree::new(Config::default());
+        tree.extend(points.iter().cloned());
+
+        let region = BoxRegion::new_cube(&Point3::origin(), 10.0);
+        let inside = points.iter().filter(|point| region.contains(point)).count();
+
+        assert_eq!(tree.delete_box(&region), inside);
+        assert_eq!(tree.len(), points.len() - inside);
+        assert_eq!(tree.search_box(&region).count(), 0);
+        assert!(tree.iter().all(|point| !region.contains(point)));
+
+        let remains = tree.len();
+        assert_eq!(tree.delete_outside_box(&region), remains);
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_insert_downsampled() {
+        let mut tree = KdTree::new(Config::default());
+        assert!(tree.insert_downsampled(WorldPoint::new(Point3::new(0.1, 0.1, 0.1))));
+        // nearer to the grid center
+        assert!(tree.insert_downsampled(WorldPoint::new(Point3::new(0.2, 0.2, 0.2))));
+        // farther from the grid center
+        assert!(!tree.insert_downsampled(WorldPoint::new(Point3::new(0.4, 0.4, 0.4))));
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree.iter().next().map(|p| p.x), Some(0.2));
+    }
+}
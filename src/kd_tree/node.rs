@@ -0,0 +1,203 @@
+use std::{cmp::Ordering, ops::Deref};
+
+use nalgebra::{Point3, RealField, Scalar};
+
+use crate::frame::WorldPoint;
+
+use super::{BoxRegion, Config};
+
+pub(crate) struct Node<T: Scalar> {
+    pub(crate) point: WorldPoint<T>,
+    /// The split axis of this node.
+    axis: usize,
+    pub(crate) left: Option<Box<Node<T>>>,
+    pub(crate) right: Option<Box<Node<T>>>,
+    /// The number of the points in this subtree, including the deleted ones.
+    pub(crate) size: usize,
+    /// The number of the deleted points in this subtree.
+    pub(crate) deleted_size: usize,
+    /// Whether the point of this node is lazily deleted.
+    pub(crate) is_deleted: bool,
+    /// The bounding box of this subtree, including the deleted points.
+    pub(crate) bounds: BoxRegion<T>,
+}
+
+impl<T: RealField> Node<T> {
+    fn new_leaf(point: WorldPoint<T>, axis: usize) -> Self {
+        let bounds = BoxRegion {
+            min: point.deref().clone(),
+            max: point.deref().clone(),
+        };
+        Self {
+            point,
+            axis,
+            left: None,
+            right: None,
+            size: 1,
+            deleted_size: 0,
+            is_deleted: false,
+            bounds,
+        }
+    }
+
+    /// Build a balanced tree by splitting the points at the median of the widest axis.
+    pub(crate) fn build(mut points: Vec<WorldPoint<T>>) -> Option<Box<Self>> {
+        if points.is_empty() {
+            return None;
+        }
+        let bounds = points.iter().fold(
+            BoxRegion {
+                min: points[0].deref().clone(),
+                max: points[0].deref().clone(),
+            },
+            |bounds, point| BoxRegion {
+                min: bounds.min.inf(point),
+                max: bounds.max.sup(point),
+            },
+        );
+        let (axis, _) = (bounds.max - bounds.min).argmax();
+
+        let median = points.len() / 2;
+        points.select_nth_unstable_by(median, |a, b| {
+            a[axis].partial_cmp(&b[axis]).unwrap_or(Ordering::Equal)
+        });
+        let right = points.split_off(median + 1);
+        let point = points.pop()?;
+
+        let mut node = Box::new(Self::new_leaf(point, axis));
+        node.left = Self::build(points);
+        node.right = Self::build(right);
+        node.pull_update();
+        Some(node)
+    }
+
+    /// Recompute the size and the bounds of this subtree from its children.
+    fn pull_update(&mut self) {
+        let children = [self.left.as_deref(), self.right.as_deref()];
+        let children = children.iter().flatten();
+
+        self.size = 1 + children.clone().map(|child| child.size).sum::<usize>();
+        self.deleted_size = usize::from(self.is_deleted)
+            + children
+                .clone()
+                .map(|child| child.deleted_size)
+                .sum::<usize>();
+        self.bounds = children.fold(
+            BoxRegion {
+                min: self.point.deref().clone(),
+                max: self.point.deref().clone(),
+            },
+            |bounds, child| BoxRegion {
+                min: bounds.min.inf(&child.bounds.min),
+                max: bounds.max.sup(&child.bounds.max),
+            },
+        );
+    }
+
+    fn needs_rebuild(&self, config: &Config<T>) -> bool {
+        if self.size < config.min_rebuild_size {
+            return false;
+        }
+        let size: T = nalgebra::convert(self.size as f64);
+        let children_size = [&self.left, &self.right].map(|child| {
+            let child_size = child.as_ref().map_or(0, |child| child.size);
+            nalgebra::convert::<_, T>(child_size as f64)
+        });
+        let deleted_size: T = nalgebra::convert(self.deleted_size as f64);
+
+        children_size
+            .into_iter()
+            .any(|child_size| child_size > config.balance_alpha.clone() * size.clone())
+            || deleted_size > config.delete_alpha.clone() * size
+    }
+
+    /// Collect the points which are not deleted in this subtree.
+    fn collect_valid(self, points: &mut Vec<WorldPoint<T>>) {
+        let Self {
+            point,
+            left,
+            right,
+            is_deleted,
+            ..
+        } = self;
+        if !is_deleted {
+            points.push(point);
+        }
+        [left, right]
+            .into_iter()
+            .flatten()
+            .for_each(|child| (*child).collect_valid(points));
+    }
+
+    fn rebuild(node: &mut Option<Box<Self>>) {
+        let Some(root) = node.take() else {
+            return;
+        };
+        let mut points = Vec::with_capacity(root.size - root.deleted_size);
+        (*root).collect_valid(&mut points);
+        *node = Self::build(points);
+    }
+
+    fn rebuild_if_needed(node: &mut Option<Box<Self>>, config: &Config<T>) {
+        if node.as_ref().is_some_and(|node| node.needs_rebuild(config)) {
+            Self::rebuild(node);
+        }
+    }
+
+    pub(crate) fn insert(node: &mut Option<Box<Self>>, point: WorldPoint<T>, config: &Config<T>) {
+        let Some(current) = node else {
+            *node = Some(Box::new(Self::new_leaf(point, 0)));
+            return;
+        };
+
+        let axis = current.axis;
+        let child = if point[axis] < current.point[axis] {
+            &mut current.left
+        } else {
+            &mut current.right
+        };
+        match child {
+            Some(_) => Self::insert(child, point, config),
+            None => *child = Some(Box::new(Self::new_leaf(point, (axis + 1) % 3))),
+        }
+        current.pull_update();
+
+        Self::rebuild_if_needed(node, config);
+    }
+
+    /// Lazily delete the points which `should_delete` returns `true`,
+    /// the subtrees which `skip_subtree` returns `true` are not visited.
+    ///
+    /// Returns the number of deleted points.
+    pub(crate) fn delete_if(
+        node: &mut Option<Box<Self>>,
+        skip_subtree: &impl Fn(&Self) -> bool,
+        should_delete: &impl Fn(&Point3<T>) -> bool,
+        config: &Config<T>,
+    ) -> usize {
+        let Some(current) = node else {
+            return 0;
+        };
+        if current.size == current.deleted_size || skip_subtree(current) {
+            return 0;
+        }
+
+        let mut deleted = 0;
+        if !current.is_deleted && should_delete(&current.point) {
+            current.is_deleted = true;
+            deleted += 1;
+        }
+        deleted += Self::delete_if(&mut current.left, skip_subtree, should_delete, config);
+        deleted += Self::delete_if(&mut current.right, skip_subtree, should_delete, config);
+
+        if deleted > 0 {
+            current.pull_update();
+            if current.size == current.deleted_size {
+                *node = None;
+            } else {
+                Self::rebuild_if_needed(node, config);
+            }
+        }
+        deleted
+    }
+}
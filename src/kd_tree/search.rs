@@ -0,0 +1,120 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use nalgebra::{Point3, RealField, Scalar};
+
+use crate::frame::WorldPoint;
+
+use super::{BoxRegion, node::Node};
+
+#[derive(Debug)]
+pub struct Neighbour<'a, T: Scalar> {
+    pub point: &'a WorldPoint<T>,
+    pub distance_squared: T,
+}
+
+pub(crate) fn nearest<'a, T: RealField>(
+    root: Option<&'a Node<T>>,
+    point: &Point3<T>,
+    k: usize,
+) -> Vec<Neighbour<'a, T>> {
+    let mut heap = BinaryHeap::with_capacity(k + 1);
+    if let Some(root) = root
+        && k > 0
+    {
+        search_nearest(root, point, k, &mut heap);
+    }
+    heap.into_sorted_vec()
+}
+
+fn search_nearest<'a, T: RealField>(
+    node: &'a Node<T>,
+    point: &Point3<T>,
+    k: usize,
+    heap: &mut BinaryHeap<Neighbour<'a, T>>,
+) {
+    if node.size == node.deleted_size || !may_be_nearer(heap, k, &node.bounds, point) {
+        return;
+    }
+
+    if !node.is_deleted {
+        let distance_squared = nalgebra::distance_squared(&node.point, point);
+        heap.push(Neighbour {
+            point: &node.point,
+            distance_squared,
+        });
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+
+    let children = [node.left.as_deref(), node.right.as_deref()];
+    let mut children = children.into_iter().flatten().collect::<Vec<_>>();
+    // visit the nearer child first for better pruning
+    children.sort_by(|a, b| {
+        let a = a.bounds.distance_squared(point);
+        let b = b.bounds.distance_squared(point);
+        a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+    });
+    children
+        .into_iter()
+        .for_each(|child| search_nearest(child, point, k, heap));
+}
+
+fn may_be_nearer<T: RealField>(
+    heap: &BinaryHeap<Neighbour<'_, T>>,
+    k: usize,
+    bounds: &BoxRegion<T>,
+    point: &Point3<T>,
+) -> bool {
+    match heap.peek() {
+        Some(farthest) if heap.len() >= k => {
+            bounds.distance_squared(point) < farthest.distance_squared
+        }
+        _ => true,
+    }
+}
+
+pub(crate) fn search_box<'a, T: RealField>(
+    root: Option<&'a Node<T>>,
+    region: &'a BoxRegion<T>,
+) -> impl Iterator<Item = &'a WorldPoint<T>> {
+    let mut stack = Vec::from_iter(root);
+    std::iter::from_fn(move || {
+        loop {
+            let node = stack.pop()?;
+            if node.size == node.deleted_size || !region.intersects(&node.bounds) {
+                continue;
+            }
+            stack.extend(node.left.as_deref());
+            stack.extend(node.right.as_deref());
+            if !node.is_deleted && region.contains(&node.point) {
+                return Some(&node.point);
+            }
+        }
+    })
+}
+
+impl<T: RealField> PartialEq for Neighbour<'_, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T: RealField> Eq for Neighbour<'_, T> {}
+
+impl<T: RealField> PartialOrd for Neighbour<'_, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The neighbours are ordered by the distance, and a `NaN` distance is farther than any other,
+/// so that it is the first to be dropped from the nearest ones.
+impl<T: RealField> Ord for Neighbour<'_, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let is_nan = |distance: &T| distance.partial_cmp(distance).is_none();
+        let (a, b) = (&self.distance_squared, &other.distance_squared);
+        a.partial_cmp(b)
+            .unwrap_or_else(|| is_nan(a).cmp(&is_nan(b)))
+    }
+}
@@ -6,5 +6,7 @@
 pub mod algorithm;
 pub mod eskf;
 pub mod frame;
+pub mod kd_tree;
+pub mod trajectory;
 mod utils;
 pub mod voxel_map;
@@ -0,0 +1,179 @@
+//! The trajectory of the estimated states, which can be exported for the evaluation.
+
+use std::io::{self, Write};
+
+use nalgebra::{Matrix6, RealField, Scalar, UnitQuaternion, Vector3};
+
+use crate::frame::{IsometryFramed, frames};
+
+pub mod evaluation;
+
+/// A timestamped estimation of the pose.
+#[derive(Debug, Clone)]
+pub struct TrajectoryPoint<T: Scalar> {
+    pub timestamp: T,
+    pub pose: IsometryFramed<T, fn(frames::Imu) -> frames::World>,
+    /// The velocity in the world frame.
+    pub velocity: Vector3<T>,
+    /// The covariance of the pose error, the rotation comes first and then the position.
+    pub pose_cov: Matrix6<T>,
+}
+
+/// The recorded [`TrajectoryPoint`]s ordered by the timestamp.
+#[derive(Debug, Clone)]
+pub struct Trajectory<T: Scalar> {
+    points: Vec<TrajectoryPoint<T>>,
+}
+
+impl<T: Scalar> Trajectory<T> {
+    #[inline]
+    pub const fn new() -> Self {
+        Self { points: Vec::new() }
+    }
+
+    #[inline]
+    pub fn push(&mut self, point: TrajectoryPoint<T>) {
+        self.points.push(point);
+    }
+
+    #[inline]
+    pub fn points(&self) -> &[TrajectoryPoint<T>] {
+        &self.points
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+}
+
+impl<T: RealField> Trajectory<T> {
+    /// Write the trajectory in the TUM format, one pose per line:
+    ///
+    /// ```text
+    /// timestamp tx ty tz qx qy qz qw
+    /// ```
+    pub fn write_tum(&self, mut writer: impl Write) -> io::Result<()> {
+        self.points.iter().try_for_each(|point| {
+            let translation = &point.pose.translation.vector;
+            let rotation = UnitQuaternion::from_rotation_matrix(&point.pose.rotation);
+            let values = [point.timestamp.clone()]
+                .into_iter()
+                .chain(translation.iter().cloned())
+                .chain(rotation.coords.iter().cloned());
+            write_line(&mut writer, values)
+        })
+    }
+
+    /// Write the trajectory in the KITTI format,
+    /// one pose per line as the row-major 3x4 matrix `[R | t]` without the timestamp.
+    pub fn write_kitti(&self, mut writer: impl Write) -> io::Result<()> {
+        self.points.iter().try_for_each(|point| {
+            let matrix = point.pose.to_matrix();
+            let values = (0..3).flat_map(|row| (0..4).map(move |col| (row, col)));
+            write_line(&mut writer, values.map(|index| matrix[index].clone()))
+        })
+    }
+}
+
+fn write_line<T: RealField>(
+    writer: &mut impl Write,
+    values: impl Iterator<Item = T>,
+) -> io::Result<()> {
+    for (i, value) in values.enumerate() {
+        let value: f64 = value.to_subset_unchecked();
+        if i == 0 {
+            write!(writer, "{value}")?;
+        } else {
+            write!(writer, " {value}")?;
+        }
+    }
+    writeln!(writer)
+}
+
+impl<T: Scalar> Default for Trajectory<T> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Scalar> FromIterator<TrajectoryPoint<T>> for Trajectory<T> {
+    fn from_iter<I: IntoIterator<Item = TrajectoryPoint<T>>>(iter: I) -> Self {
+        Self {
+            points: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl<T: Scalar> Extend<TrajectoryPoint<T>> for Trajectory<T> {
+    fn extend<I: IntoIterator<Item = TrajectoryPoint<T>>>(&mut self, iter: I) {
+        self.points.extend(iter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{IsometryMatrix3, Rotation3, Translation3};
+
+    use super::*;
+    use crate::frame::Framed;
+
+    #[test]
+    fn test_export() -> io::Result<()> {
+        let pose = IsometryMatrix3::from_parts(
+            Translation3::new(1.0, 2.0, 3.0),
+            Rotation3::from_axis_angle(&Vector3::z_axis(), std::f64::consts::FRAC_PI_2),
+        );
+        let trajectory = Trajectory::from_iter([TrajectoryPoint {
+            timestamp: 0.5,
+            pose: Framed::new(pose),
+            velocity: Vector3::zeros(),
+            pose_cov: Matrix6::zeros(),
+        }]);
+
+        let mut tum = Vec::new();
+        trajectory.write_tum(&mut tum)?;
+        let tum = String::from_utf8_lossy(&tum);
+        let values = tum
+            .split_whitespace()
+            .map(|x| x.parse::<f64>().unwrap_or(f64::NAN))
+            .collect::<Vec<_>>();
+        let half_sqrt2 = std::f64::consts::FRAC_1_SQRT_2;
+        let expected = [0.5, 1.0, 2.0, 3.0, 0.0, 0.0, half_sqrt2, half_sqrt2];
+        assert_eq!(values.len(), expected.len());
+        assert!(
+            values
+                .iter()
+                .zip(expected)
+                .all(|(a, b)| (a - b).abs() < 1e-12)
+        );
+
+        let mut kitti = Vec::new();
+        trajectory.write_kitti(&mut kitti)?;
+        let kitti = String::from_utf8_lossy(&kitti);
+        let values = kitti
+            .split_whitespace()
+            .map(|x| x.parse::<f64>().unwrap_or(f64::NAN))
+            .collect::<Vec<_>>();
+        let expected = [0.0, -1.0, 0.0, 1.0, 1.0, 0.0, 0.0, 2.0, 0.0, 0.0, 1.0, 3.0];
+        assert_eq!(values.len(), expected.len());
+        assert!(
+            values
+                .iter()
+                .zip(expected)
+                .all(|(a, b)| (a - b).abs() < 1e-12)
+        );
+        Ok(())
+    }
+}
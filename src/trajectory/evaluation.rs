@@ -0,0 +1,416 @@
+//! The evaluation of an estimated [`Trajectory`] against the ground truth,
+//! including the absolute trajectory error (ATE) and the relative pose error (RPE).
+
+use std::cmp::Ordering;
+
+use nalgebra::{IsometryMatrix3, Matrix3, RealField, Rotation3, Scalar, UnitQuaternion, Vector3};
+
+use super::Trajectory;
+
+/// The alignment of the estimated trajectory to the ground truth before the evaluation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    /// Evaluate the trajectories as they are.
+    None,
+    /// Align with a rigid transformation, see also [`umeyama`].
+    SE3,
+    /// Align with a similarity transformation, which also corrects the scale, see also [`umeyama`].
+    Sim3,
+}
+
+pub struct EvaluationConfig<T> {
+    pub alignment: Alignment,
+    /// The maximum timestamp difference to match an estimated pose with its nearest ground truth.
+    pub max_time_diff: T,
+    /// The segment lengths of the relative pose error, measured by the distance traveled along the ground truth.
+    pub segment_lengths: Vec<T>,
+}
+
+/// The similarity transformation `y = scale * rotation * x + translation`.
+#[derive(Debug, Clone)]
+pub struct AlignTransform<T: Scalar> {
+    pub rotation: Rotation3<T>,
+    pub translation: Vector3<T>,
+    pub scale: T,
+}
+
+/// The statistics of the errors.
+#[derive(Debug, Clone)]
+pub struct ErrorStats<T> {
+    pub rmse: T,
+    pub mean: T,
+    pub median: T,
+    pub max: T,
+    /// The number of the errors.
+    pub count: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct AteReport<T: Scalar> {
+    /// The transformation aligning the estimated trajectory to the ground truth.
+    pub alignment: AlignTransform<T>,
+    /// The statistics of the position errors after the alignment.
+    pub translation: ErrorStats<T>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RpeReport<T> {
+    pub segment_length: T,
+    /// The statistics of the relative translation errors.
+    pub translation: ErrorStats<T>,
+    /// The statistics of the relative rotation errors in radians.
+    pub rotation: ErrorStats<T>,
+}
+
+/// Match every estimated pose with the nearest ground truth pose by the timestamp,
+/// the pairs whose timestamp difference exceeds `max_time_diff` are dropped.
+///
+/// Returns the indices of the matched poses, the ground truth should be ordered by the timestamp.
+pub fn associate<T: RealField>(
+    estimated: &Trajectory<T>,
+    ground_truth: &Trajectory<T>,
+    max_time_diff: &T,
+) -> Vec<(usize, usize)> {
+    let ground_truth = ground_truth.points();
+    estimated
+        .points()
+        .iter()
+        .enumerate()
+        .filter_map(|(i, point)| {
+            let timestamp = &point.timestamp;
+            let next = ground_truth.partition_point(|truth| truth.timestamp < *timestamp);
+            let time_diff =
+                |j: usize| (ground_truth[j].timestamp.clone() - timestamp.clone()).abs();
+            let nearest = [next.checked_sub(1), Some(next)]
+                .into_iter()
+                .flatten()
+                .filter(|j| *j < ground_truth.len())
+                .min_by(|a, b| {
+                    time_diff(*a)
+                        .partial_cmp(&time_diff(*b))
+                        .unwrap_or(Ordering::Equal)
+                })?;
+            (time_diff(nearest) <= *max_time_diff).then_some((i, nearest))
+        })
+        .collect()
+}
+
+/// Solve the [`AlignTransform`] minimizing `sum(|target - (scale * rotation * source + translation)|^2)`,
+/// the scale is fixed to one unless `with_scale`.
+///
+/// Returns `None` if there are less than 3 pairs of the points or the points are degenerate.
+///
+/// See also Least-Squares Estimation of Transformation Parameters Between Two Point Patterns, Shinji Umeyama, 1991.
+pub fn umeyama<T: RealField>(
+    source: &[Vector3<T>],
+    target: &[Vector3<T>],
+    with_scale: bool,
+) -> Option<AlignTransform<T>> {
+    let len = source.len();
+    if len < 3 || len != target.len() {
+        return None;
+    }
+    let n: T = nalgebra::convert(len as f64);
+    let mean = |points: &[Vector3<T>]| points.iter().sum::<Vector3<T>>() / n.clone();
+    let source_mean = mean(source);
+    let target_mean = mean(target);
+
+    let (cov, source_variance) = source.iter().zip(target).fold(
+        (Matrix3::zeros(), T::zero()),
+        |(cov, variance), (source, target)| {
+            let source = source - &source_mean;
+            let target = target - &target_mean;
+            let variance = variance + source.norm_squared();
+            (cov + target * source.transpose(), variance)
+        },
+    );
+    let cov = cov / n.clone();
+    let source_variance = source_variance / n;
+
+    let svd = cov.svd(true, true);
+    let (u, v_t) = (svd.u?, svd.v_t?);
+    let mut sign = Vector3::repeat(T::one());
+    if (u.determinant() * v_t.determinant()) < T::zero() {
+        sign.z = -T::one();
+    }
+    let rotation = &u * Matrix3::from_diagonal(&sign) * &v_t;
+    let scale = if with_scale {
+        if source_variance <= T::default_epsilon() {
+            return None;
+        }
+        svd.singular_values.dot(&sign) / source_variance
+    } else {
+        T::one()
+    };
+    let translation = &target_mean - &rotation * &source_mean * scale.clone();
+
+    Some(AlignTransform {
+        rotation: Rotation3::from_matrix_unchecked(rotation),
+        translation,
+        scale,
+    })
+}
+
+/// Evaluate the absolute trajectory error of the positions,
+/// returns `None` if not enough poses are matched for the alignment.
+pub fn absolute_trajectory_error<T: RealField>(
+    estimated: &Trajectory<T>,
+    ground_truth: &Trajectory<T>,
+    config: &EvaluationConfig<T>,
+) -> Option<AteReport<T>> {
+    let (estimated, ground_truth) = matched_poses(estimated, ground_truth, config)?;
+    let (alignment, estimated) = align(&estimated, &ground_truth, config.alignment)?;
+
+    let errors = estimated
+        .iter()
+        .zip(&ground_truth)
+        .map(|(estimated, truth)| {
+            (&estimated.translation.vector - &truth.translation.vector).norm()
+        })
+        .collect();
+
+    Some(AteReport {
+        alignment,
+        translation: ErrorStats::new(errors)?,
+    })
+}
+
+/// Evaluate the relative pose error over every segment length in [`EvaluationConfig::segment_lengths`],
+/// the segment lengths without any pair of poses far enough are skipped.
+pub fn relative_pose_error<T: RealField>(
+    estimated: &Trajectory<T>,
+    ground_truth: &Trajectory<T>,
+    config: &EvaluationConfig<T>,
+) -> Vec<RpeReport<T>> {
+    let Some((estimated, ground_truth)) = matched_poses(estimated, ground_truth, config) else {
+        return Vec::new();
+    };
+    let Some((_, estimated)) = align(&estimated, &ground_truth, config.alignment) else {
+        return Vec::new();
+    };
+
+    // the distance traveled along the ground truth
+    let distances = ground_truth.windows(2).scan(T::zero(), |distance, poses| {
+        *distance += (&poses[1].translation.vector - &poses[0].translation.vector).norm();
+        Some(distance.clone())
+    });
+    let distances = std::iter::once(T::zero())
+        .chain(distances)
+        .collect::<Vec<_>>();
+
+    config
+        .segment_lengths
+        .iter()
+        .filter_map(|segment_length| {
+            let (translation, rotation) = (0..distances.len())
+                .filter_map(|i| {
+                    let target = distances[i].clone() + segment_length.clone();
+                    let j = i + distances[i..].partition_point(|distance| *distance < target);
+                    let j = (j < distances.len()).then_some(j)?;
+
+                    let estimated_delta = estimated[i].inv_mul(&estimated[j]);
+                    let truth_delta = ground_truth[i].inv_mul(&ground_truth[j]);
+                    let error = truth_delta.inv_mul(&estimated_delta);
+                    // `Rotation3::angle` is NaN if the trace slightly exceeds 3 by the rounding errors
+                    let angle = UnitQuaternion::from_rotation_matrix(&error.rotation).angle();
+                    Some((error.translation.vector.norm(), angle))
+                })
+                .unzip();
+            Some(RpeReport {
+                segment_length: segment_length.clone(),
+                translation: ErrorStats::new(translation)?,
+                rotation: ErrorStats::new(rotation)?,
+            })
+        })
+        .collect()
+}
+
+type MatchedPoses<T> = (Vec<IsometryMatrix3<T>>, Vec<IsometryMatrix3<T>>);
+
+fn matched_poses<T: RealField>(
+    estimated: &Trajectory<T>,
+    ground_truth: &Trajectory<T>,
+    config: &EvaluationConfig<T>,
+) -> Option<MatchedPoses<T>> {
+    let pairs = associate(estimated, ground_truth, &config.max_time_diff);
+    if pairs.is_empty() {
+        return None;
+    }
+    Some(
+        pairs
+            .into_iter()
+            .map(|(i, j)| {
+                let estimated = (*estimated.points()[i].pose).clone();
+                let truth = (*ground_truth.points()[j].pose).clone();
+                (estimated, truth)
+            })
+            .unzip(),
+    )
+}
+
+/// Align the `estimated` poses to the `ground_truth` ones, returns the transformation and the aligned poses.
+fn align<T: RealField>(
+    estimated: &[IsometryMatrix3<T>],
+    ground_truth: &[IsometryMatrix3<T>],
+    alignment: Alignment,
+) -> Option<(AlignTransform<T>, Vec<IsometryMatrix3<T>>)> {
+    let positions = |poses: &[IsometryMatrix3<T>]| {
+        poses
+            .iter()
+            .map(|pose| pose.translation.vector.clone())
+            .collect::<Vec<_>>()
+    };
+    let transform = match alignment {
+        Alignment::None => AlignTransform {
+            rotation: Rotation3::identity(),
+            translation: Vector3::zeros(),
+            scale: T::one(),
+        },
+        Alignment::SE3 | Alignment::Sim3 => umeyama(
+            &positions(estimated),
+            &positions(ground_truth),
+            alignment == Alignment::Sim3,
+        )?,
+    };
+    let aligned = estimated
+        .iter()
+        .map(|pose| transform.transform_pose(pose))
+        .collect();
+    Some((transform, aligned))
+}
+
+impl<T: RealField> AlignTransform<T> {
+    /// Transform the `pose`, the scale is applied to the translation only.
+    pub fn transform_pose(&self, pose: &IsometryMatrix3<T>) -> IsometryMatrix3<T> {
+        let translation =
+            &self.rotation * &pose.translation.vector * self.scale.clone() + &self.translation;
+        IsometryMatrix3::from_parts(translation.into(), &self.rotation * &pose.rotation)
+    }
+}
+
+impl<T: RealField> ErrorStats<T> {
+    /// Returns `None` if the `errors` is empty.
+    pub fn new(mut errors: Vec<T>) -> Option<Self> {
+        let count = errors.len();
+        if count == 0 {
+            return None;
+        }
+        errors.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+        let n: T = nalgebra::convert(count as f64);
+        let squared_sum = errors
+            .iter()
+            .fold(T::zero(), |sum, error| sum + error.clone().powi(2));
+        let mean = errors
+            .iter()
+            .fold(T::zero(), |sum, error| sum + error.clone())
+            / n.clone();
+        let median = if count.is_multiple_of(2) {
+            (errors[count / 2 - 1].clone() + errors[count / 2].clone()) / nalgebra::convert(2.0)
+        } else {
+            errors[count / 2].clone()
+        };
+        Some(Self {
+            rmse: (squared_sum / n).sqrt(),
+            mean,
+            median,
+            max: errors[count - 1].clone(),
+            count,
+        })
+    }
+}
+
+impl<T: RealField> Default for EvaluationConfig<T> {
+    fn default() -> Self {
+        Self {
+            alignment: Alignment::SE3,
+            max_time_diff: nalgebra::convert(0.01),
+            segment_lengths: [1.0, 5.0, 10.0].map(nalgebra::convert).to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Matrix6, Translation3};
+
+    use super::*;
+    use crate::{frame::Framed, trajectory::TrajectoryPoint};
+
+    fn trajectory(poses: impl IntoIterator<Item = (f64, IsometryMatrix3<f64>)>) -> Trajectory<f64> {
+        poses
+            .into_iter()
+            .map(|(timestamp, pose)| TrajectoryPoint {
+                timestamp,
+                pose: Framed::new(pose),
+                velocity: Vector3::zeros(),
+                pose_cov: Matrix6::zeros(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sim3_aligned_errors() {
+        let ground_truth = (0..100).map(|i| {
+            let t = i as f64 * 0.1;
+            let pose = IsometryMatrix3::from_parts(
+                Translation3::new(t.cos() * 5.0, t.sin() * 5.0, t * 0.2),
+                Rotation3::from_axis_angle(&Vector3::z_axis(), t),
+            );
+            (t, pose)
+        });
+        let ground_truth = trajectory(ground_truth);
+
+        // the estimated trajectory in another frame with another scale, and with a small time offset
+        let transform = AlignTransform {
+            rotation: Rotation3::from_euler_angles(0.1, -0.2, 1.0),
+            translation: Vector3::new(1.0, -2.0, 3.0),
+            scale: 0.5,
+        };
+        let estimated = ground_truth.points().iter().map(|point| {
+            (
+                point.timestamp + 0.001,
+                transform.transform_pose(&point.pose),
+            )
+        });
+        let estimated = trajectory(estimated);
+
+        let config = EvaluationConfig {
+            alignment: Alignment::Sim3,
+            ..Default::default()
+        };
+        let ate = absolute_trajectory_error(&estimated, &ground_truth, &config);
+        let Some(ate) = ate else {
+            panic!("the trajectories should be matched");
+        };
+        assert_eq!(ate.translation.count, 100);
+        assert!(ate.translation.max < 1e-9);
+        assert!((ate.alignment.scale - 2.0).abs() < 1e-9);
+
+        let rpe = relative_pose_error(&estimated, &ground_truth, &config);
+        assert_eq!(rpe.len(), 3);
+        assert!(rpe.iter().all(|report| report.translation.max < 1e-9));
+        assert!(rpe.iter().all(|report| report.rotation.max < 1e-6));
+
+        // no alignment reveals the transformation
+        let config = EvaluationConfig {
+            alignment: Alignment::None,
+            ..config
+        };
+        let ate = absolute_trajectory_error(&estimated, &ground_truth, &config);
+        assert!(ate.is_some_and(|ate| ate.translation.rmse > 1.0));
+    }
+
+    #[test]
+    fn test_error_stats() {
+        let stats = ErrorStats::new(vec![3.0, 1.0, 4.0, 2.0]);
+        let Some(stats) = stats else {
+            panic!("the errors are not empty");
+        };
+        assert_eq!(stats.mean, 2.5);
+        assert_eq!(stats.median, 2.5);
+        assert_eq!(stats.max, 4.0);
+        assert_eq!(stats.rmse, 7.5f64.sqrt());
+        assert!(ErrorStats::<f64>::new(Vec::new()).is_none());
+    }
+}
@@ -116,10 +116,11 @@ impl<T: FloatCore> ToRadians for T {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct VectorSquareSum<T: Scalar> {
-    count: usize,
-    sum: Vector3<T>,
-    square_sum: Matrix3<T>,
+    pub(crate) count: usize,
+    pub(crate) sum: Vector3<T>,
+    pub(crate) square_sum: Matrix3<T>,
 }
 
 impl<T> VectorSquareSum<T>
@@ -136,6 +137,13 @@ where
     pub fn count(&self) -> usize {
         self.count
     }
+
+    #[inline]
+    pub fn push(&mut self, vector: &Vector3<T>) {
+        self.count += 1;
+        self.sum += vector;
+        self.square_sum += vector * vector.transpose();
+    }
 }
 
 impl<T> Default for VectorSquareSum<T>
@@ -160,9 +168,7 @@ where
         I: Iterator<Item = &'a Vector3<T>>,
     {
         iter.fold(Self::default(), |mut acc, current| {
-            acc.count += 1;
-            acc.sum += current;
-            acc.square_sum += current * current.transpose();
+            acc.push(current);
             acc
         })
     }
@@ -181,6 +187,22 @@ impl<I: Iterator> CollectTo for I {
     }
 }
 
+/// The parallel counterpart of [`CollectTo`], e.g. for the iterators of [`maybe_par_iter!`].
+#[cfg(feature = "rayon")]
+pub trait ParCollectTo: rayon::iter::ParallelIterator {
+    fn collect_to<T>(self, collection: &mut T) -> &mut T
+    where
+        T: rayon::iter::ParallelExtend<Self::Item>;
+}
+
+#[cfg(feature = "rayon")]
+impl<I: rayon::iter::ParallelIterator> ParCollectTo for I {
+    fn collect_to<T: rayon::iter::ParallelExtend<I::Item>>(self, collection: &mut T) -> &mut T {
+        collection.par_extend(self);
+        collection
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -22,3 +22,18 @@ macro_rules! AnyStorageVector {
     };
 }
 pub(crate) use AnyStorageVector;
+
+/// Iterate in parallel by [`rayon::iter::IntoParallelIterator::into_par_iter`] with the `rayon` feature,
+/// otherwise serially by [`IntoIterator::into_iter`].
+///
+/// Only the order-preserving adapters are used on the result, so both are bit-identical.
+macro_rules! maybe_par_iter {
+    ( $iter:expr ) => {{
+        #[cfg(feature = "rayon")]
+        let iter = rayon::iter::IntoParallelIterator::into_par_iter($iter);
+        #[cfg(not(feature = "rayon"))]
+        let iter = IntoIterator::into_iter($iter);
+        iter
+    }};
+}
+pub(crate) use maybe_par_iter;
@@ -1,16 +1,21 @@
+mod free_space;
 pub mod index;
 mod oct_tree;
 mod residual;
+mod shared;
+pub mod snapshot;
 pub mod uncertain;
 
-use std::ops::Deref;
+use std::{ops::Deref, sync::Arc};
 
+pub use free_space::FreeSpaceConfig;
 use nalgebra::{ComplexField, RealField};
-use nohash_hasher::IntMap;
-pub use residual::Residual;
+use nohash_hasher::{IntMap, IntSet};
+pub use residual::{Residual, ResidualCounts};
+pub use shared::SharedVoxelMap;
 use simba::scalar::SupersetOf;
 
-use crate::frame::frames;
+use crate::frame::{WorldPoint, frames};
 
 use index::{ToVoxelIndex, VoxelIndex};
 use oct_tree::OctTreeRoot;
@@ -21,12 +26,42 @@ use uncertain::{
 
 pub type MapIndex<T> = VoxelIndex<T, frames::World>;
 
+type Roots<T> = IntMap<MapIndex<T>, Arc<OctTreeRoot<T>>>;
+
+/// The voxel map, which is read through its [`VoxelMapView`] by [`Deref`].
+///
+/// The voxels are shared with the views taken from the map, see also [`VoxelMap::view`],
+/// and copied on write only if they are modified while a view is held.
 pub struct VoxelMap<T>
 where
     T: ComplexField,
 {
-    roots: IntMap<MapIndex<T>, OctTreeRoot<T>>,
-    config: Config<T>,
+    view: VoxelMapView<T>,
+    /// The position where the map sliding window was last updated.
+    last_slide_position: Option<WorldPoint<T>>,
+    /// The voxels never evicted by the sliding, see also [`VoxelMap::pin`].
+    pinned: IntSet<MapIndex<T>>,
+    /// The views published to the readers, see also [`VoxelMap::share`].
+    shared: Option<SharedVoxelMap<T>>,
+}
+
+/// A read-only view of the [`VoxelMap`] at the time it was taken, which is cheap to clone
+/// and can be read on other threads while the map is being updated.
+pub struct VoxelMapView<T>
+where
+    T: ComplexField,
+{
+    roots: Arc<Roots<T>>,
+    config: Arc<Config<T>>,
+}
+
+impl<T: ComplexField> Clone for VoxelMapView<T> {
+    fn clone(&self) -> Self {
+        Self {
+            roots: self.roots.clone(),
+            config: self.config.clone(),
+        }
+    }
 }
 
 pub struct Config<T> {
@@ -37,11 +72,15 @@ pub struct Config<T> {
     /// voxel size in the voxel grid
     pub voxel_size: T,
 
-    /// map size for map sliding window
+    /// map size for map sliding window, which is the side length of the window cube in voxels.
     pub map_size: usize,
 
     /// delta pose change threshold to update map sliding window
     pub sliding_thresh: T,
+
+    /// The removal of the voxels seen through by the scans, e.g. left by the moving objects,
+    /// `None` disables the removal, see also [`VoxelMap::insert_scan`].
+    pub free_space: Option<FreeSpaceConfig<T>>,
 }
 
 impl<T> Default for Config<T>
@@ -56,6 +95,7 @@ where
             voxel_size,
             map_size: 200,
             sliding_thresh: nalgebra::convert(8.0),
+            free_space: None,
         }
     }
 }
@@ -65,11 +105,62 @@ where
     T: ComplexField,
 {
     pub fn new(config: Config<T>) -> Self {
+        Self::from_roots(IntMap::default(), config)
+    }
+
+    fn from_roots(roots: Roots<T>, config: Config<T>) -> Self {
         Self {
-            roots: IntMap::default(),
-            config,
+            view: VoxelMapView {
+                roots: Arc::new(roots),
+                config: Arc::new(config),
+            },
+            last_slide_position: None,
+            pinned: IntSet::default(),
+            shared: None,
         }
     }
+
+    /// Pin the current voxels, e.g. those of a prior map, which are kept by [`VoxelMap::slide`]
+    /// however far the window slides away from them, and never removed by [`VoxelMap::insert_scan`].
+    pub fn pin(&mut self) {
+        self.pinned.extend(self.view.roots.keys().cloned());
+    }
+
+    /// Take a view of the current map, see also [`VoxelMapView`].
+    #[inline]
+    pub fn view(&self) -> VoxelMapView<T> {
+        self.view.clone()
+    }
+
+    /// The roots to be modified, the table of the roots is copied if any view still holds it,
+    /// which is the case after each [`VoxelMap::publish`] of a shared map.
+    #[inline]
+    fn roots_mut(&mut self) -> &mut Roots<T> {
+        Arc::make_mut(&mut self.view.roots)
+    }
+}
+
+impl<T> Deref for VoxelMap<T>
+where
+    T: ComplexField,
+{
+    type Target = VoxelMapView<T>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.view
+    }
+}
+
+impl<T> VoxelMapView<T>
+where
+    T: ComplexField,
+{
+    #[inline]
+    pub fn config(&self) -> &Config<T> {
+        &self.config
+    }
+
     pub fn planes(&self) -> impl Iterator<Item = &Plane<T>> {
         // TODO: parallel optimizable
         self.roots
@@ -77,6 +168,17 @@ where
             .flat_map(|root| root.iter_planes())
             .map(|plane| plane.deref())
     }
+
+    /// The number of the voxels (oct tree roots) in the map.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.roots.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
 }
 
 impl<T> VoxelMap<T>
@@ -84,13 +186,50 @@ where
     T: RealField,
 {
     pub fn insert(&mut self, point: UncertainWorldPoint<T>) {
-        let voxel_size = &self.config.voxel_size;
+        let VoxelMapView { roots, config } = &mut self.view;
+        let voxel_size = &config.voxel_size;
         let index = point.as_voxel_index(voxel_size.clone());
-        let root = self
-            .roots
+        let root = Arc::make_mut(roots)
             .entry(index)
-            .or_insert_with(|| OctTreeRoot::new(&point, voxel_size.clone()));
-        root.insert(&self.config.plane, point);
+            .or_insert_with(|| Arc::new(OctTreeRoot::new(&point, voxel_size.clone())));
+        Arc::make_mut(root).insert(&config.plane, point);
+    }
+
+    /// Slide the map window to the given `position`,
+    /// evicting the voxels outside the [`Config::map_size`] cube around it.
+    ///
+    /// Nothing happens unless the `position` has moved more than [`Config::sliding_thresh`]
+    /// since the last slide, and the pinned voxels are never evicted, see also [`VoxelMap::pin`].
+    ///
+    /// Returns the number of evicted voxels.
+    pub fn slide(&mut self, position: &WorldPoint<T>) -> usize {
+        let Some(last_position) = &self.last_slide_position else {
+            self.last_slide_position = Some(position.clone());
+            return 0;
+        };
+
+        let distance = (position.deref() - last_position.deref()).norm();
+        if distance <= self.config.sliding_thresh {
+            return 0;
+        }
+        self.last_slide_position = Some(position.clone());
+
+        let center = position.as_voxel_index(self.config.voxel_size.clone());
+        let half_size = (self.config.map_size / 2) as i64;
+
+        let len = self.roots.len();
+        let pinned = &self.pinned;
+        let is_inside = |index: &MapIndex<T>| {
+            pinned.contains(index)
+                || (index.deref() - center.deref())
+                    .iter()
+                    .all(|x| x.abs() <= half_size)
+        };
+        if self.roots.keys().all(is_inside) {
+            return 0;
+        }
+        Arc::make_mut(&mut self.view.roots).retain(|index, _| is_inside(index));
+        len - self.roots.len()
     }
 }
 impl<T> Extend<UncertainWorldPoint<T>> for VoxelMap<T>
@@ -101,7 +240,138 @@ where
     where
         I: IntoIterator<Item = UncertainWorldPoint<T>>,
     {
-        // TODO: could this be optimized by using `rayon`?
+        #[cfg(not(feature = "rayon"))]
         iter.into_iter().for_each(|point| self.insert(point));
+        #[cfg(feature = "rayon")]
+        self.par_insert(iter);
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T> VoxelMap<T>
+where
+    T: RealField,
+{
+    /// Insert the `points` grouped by their voxels, the voxels in parallel and the points of each voxel
+    /// in order, which is bit-identical to inserting the points one by one.
+    fn par_insert(&mut self, points: impl IntoIterator<Item = UncertainWorldPoint<T>>) {
+        use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+        let VoxelMapView { roots, config } = &mut self.view;
+        let voxel_size = &config.voxel_size;
+        let roots = Arc::make_mut(roots);
+        let mut groups = IntMap::<MapIndex<T>, Vec<UncertainWorldPoint<T>>>::default();
+        for point in points {
+            let index = point.as_voxel_index(voxel_size.clone());
+            // the new roots are created in the order of the points, the same as the serial insertion
+            roots
+                .entry(index.clone())
+                .or_insert_with(|| Arc::new(OctTreeRoot::new(&point, voxel_size.clone())));
+            groups.entry(index).or_default().push(point);
+        }
+
+        roots
+            .iter_mut()
+            .filter_map(|(index, root)| Some((root, groups.remove(index)?)))
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .for_each(|(root, points)| {
+                let root = Arc::make_mut(root);
+                points
+                    .into_iter()
+                    .for_each(|point| root.insert(&config.plane, point));
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point3;
+
+    use super::*;
+
+    fn point(x: f64) -> UncertainWorldPoint<f64> {
+        UncertainWorldPoint::new(WorldPoint::new(Point3::new(x, 0.1, 0.1)))
+    }
+
+    fn position(x: f64) -> WorldPoint<f64> {
+        WorldPoint::new(Point3::new(x, 0.0, 0.0))
+    }
+
+    fn new_map() -> VoxelMap<f64> {
+        VoxelMap::new(Config {
+            voxel_size: 1.0,
+            map_size: 10,
+            sliding_thresh: 1.0,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_slide() {
+        let mut map = new_map();
+        map.extend([0.5, 4.5, 9.5, 20.5].map(point));
+        assert_eq!(map.slide(&position(0.0)), 0);
+        // not moved enough
+        assert_eq!(map.slide(&position(0.5)), 0);
+
+        // the window covers the voxels from 5 to 15 along x
+        assert_eq!(map.slide(&position(10.0)), 3);
+        assert_eq!(map.len(), 1);
+        assert!(map.roots.contains_key(&point(9.5).as_voxel_index(1.0)));
+    }
+
+    #[test]
+    fn test_slide_pinned() {
+        let mut map = new_map();
+        map.extend([0.5, 4.5].map(point));
+        map.pin();
+        map.extend([30.5].map(point));
+        map.slide(&position(0.0));
+
+        assert_eq!(map.slide(&position(50.0)), 1);
+        assert_eq!(map.len(), 2);
+    }
+
+    /// The points on two planes across several voxels, jittered off the planes.
+    #[cfg(feature = "rayon")]
+    fn plane_points() -> Vec<UncertainWorldPoint<f64>> {
+        (0..4000)
+            .map(|i| {
+                let (u, v) = ((i % 50) as f64 * 0.08, (i / 50) as f64 * 0.05);
+                let jitter = (i as f64 * 12.9898).sin() * 0.01;
+                let point = if i % 2 == 0 {
+                    Point3::new(u, v, 0.2 * u + jitter)
+                } else {
+                    Point3::new(u, 3.9 + jitter, 0.5 * v)
+                };
+                UncertainWorldPoint::new(WorldPoint::new(point))
+            })
+            .collect()
+    }
+
+    /// The planes of each voxel in the order of the voxels, whose floats are formatted exactly.
+    #[cfg(feature = "rayon")]
+    fn voxel_planes(map: &VoxelMap<f64>) -> Vec<String> {
+        let mut roots = map.roots.iter().collect::<Vec<_>>();
+        roots.sort_by_key(|(index, _)| (index.x, index.y, index.z));
+        roots
+            .into_iter()
+            .map(|(index, root)| format!("{index:?} {:?}", root.iter_planes().collect::<Vec<_>>()))
+            .collect()
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_insert_serial() {
+        let mut serial = new_map();
+        plane_points()
+            .into_iter()
+            .for_each(|point| serial.insert(point));
+        let mut parallel = new_map();
+        parallel.par_insert(plane_points());
+
+        assert!(serial.planes().count() > 0);
+        assert_eq!(voxel_planes(&parallel), voxel_planes(&serial));
     }
 }
@@ -0,0 +1,230 @@
+//! The removal of the dynamic objects from the map by the free space seen through by the scans,
+//! see also [`VoxelMap::insert_scan`].
+
+use std::sync::Arc;
+
+use nalgebra::{Point3, RealField, Vector3};
+use nohash_hasher::{IntMap, IntSet};
+use simba::scalar::SupersetOf;
+
+use crate::frame::{Framed, WorldPoint};
+
+use super::{MapIndex, VoxelMap, index::ToVoxelIndex, uncertain::UncertainWorldPoint};
+
+/// The hit and miss policy of the occupancy of each voxel, see also [`Config::free_space`](super::Config::free_space).
+///
+/// A voxel is hit by a scan if any point of the scan falls in it, and missed if the rays
+/// from the lidar to the points of the scan see through it, the voxel is removed with its points
+/// and planes once its occupancy drops to zero.
+#[derive(Debug, Clone)]
+pub struct FreeSpaceConfig<T> {
+    /// The occupancy added to a voxel hit by a scan.
+    pub hit: u32,
+    /// The occupancy subtracted from a voxel missed by a scan.
+    pub miss: u32,
+    /// The maximum occupancy, larger for the voxels observed for long to be removed slower.
+    pub max_occupancy: u32,
+    /// The minimum number of the rays of a scan seeing through a voxel to miss it.
+    pub min_rays: usize,
+    /// The length at the end of each ray not seen through, in meters,
+    /// against the range noise and the rays grazing the surfaces.
+    pub end_margin: T,
+    /// The rays longer than this are ignored, in meters, since their angular noise sweeps wider.
+    pub max_range: T,
+}
+
+impl<T: SupersetOf<f64>> Default for FreeSpaceConfig<T> {
+    fn default() -> Self {
+        Self {
+            hit: 2,
+            miss: 1,
+            max_occupancy: 10,
+            min_rays: 3,
+            end_margin: nalgebra::convert(1.0),
+            max_range: nalgebra::convert(30.0),
+        }
+    }
+}
+
+impl<T> VoxelMap<T>
+where
+    T: RealField,
+{
+    /// Insert the points of a scan observed from the lidar `origin`, and update the occupancy of the voxels
+    /// hit and missed by the scan if the [`Config::free_space`](super::Config::free_space) is enabled.
+    ///
+    /// The pinned voxels are never missed, see also [`VoxelMap::pin`].
+    ///
+    /// Returns the number of the voxels removed as missed.
+    pub fn insert_scan(
+        &mut self,
+        origin: &WorldPoint<T>,
+        points: impl IntoIterator<Item = UncertainWorldPoint<T>>,
+    ) -> usize {
+        let Some(config) = self.config.free_space.clone() else {
+            self.extend(points);
+            return 0;
+        };
+        let voxel_size = self.config.voxel_size.clone();
+        let to_voxel_units =
+            |point: &Point3<T>| point.map(|x| (x / voxel_size.clone()).to_subset_unchecked());
+        let start = to_voxel_units(origin);
+
+        let points = points.into_iter().collect::<Vec<_>>();
+        let mut hits = IntSet::<MapIndex<T>>::default();
+        let mut ends = Vec::new();
+        for point in &points {
+            hits.insert(point.as_voxel_index(voxel_size.clone()));
+            let ray: Vector3<T> = &***point - &**origin;
+            let range = ray.norm();
+            if range > config.end_margin && range <= config.max_range {
+                let end = &**origin + ray * (T::one() - config.end_margin.clone() / range);
+                ends.push(to_voxel_units(&end));
+            }
+        }
+        self.extend(points);
+
+        let mut passes = IntMap::<MapIndex<T>, usize>::default();
+        for end in &ends {
+            traverse(&start, end, |cell| {
+                let index: MapIndex<T> = Framed::new(cell);
+                if self.roots.contains_key(&index)
+                    && !hits.contains(&index)
+                    && !self.pinned.contains(&index)
+                {
+                    *passes.entry(index).or_default() += 1;
+                }
+            });
+        }
+
+        for index in &hits {
+            if let Some(root) = self.roots_mut().get_mut(index) {
+                let root = Arc::make_mut(root);
+                root.occupancy = (root.occupancy + config.hit).min(config.max_occupancy);
+            }
+        }
+        let len = self.roots.len();
+        for (index, rays) in passes {
+            if rays < config.min_rays {
+                continue;
+            }
+            let roots = self.roots_mut();
+            let Some(root) = roots.get_mut(&index) else {
+                continue;
+            };
+            let occupancy = root.occupancy.saturating_sub(config.miss);
+            if occupancy == 0 {
+                roots.remove(&index);
+            } else {
+                Arc::make_mut(root).occupancy = occupancy;
+            }
+        }
+        len - self.roots.len()
+    }
+}
+
+/// Visit the voxels traversed by the segment from `start` to `end` in the voxel units, see also
+/// A Fast Voxel Traversal Algorithm for Ray Tracing, John Amanatides and Andrew Woo, Eurographics 1987
+fn traverse(start: &Point3<f64>, end: &Point3<f64>, mut visit: impl FnMut(Point3<i64>)) {
+    let mut cell = start.map(|x| x.floor() as i64);
+    let end_cell = end.map(|x| x.floor() as i64);
+    let direction = end - start;
+
+    let step = direction.map(|d| if d > 0.0 { 1 } else { -1 });
+    let mut t_max = Vector3::from_fn(|i, _| {
+        let boundary = if direction[i] > 0.0 {
+            cell[i] + 1
+        } else {
+            cell[i]
+        };
+        match direction[i] {
+            0.0 => f64::INFINITY,
+            d => (boundary as f64 - start[i]) / d,
+        }
+    });
+    let t_delta = direction.map(|d| match d {
+        0.0 => f64::INFINITY,
+        d => d.abs().recip(),
+    });
+
+    loop {
+        visit(cell);
+        if cell == end_cell {
+            break;
+        }
+        let axis = t_max.imin();
+        if t_max[axis] > 1.0 {
+            break;
+        }
+        cell[axis] += step[axis];
+        t_max[axis] += t_delta[axis];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::voxel_map::Config;
+
+    use super::*;
+
+    fn scan(x: f64, half_width: f64) -> impl Iterator<Item = UncertainWorldPoint<f64>> {
+        let n = (half_width / 0.05) as i32;
+        (-n..=n).flat_map(move |i| {
+            (-n..=n).map(move |j| {
+                let point = Point3::new(x, i as f64 * 0.05, j as f64 * 0.05);
+                UncertainWorldPoint::new(WorldPoint::new(point))
+            })
+        })
+    }
+
+    #[test]
+    fn test_traverse() {
+        let mut cells = Vec::new();
+        traverse(
+            &Point3::new(0.5, 0.5, 0.5),
+            &Point3::new(2.5, 1.5, 0.5),
+            |cell| cells.push(cell),
+        );
+        assert_eq!(cells.first(), Some(&Point3::new(0, 0, 0)));
+        assert_eq!(cells.last(), Some(&Point3::new(2, 1, 0)));
+        assert_eq!(cells.len(), 4);
+    }
+
+    #[test]
+    fn test_insert_scan_removes_seen_through_voxels() {
+        let mut map = VoxelMap::new(Config::<f64> {
+            free_space: Some(Default::default()),
+            ..Default::default()
+        });
+        let origin = WorldPoint::new(Point3::origin());
+
+        // an object in front of the wall
+        assert_eq!(
+            map.insert_scan(&origin, scan(10.0, 0.45).chain(scan(5.0, 0.2))),
+            0
+        );
+        let len = map.len();
+
+        // the object moves away, and the wall is seen through where it was
+        let removed = (0..3)
+            .map(|_| map.insert_scan(&origin, scan(10.0, 0.45)))
+            .sum::<usize>();
+        assert!(removed > 0);
+        assert_eq!(map.len(), len - removed);
+        let object = WorldPoint::new(Point3::new(5.0, 0.0, 0.0));
+        assert!(!map.roots.contains_key(&object.as_voxel_index(0.5)));
+        let wall = WorldPoint::new(Point3::new(10.0, 0.0, 0.0));
+        assert!(map.roots.contains_key(&wall.as_voxel_index(0.5)));
+
+        // the object in a pinned prior map is kept however many times it is seen through
+        map.insert_scan(&origin, scan(5.0, 0.2));
+        map.pin();
+        let len = map.len();
+        let removed = (0..10)
+            .map(|_| map.insert_scan(&origin, scan(10.0, 0.45)))
+            .sum::<usize>();
+        assert_eq!(removed, 0);
+        assert_eq!(map.len(), len);
+        assert!(map.roots.contains_key(&object.as_voxel_index(0.5)));
+    }
+}
@@ -1,6 +1,7 @@
 mod branch;
 mod iter;
 mod leaf;
+mod snapshot;
 mod storage;
 
 use crate::{
@@ -20,21 +21,27 @@ use nalgebra::{ComplexField, RealField, Scalar};
 
 type UncertainWorldPoints<T> = Vec<UncertainWorldPoint<T>>;
 
+#[derive(Clone)]
 pub struct OctTreeRoot<T: Scalar> {
     /// Allocator for the nodes in the tree.
     storage: TreeStorage<T>,
+    /// The occupancy by the hits and the misses of the scans, see also [`FreeSpaceConfig`](super::FreeSpaceConfig).
+    pub(crate) occupancy: u32,
 }
 
+#[derive(Clone)]
 pub struct OctTreeNode<T: Scalar> {
     tree: OctTree<T>,
     state: NodeState<T>,
 }
 
+#[derive(Clone)]
 pub(crate) enum OctTree<T: Scalar> {
     Branch(Branch<T>),
     Leaf(Leaf<T>),
 }
 
+#[derive(Clone)]
 struct NodeState<T: Scalar> {
     center: WorldPoint<T>,
     /// The quarter length of the side of the node.
@@ -59,6 +66,7 @@ impl<T: ComplexField> OctTreeRoot<T> {
         let root = OctTreeNode::new_leaf(Leaf::new(), center, quarter_side_length, 0);
         Self {
             storage: TreeStorage::new(root),
+            occupancy: 0,
         }
     }
 
@@ -16,6 +16,7 @@ use nalgebra::{ClosedSubAssign, RealField, Scalar, Vector3};
 
 type Childrens<T> = [[[Option<TreeID<T>>; 2]; 2]; 2];
 
+#[derive(Clone)]
 pub(crate) struct Branch<T: Scalar> {
     pub(crate) childrens: Childrens<T>,
 }
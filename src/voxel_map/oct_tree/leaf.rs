@@ -1,30 +1,41 @@
 use crate::voxel_map::uncertain::{
     UncertainPlane, UncertainWorldPoint,
-    plane::{PlaneConfig, PlaneInitError},
+    plane::{PlaneConfig, PlaneInitError, PlanePointsSum},
 };
 
 use nalgebra::{RealField, Scalar};
+use num_traits::Zero;
 
 use super::UncertainWorldPoints;
 
+#[derive(Clone)]
 pub struct Leaf<T: Scalar> {
     pub plane: Option<UncertainPlane<T>>,
-    /// Cached points for initializing a new plane or updating the existing plane.
-    /// If the cached points is `None`, the plane is not need to be updated.
+    /// Cached points for pruning the leaf into a branch if the points are not a plane.
+    /// If the cached points is `None`, the leaf is full and the points are only summed.
     pub(crate) cached_points: Option<UncertainWorldPoints<T>>,
+    /// The running sums of all the points inserted, which the plane is estimated from.
+    pub(crate) sum: PlanePointsSum<T>,
 }
 
-impl<T: Scalar> Leaf<T> {
-    pub const fn new() -> Self {
+impl<T: Scalar + Zero> Leaf<T> {
+    pub fn new() -> Self {
         Self {
             plane: None,
             cached_points: Some(Vec::new()),
+            sum: Default::default(),
         }
     }
+}
+
+impl<T: RealField> Leaf<T> {
     pub fn new_with_point(point: UncertainWorldPoint<T>) -> Self {
+        let mut sum = PlanePointsSum::default();
+        sum.push(&point);
         Self {
             plane: None,
             cached_points: Some(vec![point]),
+            sum,
         }
     }
 }
@@ -45,16 +56,15 @@ where
         let Leaf {
             plane,
             cached_points,
+            sum,
         } = self;
 
-        let Some(points) = cached_points else {
-            // cached points is disabled, the plane is not need to be updated.
-            return Ok(());
-        };
-
-        points.push(point);
+        sum.push(&point);
+        if let Some(points) = cached_points {
+            points.push(point);
+        }
 
-        let len = points.len();
+        let len = sum.count();
 
         let is_plane_needs_update = || len % config.update_threshold == 0;
 
@@ -62,25 +72,56 @@ where
         let _ = plane.take_if(|_| is_plane_needs_update());
 
         if plane.is_none() {
-            *plane = UncertainPlane::from_uncertain_world_points(points, config)
+            *plane = UncertainPlane::from_points_sum(sum, config)
                 .map(Some)
-                .or_else(|err| {
-                    if let PlaneInitError::EigenValueTooBig = err
-                        && depth < config.max_layer
+                .or_else(|err| match cached_points {
+                    Some(points)
+                        if matches!(err, PlaneInitError::EigenValueTooBig)
+                            && depth < config.max_layer =>
                     {
                         Err(std::mem::take(points))
-                    } else {
-                        Ok(None)
                     }
+                    _ => Ok(None),
                 })?;
         }
 
         let plane_is_full = len >= config.max_points;
 
         if plane_is_full {
-            // disable plane initialize and update
+            // the plane keeps being updated by the sums without the points
             *cached_points = None;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point3;
+
+    use crate::frame::WorldPoint;
+
+    use super::*;
+
+    #[test]
+    fn test_plane_updated_past_max_points() {
+        let config = PlaneConfig::<f64>::default();
+        let mut leaf = Leaf::new();
+        let mut centers = Vec::new();
+        for i in 0..3 * config.max_points {
+            let point = Point3::new((i % 10) as f64 * 0.03, (i / 10) as f64 * 0.01, 0.0);
+            let point = UncertainWorldPoint::new(WorldPoint::new(point));
+            assert!(leaf.insert(&config, 0, point).is_ok());
+            if let Some(plane) = &leaf.plane {
+                centers.push(plane.center.y);
+            }
+        }
+        assert!(leaf.cached_points.is_none());
+        assert_eq!(leaf.sum.count(), 3 * config.max_points);
+        // the plane follows the points growing along the y axis after the leaf is full
+        let Some(last) = centers.last() else {
+            panic!("no plane is fitted");
+        };
+        assert!(*last > 0.06);
+    }
+}
@@ -0,0 +1,254 @@
+use std::num::NonZero;
+
+use nalgebra::{Matrix3, Matrix6, Point3, RealField, Vector3};
+
+use super::{
+    NodeState, OctTree, OctTreeNode, OctTreeRoot,
+    branch::Branch,
+    leaf::Leaf,
+    storage::{TreeID, TreeStorage},
+};
+use crate::{
+    frame::{Framed, WorldPoint},
+    utils::VectorSquareSum,
+    voxel_map::{
+        snapshot::{NodeSnapshot, PlaneSnapshot, PlaneSumSnapshot, PointSnapshot, TreeSnapshot},
+        uncertain::{
+            UncertainPlane, UncertainWorldPoint,
+            plane::{Plane, PlanePointsSum},
+        },
+    },
+};
+
+impl<T: RealField> OctTreeRoot<T> {
+    pub(crate) fn snapshot_nodes(&self) -> Vec<(usize, NodeSnapshot)> {
+        self.storage
+            .iter_keyed_nodes()
+            .map(|(key, node)| (key, node.snapshot()))
+            .collect()
+    }
+
+    /// Returns `None` if the nodes do not form a valid tree.
+    ///
+    /// The sums of the leaves are rebuilt from the cached points if not `is_summed`,
+    /// see also [`MapSnapshot::is_summed`](crate::voxel_map::snapshot::MapSnapshot::is_summed).
+    pub(crate) fn from_snapshot_nodes(
+        nodes: Vec<(usize, NodeSnapshot)>,
+        is_summed: bool,
+    ) -> Option<Self> {
+        let nodes = nodes
+            .into_iter()
+            .map(|(key, node)| Some((key, OctTreeNode::from_snapshot(node, is_summed)?)))
+            .collect::<Option<Vec<_>>>()?;
+        let storage = TreeStorage::from_keyed_nodes(nodes)?;
+        Some(Self {
+            storage,
+            occupancy: 0,
+        })
+    }
+}
+
+impl<T: RealField> OctTreeNode<T> {
+    fn snapshot(&self) -> NodeSnapshot {
+        let NodeState {
+            center,
+            quarter_side_length,
+            depth,
+        } = &self.state;
+        let tree = match &self.tree {
+            OctTree::Branch(branch) => {
+                let mut childrens = [0; 8];
+                branch
+                    .childrens
+                    .iter()
+                    .flatten()
+                    .flatten()
+                    .zip(&mut childrens)
+                    .for_each(|(child, key)| {
+                        *key = child.as_ref().map_or(0, |id| id.index.get());
+                    });
+                TreeSnapshot::Branch(childrens)
+            }
+            OctTree::Leaf(leaf) => TreeSnapshot::Leaf {
+                plane: leaf.plane.as_ref().map(snapshot_plane),
+                cached_points: leaf
+                    .cached_points
+                    .as_ref()
+                    .map(|points| points.iter().map(snapshot_point).collect()),
+                sum: snapshot_sum(&leaf.sum),
+            },
+        };
+        NodeSnapshot {
+            center: to_f64s(&center.coords),
+            quarter_side_length: quarter_side_length.to_subset_unchecked(),
+            depth: *depth,
+            tree,
+        }
+    }
+
+    fn from_snapshot(node: NodeSnapshot, is_summed: bool) -> Option<Self> {
+        let NodeSnapshot {
+            center,
+            quarter_side_length,
+            depth,
+            tree,
+        } = node;
+        let tree = match tree {
+            TreeSnapshot::Branch(keys) => {
+                let mut branch = Branch::new();
+                branch
+                    .childrens
+                    .iter_mut()
+                    .flatten()
+                    .flatten()
+                    .zip(keys)
+                    .for_each(|(child, key)| {
+                        *child = NonZero::new(key).map(|key| {
+                            // SAFETY:
+                            //
+                            // The ids are validated by `TreeStorage::from_keyed_nodes` before indexing.
+                            unsafe { TreeID::from_raw(key) }
+                        })
+                    });
+                OctTree::Branch(branch)
+            }
+            TreeSnapshot::Leaf {
+                plane,
+                cached_points,
+                sum,
+            } => {
+                let cached_points: Option<Vec<_>> =
+                    cached_points.map(|points| points.into_iter().map(restore_point).collect());
+                let sum = match (is_summed, &cached_points) {
+                    (true, _) => restore_sum(sum)?,
+                    (false, Some(points)) => points.iter().sum(),
+                    // the full leaf is summed again from the points inserted after,
+                    // and the plane is kept until it is refitted from them
+                    (false, None) => PlanePointsSum::default(),
+                };
+                OctTree::Leaf(Leaf {
+                    plane: match plane {
+                        Some(plane) => Some(restore_plane(plane)?),
+                        None => None,
+                    },
+                    cached_points,
+                    sum,
+                })
+            }
+        };
+        Some(Self {
+            tree,
+            state: NodeState {
+                center: Framed::new(Point3::from(from_f64s(center))),
+                quarter_side_length: nalgebra::convert(quarter_side_length),
+                depth,
+            },
+        })
+    }
+}
+
+fn snapshot_plane<T: RealField>(plane: &UncertainPlane<T>) -> PlaneSnapshot {
+    let Plane {
+        normal,
+        center,
+        radius,
+    } = &plane.state;
+    PlaneSnapshot {
+        normal: to_f64s(normal),
+        center: to_f64s(&center.coords),
+        radius: radius.to_subset_unchecked(),
+        cov: plane.cov.iter().map(|x| x.to_subset_unchecked()).collect(),
+    }
+}
+
+/// Returns `None` if the covariance is not 6x6.
+fn restore_plane<T: RealField>(plane: PlaneSnapshot) -> Option<UncertainPlane<T>> {
+    let PlaneSnapshot {
+        normal,
+        center,
+        radius,
+        cov,
+    } = plane;
+    if cov.len() != 36 {
+        return None;
+    }
+    let state = Plane {
+        normal: from_f64s(normal),
+        center: Framed::new(Point3::from(from_f64s(center))),
+        radius: nalgebra::convert(radius),
+    };
+    let cov = Matrix6::from_iterator(cov.into_iter().map(nalgebra::convert));
+    Some(UncertainPlane::new_with_cov(state, cov))
+}
+
+fn snapshot_sum<T: RealField>(sum: &PlanePointsSum<T>) -> PlaneSumSnapshot {
+    let PlanePointsSum {
+        reference,
+        points,
+        cov,
+        weighted_cov,
+        square_weighted_cov,
+    } = sum;
+    let matrices = [&points.square_sum, cov]
+        .into_iter()
+        .chain(weighted_cov)
+        .chain(square_weighted_cov.iter().flatten())
+        .flat_map(|matrix| matrix.iter())
+        .map(|x| x.to_subset_unchecked())
+        .collect();
+    PlaneSumSnapshot {
+        reference: to_f64s(reference),
+        count: points.count,
+        sum: to_f64s(&points.sum),
+        matrices,
+    }
+}
+
+/// Returns `None` if the number of the matrices is not right.
+fn restore_sum<T: RealField>(sum: PlaneSumSnapshot) -> Option<PlanePointsSum<T>> {
+    let PlaneSumSnapshot {
+        reference,
+        count,
+        sum,
+        matrices,
+    } = sum;
+    if matrices.len() != 14 * 9 {
+        return None;
+    }
+    let mut matrices = matrices
+        .chunks_exact(9)
+        .map(|matrix| Matrix3::from_iterator(matrix.iter().copied().map(nalgebra::convert)));
+    let mut next = || matrices.next().unwrap_or_else(Matrix3::zeros);
+    Some(PlanePointsSum {
+        reference: from_f64s(reference),
+        points: VectorSquareSum {
+            count,
+            sum: from_f64s(sum),
+            square_sum: next(),
+        },
+        cov: next(),
+        weighted_cov: std::array::from_fn(|_| next()),
+        square_weighted_cov: std::array::from_fn(|_| std::array::from_fn(|_| next())),
+    })
+}
+
+fn snapshot_point<T: RealField>(point: &UncertainWorldPoint<T>) -> PointSnapshot {
+    PointSnapshot {
+        coords: to_f64s(&point.state.coords),
+        cov: std::array::from_fn(|i| point.cov[i].to_subset_unchecked()),
+    }
+}
+
+fn restore_point<T: RealField>(point: PointSnapshot) -> UncertainWorldPoint<T> {
+    let state: WorldPoint<T> = Framed::new(Point3::from(from_f64s(point.coords)));
+    let cov = Matrix3::from_iterator(point.cov.into_iter().map(nalgebra::convert));
+    UncertainWorldPoint::new_with_cov(state, cov)
+}
+
+fn to_f64s<T: RealField>(vector: &Vector3<T>) -> [f64; 3] {
+    std::array::from_fn(|i| vector[i].to_subset_unchecked())
+}
+
+fn from_f64s<T: RealField>(values: [f64; 3]) -> Vector3<T> {
+    Vector3::from(values.map(nalgebra::convert))
+}
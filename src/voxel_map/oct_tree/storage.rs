@@ -4,13 +4,14 @@ use std::{
     ops::{Index, IndexMut},
 };
 
-use super::OctTreeNode;
+use super::{OctTree, OctTreeNode};
 
 use nalgebra::Scalar;
 use slab::Slab;
 
-/// TODO: add another storage type that implements [`Send`] and [`Sync`],
-/// for thread-safe storage to integrate with the `rayon` crate.
+/// The nodes of an oct tree, which is [`Send`] and [`Sync`] as plain data without any shared references,
+/// and cloned as a whole on write when shared with a [`VoxelMapView`](crate::voxel_map::VoxelMapView).
+#[derive(Clone)]
 pub(crate) struct TreeStorage<T: Scalar>(Slab<OctTreeNode<T>>);
 
 #[derive(Debug, PartialEq)]
@@ -35,6 +36,13 @@ impl<T> TreeID<T> {
     fn new_maybe_root(index: usize) -> Option<Self> {
         NonZero::new(index).map(|index| Self::new(index))
     }
+
+    /// # Safety
+    ///
+    /// The `index` must be validated by [`TreeStorage::from_keyed_nodes`] before indexing.
+    pub(crate) const unsafe fn from_raw(index: NonZero<usize>) -> Self {
+        Self::new(index)
+    }
 }
 
 impl<T> RootTreeID<T> {
@@ -149,6 +157,44 @@ impl<T: Scalar> TreeStorage<T> {
     pub fn iter_nodes(&self) -> impl Iterator<Item = &OctTreeNode<T>> {
         self.0.iter().map(|(_, node)| node)
     }
+
+    /// Iterate the nodes with their keys, the root node is at key zero.
+    pub(crate) fn iter_keyed_nodes(&self) -> impl Iterator<Item = (usize, &OctTreeNode<T>)> {
+        self.0.iter()
+    }
+
+    /// Restore the storage from the nodes with their keys, see also [`TreeStorage::iter_keyed_nodes`].
+    ///
+    /// Returns `None` if the keys are not distinct and below the number of the nodes as the storage never frees,
+    /// the root node is missing, or any child is missing or not allocated after its parent,
+    /// which guarantees the slab is as large as the nodes, the [`TreeID`]s are valid and the tree is acyclic.
+    pub(crate) fn from_keyed_nodes(
+        nodes: impl IntoIterator<Item = (usize, OctTreeNode<T>)>,
+    ) -> Option<Self> {
+        let nodes = nodes.into_iter().collect::<Vec<_>>();
+        let mut is_used = vec![false; nodes.len()];
+        let is_dense = nodes.iter().all(|(key, _)| {
+            is_used
+                .get_mut(*key)
+                .is_some_and(|is_used| !std::mem::replace(is_used, true))
+        });
+        if !is_dense {
+            return None;
+        }
+        let slab = nodes.into_iter().collect::<Slab<_>>();
+        let is_valid = slab.contains(0)
+            && slab.iter().all(|(key, node)| match &node.tree {
+                OctTree::Branch(branch) => branch
+                    .childrens
+                    .iter()
+                    .flatten()
+                    .flatten()
+                    .flatten()
+                    .all(|id| id.index.get() > key && slab.contains(id.index.get())),
+                OctTree::Leaf(_) => true,
+            });
+        is_valid.then_some(Self(slab))
+    }
 }
 
 impl<T: Scalar> VacantAlloc<T> {
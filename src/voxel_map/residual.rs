@@ -1,12 +1,12 @@
 use std::cmp::Ordering;
-use std::ops::Deref;
+use std::ops::{AddAssign, Deref};
 
 use nalgebra::{ComplexField, RealField, Scalar, U1};
 
 use crate::{eskf::state::KFState, voxel_map::MapIndex};
 
 use super::{
-    VoxelMap,
+    VoxelMapView,
     index::ToVoxelIndex,
     oct_tree::OctTreeRoot,
     uncertain::{UncertainPlane, UncertainWorldPoint},
@@ -20,13 +20,34 @@ pub struct Residual<'a, T: Scalar> {
     sigma_sqrt: T,
 }
 
+/// The numbers of the points by where their residuals are found,
+/// see also [`VoxelMapView::get_or_nearest_residual_counted`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResidualCounts {
+    /// Found in the voxel of the point.
+    pub found: usize,
+    /// Found in the nearest voxel after none is found in the voxel of the point.
+    pub nearest: usize,
+    /// Not found in either voxel.
+    pub failed: usize,
+}
+
+impl AddAssign for ResidualCounts {
+    #[inline]
+    fn add_assign(&mut self, rhs: Self) {
+        self.found += rhs.found;
+        self.nearest += rhs.nearest;
+        self.failed += rhs.failed;
+    }
+}
+
 pub struct NoValidResidual<'a, T: ComplexField> {
     /// the root of the oct tree where the residual of the given point was not found
     voxel_root: &'a OctTreeRoot<T>,
     voxel_index: MapIndex<T>,
 }
 
-impl<T> VoxelMap<T>
+impl<T> VoxelMapView<T>
 where
     T: RealField,
 {
@@ -42,19 +63,34 @@ where
         &self,
         point: &UncertainWorldPoint<T>,
     ) -> Option<Residual<'_, T>> {
-        self.get_residual(point)
-            .or_else(|err| {
-                let NoValidResidual {
-                    voxel_root,
-                    voxel_index,
-                } = err.ok_or(())?;
+        self.get_or_nearest_residual_counted(point, &mut ResidualCounts::default())
+    }
 
+    /// Same as [`VoxelMapView::get_or_nearest_residual`], and count where the residual is found into `counts`.
+    pub fn get_or_nearest_residual_counted(
+        &self,
+        point: &UncertainWorldPoint<T>,
+        counts: &mut ResidualCounts,
+    ) -> Option<Residual<'_, T>> {
+        let residual = match self.get_residual(point) {
+            Ok(residual) => {
+                counts.found += 1;
+                return Some(residual);
+            }
+            Err(None) => None,
+            Err(Some(NoValidResidual {
+                voxel_root,
+                voxel_index,
+            })) => {
                 let nearest_coord = voxel_root.nearest_voxel(point, voxel_index);
-
-                self.get_residual_by_coord(nearest_coord, point)
-                    .map_err(drop)
-            })
-            .ok()
+                self.get_residual_by_coord(nearest_coord, point).ok()
+            }
+        };
+        match residual {
+            Some(_) => counts.nearest += 1,
+            None => counts.failed += 1,
+        }
+        residual
     }
 
     fn get_residual_by_coord(
@@ -65,7 +101,8 @@ where
         let voxel_root = self.roots.get(&voxel_index).ok_or(None)?;
         let radius_factor: T = nalgebra::convert(3.0);
 
-        // TODO: could be optimized by using `rayon`?
+        // serial even with the `rayon` feature, a voxel only has a few planes
+        // and the residuals of the points are already searched in parallel
         voxel_root
             .iter_planes()
             .map(|plane| {
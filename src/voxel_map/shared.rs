@@ -0,0 +1,110 @@
+//! The sharing of the [`VoxelMap`] with the readers on other threads,
+//! e.g. a planner reading the map while the odometry keeps updating it.
+
+use std::sync::{Arc, PoisonError, RwLock};
+
+use nalgebra::ComplexField;
+
+use super::{VoxelMap, VoxelMapView};
+
+/// A handle of the latest [`VoxelMapView`] published by the [`VoxelMap`], see also [`VoxelMap::share`].
+///
+/// The readers [`load`](SharedVoxelMap::load) the view and read it without blocking the updates of the map,
+/// which is consistent as the map at the time it was published.
+pub struct SharedVoxelMap<T: ComplexField>(Arc<RwLock<VoxelMapView<T>>>);
+
+impl<T: ComplexField> Clone for SharedVoxelMap<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: ComplexField> SharedVoxelMap<T> {
+    /// The latest view published, the lock is only held to clone the view.
+    pub fn load(&self) -> VoxelMapView<T> {
+        // the view is replaced as a whole, so it is still consistent if a writer panicked
+        self.0
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn store(&self, view: VoxelMapView<T>) {
+        let mut guard = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        let old = std::mem::replace(&mut *guard, view);
+        drop(guard);
+        // the voxels only held by the old view are freed after the lock is released
+        drop(old);
+    }
+}
+
+impl<T: ComplexField> VoxelMap<T> {
+    /// Share the map with the readers, which get the views published by [`VoxelMap::publish`].
+    ///
+    /// The voxels of the published view are copied on their next write, and the table of the roots
+    /// on the first write after each publish. So sharing the map costs copying the voxels updated
+    /// between two publishes, plus the pointers to all the roots once per publish,
+    /// which grows with the size of the map but not with the points in the voxels.
+    pub fn share(&mut self) -> SharedVoxelMap<T> {
+        self.shared
+            .get_or_insert_with(|| SharedVoxelMap(Arc::new(RwLock::new(self.view.clone()))))
+            .clone()
+    }
+
+    /// Publish the current map to the readers of [`VoxelMap::share`], nothing happens if the map is not shared.
+    pub fn publish(&self) {
+        if let Some(shared) = &self.shared {
+            shared.store(self.view());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point3;
+
+    use crate::{
+        frame::WorldPoint,
+        voxel_map::{Config, uncertain::UncertainWorldPoint},
+    };
+
+    use super::*;
+
+    fn wall(x: f64) -> impl Iterator<Item = UncertainWorldPoint<f64>> {
+        (0..20).flat_map(move |i| {
+            (0..20).map(move |j| {
+                let point = Point3::new(x, i as f64 * 0.05, j as f64 * 0.05);
+                UncertainWorldPoint::new(WorldPoint::new(point))
+            })
+        })
+    }
+
+    #[test]
+    fn test_read_while_inserting() {
+        let mut map = VoxelMap::new(Config::<f64>::default());
+        let shared = map.share();
+        map.extend(wall(0.1));
+        assert!(shared.load().is_empty());
+
+        map.publish();
+        let view = shared.load();
+        let (len, planes) = (view.len(), view.planes().count());
+        assert!(planes > 0);
+
+        std::thread::scope(|scope| {
+            let reader = scope.spawn(|| {
+                let point = UncertainWorldPoint::new(WorldPoint::new(Point3::new(0.1, 0.5, 0.5)));
+                view.get_or_nearest_residual(&point).is_some()
+            });
+            map.extend(wall(0.1).chain(wall(5.1)));
+            assert!(matches!(reader.join(), Ok(true)));
+        });
+
+        // the view is unchanged by the later insertions
+        assert_eq!(view.len(), len);
+        assert_eq!(view.planes().count(), planes);
+        assert!(map.len() > len);
+        map.publish();
+        assert_eq!(shared.load().len(), map.len());
+    }
+}
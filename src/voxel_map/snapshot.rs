@@ -0,0 +1,581 @@
+//! Persistence of the [`VoxelMap`], which can be saved after a mapping run and loaded back later.
+//!
+//! The map is converted into a [`MapSnapshot`] of plain data,
+//! which is encoded in a versioned little-endian binary format,
+//! or serialized by `serde` with the `serde` feature enabled.
+
+use std::{
+    fmt::{self, Display},
+    io::{self, Read, Write},
+    sync::Arc,
+};
+
+use nalgebra::{Point3, RealField};
+use nohash_hasher::IntMap;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::{Config, MapIndex, VoxelMap, VoxelMapView, oct_tree::OctTreeRoot};
+use crate::frame::Framed;
+
+const MAGIC: &[u8; 4] = b"VXMP";
+
+/// The version of the binary format and the [`MapSnapshot`].
+///
+/// The maps saved in the older versions since 1 are still loaded.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// The first version with the running sums of the leaves saved,
+/// which are rebuilt from the cached points of the leaves in the older versions.
+const SUMMED_VERSION: u32 = 2;
+
+/// The plain data of a [`VoxelMap`] with all the scalars stored as `f64`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MapSnapshot {
+    pub(crate) version: u32,
+    pub(crate) voxel_size: f64,
+    pub(crate) roots: Vec<RootSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct RootSnapshot {
+    pub(crate) index: [i64; 3],
+    pub(crate) nodes: Vec<(usize, NodeSnapshot)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct NodeSnapshot {
+    pub(crate) center: [f64; 3],
+    pub(crate) quarter_side_length: f64,
+    pub(crate) depth: u8,
+    pub(crate) tree: TreeSnapshot,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) enum TreeSnapshot {
+    /// The keys of the children ordered by `z`, `y`, `x`, zero means no child.
+    Branch([usize; 8]),
+    Leaf {
+        plane: Option<PlaneSnapshot>,
+        cached_points: Option<Vec<PointSnapshot>>,
+        /// Empty and ignored if the snapshot is not [`MapSnapshot::is_summed`].
+        #[cfg_attr(feature = "serde", serde(default))]
+        sum: PlaneSumSnapshot,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct PlaneSnapshot {
+    pub(crate) normal: [f64; 3],
+    pub(crate) center: [f64; 3],
+    pub(crate) radius: f64,
+    /// The column-major 6x6 covariance.
+    pub(crate) cov: Vec<f64>,
+}
+
+/// The running sums of the points of a leaf, see also [`PlanePointsSum`](super::uncertain::plane::PlanePointsSum).
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct PlaneSumSnapshot {
+    pub(crate) reference: [f64; 3],
+    pub(crate) count: usize,
+    pub(crate) sum: [f64; 3],
+    /// The column-major 3x3 matrices, 1 of the squared sum, 1 of the covariance sum,
+    /// 3 of the weighted covariance sums and 9 of the squared weighted covariance sums.
+    pub(crate) matrices: Vec<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct PointSnapshot {
+    pub(crate) coords: [f64; 3],
+    /// The column-major 3x3 covariance.
+    pub(crate) cov: [f64; 9],
+}
+
+#[derive(Debug)]
+pub enum MapLoadError {
+    Io(io::Error),
+    /// The data does not start with the magic bytes of the format.
+    InvalidMagic,
+    UnsupportedVersion(u32),
+    /// The voxel size of the map differs from the one in the [`Config`].
+    VoxelSizeMismatch {
+        saved: f64,
+        configured: f64,
+    },
+    /// The data is corrupted, e.g. an unknown tag or a dangling child of the oct tree.
+    InvalidData,
+}
+
+impl<T: RealField> VoxelMapView<T> {
+    pub fn snapshot(&self) -> MapSnapshot {
+        let roots = self
+            .roots
+            .iter()
+            .map(|(index, root)| RootSnapshot {
+                index: [index.x, index.y, index.z],
+                nodes: root.snapshot_nodes(),
+            })
+            .collect();
+        MapSnapshot {
+            version: FORMAT_VERSION,
+            voxel_size: self.config.voxel_size.to_subset_unchecked(),
+            roots,
+        }
+    }
+
+    /// Save the map in the versioned binary format, see also [`MapSnapshot::write_to`].
+    #[inline]
+    pub fn save(&self, writer: impl Write) -> io::Result<()> {
+        self.snapshot().write_to(writer)
+    }
+}
+
+impl<T: RealField> VoxelMap<T> {
+    /// Restore the map from the `snapshot`, the [`Config::voxel_size`] must be the same as the saved one.
+    pub fn from_snapshot(snapshot: MapSnapshot, config: Config<T>) -> Result<Self, MapLoadError> {
+        let MapSnapshot {
+            version,
+            voxel_size,
+            roots,
+        } = snapshot;
+        if !is_supported(version) {
+            return Err(MapLoadError::UnsupportedVersion(version));
+        }
+        let is_summed = version >= SUMMED_VERSION;
+        let configured: f64 = config.voxel_size.to_subset_unchecked();
+        if voxel_size != configured {
+            return Err(MapLoadError::VoxelSizeMismatch {
+                saved: voxel_size,
+                configured,
+            });
+        }
+
+        // the saved voxels are trusted as the static map
+        let occupancy = config
+            .free_space
+            .as_ref()
+            .map_or(0, |free_space| free_space.max_occupancy);
+        let roots = roots
+            .into_iter()
+            .map(|RootSnapshot { index, nodes }| {
+                let index: MapIndex<T> = Framed::new(Point3::from(index));
+                let mut root = OctTreeRoot::from_snapshot_nodes(nodes, is_summed)
+                    .ok_or(MapLoadError::InvalidData)?;
+                root.occupancy = occupancy;
+                Ok((index, Arc::new(root)))
+            })
+            .collect::<Result<IntMap<_, _>, MapLoadError>>()?;
+
+        Ok(Self::from_roots(roots, config))
+    }
+
+    /// Load the map saved by [`VoxelMapView::save`].
+    #[inline]
+    pub fn load(reader: impl Read, config: Config<T>) -> Result<Self, MapLoadError> {
+        Self::from_snapshot(MapSnapshot::read_from(reader)?, config)
+    }
+}
+
+impl MapSnapshot {
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        let writer = &mut writer;
+        writer.write_all(MAGIC)?;
+        writer.write_all(&self.version.to_le_bytes())?;
+        write_f64s(writer, &[self.voxel_size])?;
+        write_len(writer, self.roots.len())?;
+        for root in &self.roots {
+            for x in root.index {
+                writer.write_all(&x.to_le_bytes())?;
+            }
+            write_len(writer, root.nodes.len())?;
+            for (key, node) in &root.nodes {
+                write_len(writer, *key)?;
+                node.write_to(writer, self.is_summed())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_from(mut reader: impl Read) -> Result<Self, MapLoadError> {
+        let reader = &mut reader;
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(MapLoadError::InvalidMagic);
+        }
+        let version = u32::from_le_bytes(read_array(reader)?);
+        if !is_supported(version) {
+            return Err(MapLoadError::UnsupportedVersion(version));
+        }
+        let is_summed = version >= SUMMED_VERSION;
+        let [voxel_size] = read_f64s(reader)?;
+        let roots = (0..read_len(reader)?)
+            .map(|_| {
+                let mut index = [0; 3];
+                for x in &mut index {
+                    *x = i64::from_le_bytes(read_array(reader)?);
+                }
+                let nodes = (0..read_len(reader)?)
+                    .map(|_| {
+                        let key = read_len(reader)?;
+                        Ok((key, NodeSnapshot::read_from(reader, is_summed)?))
+                    })
+                    .collect::<Result<_, MapLoadError>>()?;
+                Ok(RootSnapshot { index, nodes })
+            })
+            .collect::<Result<_, MapLoadError>>()?;
+        Ok(Self {
+            version,
+            voxel_size,
+            roots,
+        })
+    }
+
+    #[inline]
+    pub const fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Whether the running sums of the leaves are saved, otherwise they are rebuilt when loaded.
+    #[inline]
+    pub const fn is_summed(&self) -> bool {
+        self.version >= SUMMED_VERSION
+    }
+}
+
+#[inline]
+fn is_supported(version: u32) -> bool {
+    (1..=FORMAT_VERSION).contains(&version)
+}
+
+impl NodeSnapshot {
+    fn write_to(&self, writer: &mut impl Write, is_summed: bool) -> io::Result<()> {
+        write_f64s(writer, &self.center)?;
+        write_f64s(writer, &[self.quarter_side_length])?;
+        writer.write_all(&[self.depth])?;
+        match &self.tree {
+            TreeSnapshot::Branch(childrens) => {
+                writer.write_all(&[0])?;
+                childrens
+                    .iter()
+                    .try_for_each(|child| write_len(writer, *child))
+            }
+            TreeSnapshot::Leaf {
+                plane,
+                cached_points,
+                sum,
+            } => {
+                writer.write_all(&[1])?;
+                writer.write_all(&[plane.is_some() as u8])?;
+                if let Some(plane) = plane {
+                    write_f64s(writer, &plane.normal)?;
+                    write_f64s(writer, &plane.center)?;
+                    write_f64s(writer, &[plane.radius])?;
+                    write_f64s(writer, &plane.cov)?;
+                }
+                writer.write_all(&[cached_points.is_some() as u8])?;
+                if let Some(points) = cached_points {
+                    write_len(writer, points.len())?;
+                    for point in points {
+                        write_f64s(writer, &point.coords)?;
+                        write_f64s(writer, &point.cov)?;
+                    }
+                }
+                if !is_summed {
+                    return Ok(());
+                }
+                write_f64s(writer, &sum.reference)?;
+                write_len(writer, sum.count)?;
+                write_f64s(writer, &sum.sum)?;
+                write_f64s(writer, &sum.matrices)
+            }
+        }
+    }
+
+    fn read_from(reader: &mut impl Read, is_summed: bool) -> Result<Self, MapLoadError> {
+        let center = read_f64s(reader)?;
+        let [quarter_side_length] = read_f64s(reader)?;
+        let [depth] = read_array(reader)?;
+        let tree = match read_array(reader)? {
+            [0] => {
+                let mut childrens = [0; 8];
+                for child in &mut childrens {
+                    *child = read_len(reader)?;
+                }
+                TreeSnapshot::Branch(childrens)
+            }
+            [1] => {
+                let plane = read_flag(reader)?
+                    .then(|| {
+                        Ok::<_, MapLoadError>(PlaneSnapshot {
+                            normal: read_f64s(reader)?,
+                            center: read_f64s(reader)?,
+                            radius: read_f64s::<1>(reader)?[0],
+                            cov: read_f64s::<36>(reader)?.to_vec(),
+                        })
+                    })
+                    .transpose()?;
+                let cached_points = read_flag(reader)?
+                    .then(|| {
+                        (0..read_len(reader)?)
+                            .map(|_| {
+                                Ok(PointSnapshot {
+                                    coords: read_f64s(reader)?,
+                                    cov: read_f64s(reader)?,
+                                })
+                            })
+                            .collect::<Result<Vec<_>, MapLoadError>>()
+                    })
+                    .transpose()?;
+                let sum = if is_summed {
+                    PlaneSumSnapshot {
+                        reference: read_f64s(reader)?,
+                        count: read_len(reader)?,
+                        sum: read_f64s(reader)?,
+                        matrices: read_f64s::<{ 14 * 9 }>(reader)?.to_vec(),
+                    }
+                } else {
+                    PlaneSumSnapshot::default()
+                };
+                TreeSnapshot::Leaf {
+                    plane,
+                    cached_points,
+                    sum,
+                }
+            }
+            _ => return Err(MapLoadError::InvalidData),
+        };
+        Ok(Self {
+            center,
+            quarter_side_length,
+            depth,
+            tree,
+        })
+    }
+}
+
+fn write_f64s(writer: &mut impl Write, values: &[f64]) -> io::Result<()> {
+    values
+        .iter()
+        .try_for_each(|value| writer.write_all(&value.to_le_bytes()))
+}
+
+fn write_len(writer: &mut impl Write, len: usize) -> io::Result<()> {
+    writer.write_all(&(len as u64).to_le_bytes())
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_f64s<const N: usize>(reader: &mut impl Read) -> io::Result<[f64; N]> {
+    let mut values = [0.0; N];
+    for value in &mut values {
+        *value = f64::from_le_bytes(read_array(reader)?);
+    }
+    Ok(values)
+}
+
+fn read_len(reader: &mut impl Read) -> Result<usize, MapLoadError> {
+    let len = u64::from_le_bytes(read_array(reader)?);
+    usize::try_from(len).map_err(|_| MapLoadError::InvalidData)
+}
+
+fn read_flag(reader: &mut impl Read) -> Result<bool, MapLoadError> {
+    match read_array(reader)? {
+        [0] => Ok(false),
+        [1] => Ok(true),
+        _ => Err(MapLoadError::InvalidData),
+    }
+}
+
+impl Display for MapLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "failed to read the map: {err}"),
+            Self::InvalidMagic => write!(f, "not a voxel map file"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported voxel map format version: {version}")
+            }
+            Self::VoxelSizeMismatch { saved, configured } => write!(
+                f,
+                "the voxel size of the map ({saved}) differs from the configured one ({configured})"
+            ),
+            Self::InvalidData => write!(f, "the voxel map data is corrupted"),
+        }
+    }
+}
+
+impl std::error::Error for MapLoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MapLoadError {
+    #[inline]
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{frame::WorldPoint, voxel_map::uncertain::UncertainWorldPoint};
+
+    #[test]
+    fn test_save_load_round_trip() -> Result<(), MapLoadError> {
+        let mut map = VoxelMap::new(Config::<f64>::default());
+        for i in 0..20 {
+            for j in 0..20 {
+                let point = Point3::new(i as f64 * 0.05, j as f64 * 0.05, 0.1);
+                map.insert(UncertainWorldPoint::new(WorldPoint::new(point)));
+            }
+        }
+        assert!(map.planes().count() > 0);
+
+        let mut bytes = Vec::new();
+        map.save(&mut bytes)?;
+        let loaded = VoxelMap::<f64>::load(bytes.as_slice(), Config::default())?;
+
+        assert_eq!(loaded.len(), map.len());
+        assert_eq!(loaded.planes().count(), map.planes().count());
+        assert_eq!(loaded.snapshot(), map.snapshot());
+
+        let config = Config {
+            voxel_size: 1.0,
+            ..Default::default()
+        };
+        assert!(matches!(
+            VoxelMap::<f64>::load(bytes.as_slice(), config),
+            Err(MapLoadError::VoxelSizeMismatch { .. })
+        ));
+        assert!(matches!(
+            VoxelMap::<f64>::load(&bytes[1..], Config::default()),
+            Err(MapLoadError::InvalidMagic)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_v1() -> Result<(), MapLoadError> {
+        // saved by the version 1 from a plane of full leaves and a small patch of cached points
+        let bytes = include_bytes!("../../tests/fixtures/voxel_map_v1.bin");
+        let snapshot = MapSnapshot::read_from(bytes.as_slice())?;
+        assert_eq!(snapshot.version(), 1);
+        let mut written = Vec::new();
+        snapshot.write_to(&mut written)?;
+        assert_eq!(written, bytes);
+        let loaded = VoxelMap::<f64>::from_snapshot(snapshot.clone(), Config::default())?;
+
+        // the same points inserted in the same order sum up the same as the rebuilt sums
+        let mut map = VoxelMap::new(Config::<f64>::default());
+        let plane = (0..20).flat_map(|i| (0..20).map(move |j| (i as f64 * 0.05, j as f64 * 0.05)));
+        let patch = (0..5)
+            .flat_map(|i| (0..5).map(move |j| (3.1 + i as f64 * 0.05, 0.1 + j as f64 * 0.05)));
+        for (x, y) in plane.chain(patch) {
+            map.insert(UncertainWorldPoint::new(WorldPoint::new(Point3::new(
+                x, y, 0.1,
+            ))));
+        }
+        let summed = map.snapshot();
+        assert_eq!(loaded.len(), map.len());
+
+        let (mut cached, mut full) = (0, 0);
+        let upgraded = loaded.snapshot();
+        for root in &snapshot.roots {
+            let find = |roots: &[RootSnapshot]| {
+                let Some(found) = roots.iter().find(|found| found.index == root.index) else {
+                    panic!("the root {:?} is missing", root.index);
+                };
+                found.nodes.clone()
+            };
+            let nodes = root
+                .nodes
+                .iter()
+                .zip(find(&upgraded.roots))
+                .zip(find(&summed.roots));
+            for (((key, node), (upgraded_key, upgraded)), (_, summed)) in nodes {
+                assert_eq!(*key, upgraded_key);
+                let (
+                    TreeSnapshot::Leaf {
+                        plane,
+                        cached_points,
+                        ..
+                    },
+                    TreeSnapshot::Leaf {
+                        plane: upgraded_plane,
+                        cached_points: upgraded_points,
+                        sum,
+                    },
+                    TreeSnapshot::Leaf { sum: summed, .. },
+                ) = (&node.tree, &upgraded.tree, &summed.tree)
+                else {
+                    assert_eq!(node, &upgraded);
+                    continue;
+                };
+                // the planes are kept, and the full leaves are summed again after loaded
+                assert_eq!(plane, upgraded_plane);
+                assert_eq!(cached_points, upgraded_points);
+                if cached_points.is_some() {
+                    assert_eq!(sum, summed);
+                    cached += 1;
+                } else {
+                    assert_eq!(sum.count, 0);
+                    full += 1;
+                }
+            }
+        }
+        assert!(cached > 0 && full > 0);
+
+        // the upgraded map is saved in the current version
+        let mut bytes = Vec::new();
+        loaded.save(&mut bytes)?;
+        let reloaded = VoxelMap::<f64>::load(bytes.as_slice(), Config::default())?;
+        assert_eq!(reloaded.snapshot().version(), FORMAT_VERSION);
+        assert_eq!(reloaded.snapshot(), upgraded);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_corrupted() -> Result<(), MapLoadError> {
+        let mut map = VoxelMap::new(Config::<f64>::default());
+        for i in 0..20 {
+            let point = Point3::new(i as f64 * 0.05, 0.1, 0.1);
+            map.insert(UncertainWorldPoint::new(WorldPoint::new(point)));
+        }
+        let mut bytes = Vec::new();
+        map.save(&mut bytes)?;
+        assert!(matches!(
+            VoxelMap::<f64>::load(&bytes[..bytes.len() / 2], Config::default()),
+            Err(MapLoadError::Io(_))
+        ));
+
+        // the keys far beyond the nodes or repeated are rejected before allocating the storage
+        let corrupt = |key: usize| -> Result<_, MapLoadError> {
+            let mut snapshot = map.snapshot();
+            let nodes = &mut snapshot.roots[0].nodes;
+            nodes.push((key, nodes[0].1.clone()));
+            let mut bytes = Vec::new();
+            snapshot.write_to(&mut bytes)?;
+            Ok(VoxelMap::<f64>::load(bytes.as_slice(), Config::default()))
+        };
+        assert!(matches!(corrupt(1 << 60)?, Err(MapLoadError::InvalidData)));
+        assert!(matches!(corrupt(0)?, Err(MapLoadError::InvalidData)));
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use std::ops::Deref;
+use std::{iter::Sum, ops::Deref};
 
 use crate::{
     eskf::state::KFState,
@@ -8,13 +8,12 @@ use crate::{
 };
 
 use nalgebra::{
-    Matrix1, Matrix3, Matrix6, Point3, RealField, RowVector3, Scalar, SymmetricEigen, U6, Vector3,
-    stack,
+    Matrix1, Matrix3, Point3, RealField, RowVector3, Scalar, SymmetricEigen, U6, Vector3, stack,
 };
 use num_traits::Zero;
 use simba::scalar::SupersetOf;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Plane<T: Scalar> {
     pub normal: Vector3<T>,
     pub center: WorldPoint<T>,
@@ -29,7 +28,8 @@ pub struct PlaneConfig<T> {
     pub update_threshold: usize,
     /// maximum eigen value of a plane to be considered as a valid plane
     pub plane_eigen_threshold: T,
-    /// maximum number of points for a tree
+    /// maximum number of the cached points for a tree, beyond which the tree is not pruned anymore,
+    /// and the plane keeps being updated by the running sums of the points only
     pub max_points: usize,
 }
 
@@ -71,6 +71,83 @@ pub enum PlaneInitError {
     EigenValueTooBig,
 }
 
+/// The running sums of the uncertain points of a plane, which estimate the plane with its covariance
+/// without keeping the points, see also [`UncertainPlane::from_points_sum`].
+///
+/// The points `q` are summed relative to the first point against the cancellation of the large coordinates,
+/// along with their covariances `C` weighted by the coordinates, which are all the covariance of the plane
+/// needs from the points.
+#[derive(Debug, Clone)]
+pub struct PlanePointsSum<T: Scalar> {
+    pub(crate) reference: Vector3<T>,
+    pub(crate) points: VectorSquareSum<T>,
+    /// The sum `Σ C`.
+    pub(crate) cov: Matrix3<T>,
+    /// The sums `Σ q_a C` for each coordinate `a`.
+    pub(crate) weighted_cov: [Matrix3<T>; 3],
+    /// The sums `Σ q_a q_b C` for each pair of the coordinates `a` and `b`.
+    pub(crate) square_weighted_cov: [[Matrix3<T>; 3]; 3],
+}
+
+impl<T> Default for PlanePointsSum<T>
+where
+    T: Scalar + Zero,
+{
+    fn default() -> Self {
+        Self {
+            reference: Vector3::zeros(),
+            points: Default::default(),
+            cov: Matrix3::zeros(),
+            weighted_cov: std::array::from_fn(|_| Matrix3::zeros()),
+            square_weighted_cov: std::array::from_fn(|_| std::array::from_fn(|_| Matrix3::zeros())),
+        }
+    }
+}
+
+impl<T> PlanePointsSum<T>
+where
+    T: RealField,
+{
+    #[inline(always)]
+    pub fn count(&self) -> usize {
+        self.points.count()
+    }
+
+    pub fn push(&mut self, point: &UncertainWorldPoint<T>) {
+        if self.count() == 0 {
+            self.reference = point.coords.clone();
+        }
+        let relative = &point.coords - &self.reference;
+        self.points.push(&relative);
+
+        let cov = point.cov.deref();
+        self.cov += cov;
+        for (a, weighted_cov) in self.weighted_cov.iter_mut().enumerate() {
+            *weighted_cov += cov * relative[a].clone();
+        }
+        for (a, row) in self.square_weighted_cov.iter_mut().enumerate() {
+            for (b, square_weighted_cov) in row.iter_mut().enumerate() {
+                *square_weighted_cov += cov * (relative[a].clone() * relative[b].clone());
+            }
+        }
+    }
+}
+
+impl<'a, T> Sum<&'a UncertainWorldPoint<T>> for PlanePointsSum<T>
+where
+    T: RealField,
+{
+    fn sum<I>(iter: I) -> Self
+    where
+        I: Iterator<Item = &'a UncertainWorldPoint<T>>,
+    {
+        iter.fold(Self::default(), |mut acc, point| {
+            acc.push(point);
+            acc
+        })
+    }
+}
+
 impl<T> UncertainPlane<T>
 where
     T: RealField,
@@ -82,15 +159,22 @@ where
         if points.len() < config.plane_init_threshold {
             return Err(PlaneInitError::TooFewPoints);
         }
+        Self::from_points_sum(&points.iter().sum(), config)
+    }
 
-        let sum = points
-            .iter()
-            .map(|uncertain_point| &uncertain_point.coords)
-            .sum::<VectorSquareSum<T>>();
-
+    /// Estimate the plane from the running `sum` of its points, which is the same as
+    /// [`UncertainPlane::from_uncertain_world_points`] with all the summed points.
+    pub fn from_points_sum(
+        sum: &PlanePointsSum<T>,
+        config: &PlaneConfig<T>,
+    ) -> Result<Self, PlaneInitError> {
         let points_count = sum.count();
-        let (center, covariance) = sum.mean();
-        let center = WorldPoint::new(Point3::from(center));
+        if points_count < config.plane_init_threshold {
+            return Err(PlaneInitError::TooFewPoints);
+        }
+
+        let (mean, covariance) = sum.points.mean();
+        let center = WorldPoint::new(Point3::from(&sum.reference + &mean));
 
         let SymmetricEigen {
             eigenvectors,
@@ -104,42 +188,64 @@ where
         }
 
         let min_eigenvector = eigenvectors.column(min_eigen_index);
+        let points_count: T = nalgebra::convert(points_count as f64);
 
-        let covariance = points
+        // the error of the normal is `Σ_k v_k (p - center)' A_k / (n (λ_min - λ_k))` for each point,
+        // and the error of the center is `I / n`, see also `sigma_to`
+        let eigens = eigenvalues
             .iter()
-            .map(|point| {
-                let points_count: T = nalgebra::convert(points_count as f64);
+            .zip(eigenvectors.column_iter())
+            .filter_map(|(eigenvalue, eigenvector)| {
+                let eigen_diff = min_eigen_value.clone() - eigenvalue.clone();
+                if eigen_diff.is_zero() {
+                    return None;
+                }
+                let symmetric = &eigenvector * min_eigenvector.transpose()
+                    + &min_eigenvector * eigenvector.transpose();
+                let scale = (points_count.clone() * eigen_diff).recip();
+                Some((eigenvector.into_owned(), symmetric, scale))
+            })
+            .collect::<Vec<_>>();
 
-                let rows = eigenvalues
-                    .iter()
-                    .zip(eigenvectors.column_iter())
-                    .map(|(eigenvalue, eigenvector)| {
-                        let eigen_diff = min_eigen_value.clone() - eigenvalue.clone();
-                        if eigen_diff.is_zero() {
-                            return RowVector3::zeros();
-                        }
-
-                        (point.deref() - &center).transpose() / (points_count.clone() * eigen_diff)
-                            * (&eigenvector * min_eigenvector.transpose()
-                                + &min_eigenvector * eigenvector.transpose())
-                    })
-                    .flat_map(|row| row.data.0.into_iter())
-                    .map(|[x]| x);
+        // the sums of the point covariances weighted by the coordinates relative to the center
+        let centered_cov = |a: usize| &sum.weighted_cov[a] - &sum.cov * mean[a].clone();
+        let square_centered_cov = |a: usize, b: usize| {
+            &sum.square_weighted_cov[a][b]
+                - &sum.weighted_cov[b] * mean[a].clone()
+                - &sum.weighted_cov[a] * mean[b].clone()
+                + &sum.cov * (mean[a].clone() * mean[b].clone())
+        };
+        let centered_covs: [Matrix3<T>; 3] = std::array::from_fn(centered_cov);
+        let square_centered_covs: [[Matrix3<T>; 3]; 3] =
+            std::array::from_fn(|a| std::array::from_fn(|b| square_centered_cov(a, b)));
 
-                let normal_error = &eigenvectors * Matrix3::from_row_iterator(rows);
-                let position_error = Matrix3::from_diagonal_element(points_count.recip());
+        let mut normal_cov = Matrix3::zeros();
+        let mut normal_center_cov = Matrix3::zeros();
+        for (eigenvector_k, symmetric_k, scale_k) in &eigens {
+            let row = (0..3)
+                .map(|a| symmetric_k.row(a) * &centered_covs[a])
+                .sum::<RowVector3<T>>();
+            normal_center_cov += eigenvector_k * row * (scale_k.clone() / points_count.clone());
 
-                #[expect(clippy::toplevel_ref_arg)]
-                let error_matrix = stack![normal_error; position_error];
+            for (eigenvector_l, symmetric_l, scale_l) in &eigens {
+                let quadform = (0..3)
+                    .flat_map(|a| (0..3).map(move |b| (a, b)))
+                    .map(|(a, b)| {
+                        (symmetric_k * &square_centered_covs[a][b] * symmetric_l)[(a, b)].clone()
+                    })
+                    .fold(T::zero(), |acc, x| acc + x);
+                normal_cov += eigenvector_k
+                    * eigenvector_l.transpose()
+                    * (quadform * scale_k.clone() * scale_l.clone());
+            }
+        }
+        let center_cov = &sum.cov / points_count.clone().powi(2);
 
-                // TODO: can we avoid init with zeros?
-                let mut cov = Matrix6::zeros();
-                cov.quadform_tr(T::one(), &error_matrix, &point.cov, T::zero());
-                cov
-            })
-            .sum::<Matrix6<T>>();
+        #[expect(clippy::toplevel_ref_arg)]
+        let covariance =
+            stack![normal_cov, normal_center_cov; normal_center_cov.transpose(), center_cov];
 
-        let normal = eigenvectors.column(min_eigen_index).into();
+        let normal = min_eigenvector.into();
 
         Ok(Self::new_with_cov(
             Plane {
@@ -164,3 +270,84 @@ where
         sigma
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Matrix6;
+
+    use super::*;
+
+    /// The covariance of the plane by the per-point error, which the running sums should reproduce.
+    fn direct_covariance(
+        points: &[UncertainWorldPoint<f64>],
+        plane: &Plane<f64>,
+        covariance: &Matrix3<f64>,
+    ) -> Matrix6<f64> {
+        let SymmetricEigen {
+            eigenvectors,
+            eigenvalues,
+        } = covariance.symmetric_eigen();
+        let (min_eigen_index, min_eigen_value) = eigenvalues.argmin();
+        let min_eigenvector = eigenvectors.column(min_eigen_index);
+        let count = points.len() as f64;
+
+        points
+            .iter()
+            .map(|point| {
+                let normal_error = eigenvalues
+                    .iter()
+                    .zip(eigenvectors.column_iter())
+                    .filter(|(eigenvalue, _)| min_eigen_value != **eigenvalue)
+                    .map(|(eigenvalue, eigenvector)| {
+                        let row = (point.deref() - &plane.center).transpose()
+                            / (count * (min_eigen_value - eigenvalue))
+                            * (eigenvector * min_eigenvector.transpose()
+                                + min_eigenvector * eigenvector.transpose());
+                        eigenvector * row
+                    })
+                    .sum::<Matrix3<f64>>();
+                let position_error = Matrix3::from_diagonal_element(count.recip());
+
+                #[expect(clippy::toplevel_ref_arg)]
+                let error_matrix = stack![normal_error; position_error];
+                error_matrix * point.cov.deref() * error_matrix.transpose()
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_plane_from_points_sum() {
+        let points = (0..60)
+            .map(|i| {
+                let (u, v) = ((i % 8) as f64 * 0.06, (i / 8) as f64 * 0.05);
+                let offset = 1e-3 * ((i * 7 % 5) as f64 - 2.0);
+                let point = Point3::new(100.0 + u, -50.0 + v, 3.0 + 0.2 * u - 0.1 * v + offset);
+                let cov = Matrix3::new(
+                    1e-4,
+                    2e-6,
+                    0.0, //
+                    2e-6,
+                    2e-4,
+                    1e-6, //
+                    0.0,
+                    1e-6,
+                    (1.0 + i as f64 * 0.1) * 1e-4,
+                );
+                UncertainWorldPoint::new_with_cov(WorldPoint::new(point), cov)
+            })
+            .collect::<Vec<_>>();
+
+        let config = PlaneConfig::default();
+        let Ok(plane) = UncertainPlane::from_uncertain_world_points(&points, &config) else {
+            panic!("failed to fit the plane");
+        };
+
+        let sum = points
+            .iter()
+            .map(|point| &point.coords)
+            .sum::<VectorSquareSum<f64>>();
+        let expected = direct_covariance(&points, &plane, &sum.mean().1);
+        assert!((plane.cov.deref() - expected).amax() < 1e-12 * expected.amax().max(1.0));
+        assert!((plane.center.coords - sum.mean().0).amax() < 1e-9);
+    }
+}
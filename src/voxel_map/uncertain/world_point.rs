@@ -38,11 +38,31 @@ where
         PositionState<T>: SubStateOf<S, Dim = U3>,
         DefaultAllocator: Allocator<S::Dim, S::Dim>,
     {
-        let world_point = body_point.deref() * body_to_world;
         let rot_cov = eskf_cov.sub_covariance::<RotationState<T>>();
         let pos_cov = eskf_cov.sub_covariance::<PositionState<T>>();
+        Self::from_uncertain_body_point_with_cov(
+            body_point,
+            imu_to_world,
+            body_to_world,
+            cross_matrix_imu,
+            &rot_cov.into_owned(),
+            &pos_cov.into_owned(),
+        )
+    }
+
+    /// Same as [`UncertainWorldPoint::from_uncertain_body_point`] with the covariances of the
+    /// [`RotationState`] and the [`PositionState`] taken out of the ESKF covariance.
+    pub fn from_uncertain_body_point_with_cov(
+        body_point: UncertainBodyPoint<T>,
+        imu_to_world: &IsometryFramed<T, fn(frames::Imu) -> frames::World>,
+        body_to_world: &IsometryFramed<T, fn(frames::Body) -> frames::World>,
+        cross_matrix_imu: Framed<&Matrix3<T>, frames::Imu>,
+        rot_cov: &Matrix3<T>,
+        pos_cov: &Matrix3<T>,
+    ) -> Self {
+        let world_point = body_point.deref() * body_to_world;
 
-        let mut cov = pos_cov.into_owned();
+        let mut cov = pos_cov.clone();
         cov.quadform_tr(
             T::one(),
             body_to_world.rotation.matrix(),
@@ -52,7 +72,7 @@ where
         cov.quadform_tr(
             T::one(),
             &(&imu_to_world.rotation * *cross_matrix_imu),
-            &rot_cov,
+            rot_cov,
             T::one(),
         );
 